@@ -0,0 +1,105 @@
+//! `#[derive(FromParameters)]`, see `blockstream::blockchain::parameters`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, LitStr};
+
+/// Implements `FromParameters` and `FromParameter` for a struct with named
+/// fields. Fields are read in declaration order, or by name; the name of a
+/// field can be overridden with `#[parameter(name = "...")]`.
+#[proc_macro_derive(FromParameters, attributes(parameter))]
+pub fn derive_from_parameters(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &input.ident,
+                    "FromParameters requires a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                &input.ident,
+                "FromParameters can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut idents = Vec::new();
+    let mut names = Vec::new();
+    for field in fields {
+        let ident = field.ident.clone().expect("named field");
+        names.push(parameter_name(field)?.unwrap_or_else(|| ident.to_string()));
+        idents.push(ident);
+    }
+    let indexes = 0..idents.len();
+
+    let ident = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    let parameters = quote!(::blockstream::blockchain::parameters);
+    Ok(quote! {
+        impl #impl_generics #parameters::FromParameters for #ident #type_generics #where_clause {
+            fn from_parameters(
+                parameters: &[::blockstream::blockchain::decoder::Parameter],
+            ) -> ::std::result::Result<Self, #parameters::ParameterError> {
+                ::std::result::Result::Ok(Self {
+                    #(#idents: #parameters::positional(parameters, #indexes, #names)?,)*
+                })
+            }
+
+            fn from_named_parameters(
+                names: &[&str],
+                parameters: &[::blockstream::blockchain::decoder::Parameter],
+            ) -> ::std::result::Result<Self, #parameters::ParameterError> {
+                ::std::result::Result::Ok(Self {
+                    #(#idents: #parameters::named(names, parameters, #names)?,)*
+                })
+            }
+        }
+
+        impl #impl_generics #parameters::FromParameter for #ident #type_generics #where_clause {
+            fn from_parameter(
+                parameter: &::blockstream::blockchain::decoder::Parameter,
+            ) -> ::std::result::Result<Self, #parameters::ParameterError> {
+                match parameter {
+                    ::blockstream::blockchain::decoder::Parameter::Struct(fields) => {
+                        <Self as #parameters::FromParameters>::from_parameters(fields)
+                    }
+                    other => ::std::result::Result::Err(
+                        #parameters::ParameterError::mismatch("struct", other),
+                    ),
+                }
+            }
+        }
+    })
+}
+
+/// The value of `#[parameter(name = "...")]`, if present.
+fn parameter_name(field: &syn::Field) -> Result<Option<String>, Error> {
+    let mut name = None;
+    for attr in field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("parameter"))
+    {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("unsupported parameter attribute, expected `name`"))
+            }
+        })?;
+    }
+    Ok(name)
+}
@@ -0,0 +1,256 @@
+use super::http::RateLimited;
+use alloy::rpc::json_rpc::{Id, RequestPacket, Response, ResponsePacket, SerializedRequest};
+use alloy::transports::{RpcError, TransportError, TransportErrorKind, TransportFut};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::future::poll_fn;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot};
+use tower::{Layer, Service};
+
+/// How requests are coalesced into JSON-RPC batches: requests issued within
+/// `window_ms` of the first one are sent together, at most `max_size` at a
+/// time.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct BatchConfig {
+    #[serde(default = "default_max_size")]
+    pub max_size: usize,
+    #[serde(default = "default_window_ms")]
+    pub window_ms: u64,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_size: default_max_size(),
+            window_ms: default_window_ms(),
+        }
+    }
+}
+
+impl BatchConfig {
+    pub fn window(&self) -> Duration {
+        Duration::from_millis(self.window_ms)
+    }
+}
+
+fn default_max_size() -> usize {
+    50
+}
+
+fn default_window_ms() -> u64 {
+    5
+}
+
+type Reply = oneshot::Sender<Result<ResponsePacket, TransportError>>;
+
+/// Coalesces single requests issued concurrently into batch requests.
+/// Requests that are already batched are passed through. Must be applied
+/// within a Tokio runtime.
+#[derive(Debug, Clone)]
+pub struct BatchLayer {
+    config: BatchConfig,
+}
+
+impl BatchLayer {
+    pub fn new(config: BatchConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl<S> Layer<S> for BatchLayer
+where
+    S: Service<RequestPacket, Response = ResponsePacket, Error = TransportError>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    type Service = BatchService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(collect_batches(
+            inner.clone(),
+            receiver,
+            self.config.clone(),
+        ));
+        BatchService { inner, sender }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BatchService<S> {
+    inner: S,
+    sender: mpsc::UnboundedSender<(SerializedRequest, Reply)>,
+}
+
+impl<S> Service<RequestPacket> for BatchService<S>
+where
+    S: Service<RequestPacket, Response = ResponsePacket, Error = TransportError> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let request = match request {
+            RequestPacket::Single(request) => request,
+            batch => return Box::pin(self.inner.call(batch)),
+        };
+        let (reply, response) = oneshot::channel();
+        let sent = self.sender.send((request, reply)).is_ok();
+        Box::pin(async move {
+            if !sent {
+                return Err(TransportErrorKind::custom_str(
+                    "Request batching has stopped",
+                ));
+            }
+            response.await.unwrap_or_else(|_| {
+                Err(TransportErrorKind::custom_str(
+                    "Batch was dropped before it was answered",
+                ))
+            })
+        })
+    }
+}
+
+/// Gathers the requests received within the window of the first one, up to
+/// the maximum batch size, and sends them without waiting for the previous
+/// batch to be answered.
+async fn collect_batches<S>(
+    mut inner: S,
+    mut requests: mpsc::UnboundedReceiver<(SerializedRequest, Reply)>,
+    config: BatchConfig,
+) where
+    S: Service<RequestPacket, Response = ResponsePacket, Error = TransportError>,
+    S::Future: Send + 'static,
+{
+    let max_size = config.max_size.max(1);
+    while let Some(first) = requests.recv().await {
+        let mut batch = vec![first];
+        let window = tokio::time::sleep(config.window());
+        tokio::pin!(window);
+        while batch.len() < max_size {
+            tokio::select! {
+                request = requests.recv() => match request {
+                    Some(request) => batch.push(request),
+                    None => break,
+                },
+                _ = &mut window => break,
+            }
+        }
+
+        if let Err(err) = poll_fn(|cx| inner.poll_ready(cx)).await {
+            reply_with_error(batch.into_iter().map(|(_, reply)| reply), err);
+            continue;
+        }
+        let (batch, replies): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+        if batch.len() == 1 {
+            let response = inner.call(RequestPacket::Single(batch.into_iter().next().unwrap()));
+            tokio::spawn(async move {
+                let _ = replies.into_iter().next().unwrap().send(response.await);
+            });
+        } else {
+            let ids: Vec<Id> = batch.iter().map(|request| request.id().clone()).collect();
+            let response = inner.call(RequestPacket::Batch(batch));
+            tokio::spawn(async move { split_batch(response.await, ids, replies) });
+        }
+    }
+}
+
+/// Hands each request of a batch its own response, matched by ID.
+fn split_batch(
+    response: Result<ResponsePacket, TransportError>,
+    ids: Vec<Id>,
+    replies: Vec<Reply>,
+) {
+    match response {
+        Ok(ResponsePacket::Batch(responses)) => {
+            let mut responses: HashMap<Id, Response> = responses
+                .into_iter()
+                .map(|response| (response.id.clone(), response))
+                .collect();
+            for (id, reply) in ids.into_iter().zip(replies) {
+                let _ = reply.send(match responses.remove(&id) {
+                    Some(response) => Ok(ResponsePacket::Single(response)),
+                    None => Err(TransportErrorKind::custom_str(&format!(
+                        "No response to request {} in batch",
+                        id
+                    ))),
+                });
+            }
+        }
+        // Typically an error rejecting the batch as a whole.
+        Ok(ResponsePacket::Single(response)) => {
+            for (id, reply) in ids.into_iter().zip(replies) {
+                let mut response = response.clone();
+                response.id = id;
+                let _ = reply.send(Ok(ResponsePacket::Single(response)));
+            }
+        }
+        Err(err) => reply_with_error(replies, err),
+    }
+}
+
+fn reply_with_error(replies: impl IntoIterator<Item = Reply>, err: TransportError) {
+    let err = Arc::new(err);
+    for reply in replies {
+        let _ = reply.send(Err(share_error(&err)));
+    }
+}
+
+/// A copy of `err` of the same kind, so that each request of a failed batch
+/// is retried or not as if it had failed on its own. Errors that cannot be
+/// copied are shared behind a [`SharedError`].
+fn share_error(err: &Arc<TransportError>) -> TransportError {
+    match &**err {
+        RpcError::ErrorResp(payload) => RpcError::ErrorResp(payload.clone()),
+        RpcError::NullResp => RpcError::NullResp,
+        RpcError::UnsupportedFeature(feature) => RpcError::UnsupportedFeature(feature),
+        RpcError::LocalUsageError(_) => RpcError::local_usage(SharedError(err.clone())),
+        RpcError::SerError(source) => RpcError::SerError(serde::ser::Error::custom(source)),
+        RpcError::DeserError { err: source, text } => RpcError::DeserError {
+            err: serde::de::Error::custom(source),
+            text: text.clone(),
+        },
+        RpcError::Transport(TransportErrorKind::MissingBatchResponse(id)) => {
+            RpcError::Transport(TransportErrorKind::MissingBatchResponse(id.clone()))
+        }
+        RpcError::Transport(TransportErrorKind::BackendGone) => TransportErrorKind::backend_gone(),
+        RpcError::Transport(TransportErrorKind::PubsubUnavailable) => {
+            TransportErrorKind::pubsub_unavailable()
+        }
+        RpcError::Transport(TransportErrorKind::HttpError(http)) => {
+            TransportErrorKind::http_error(http.status, http.body.clone())
+        }
+        RpcError::Transport(_) => match RateLimited::from_error(err) {
+            Some(rate_limited) => TransportErrorKind::custom(rate_limited.clone()),
+            None => TransportErrorKind::custom(SharedError(err.clone())),
+        },
+    }
+}
+
+/// An error answered to every request of a batch.
+#[derive(Debug)]
+struct SharedError(Arc<TransportError>);
+
+impl fmt::Display for SharedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for SharedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
@@ -1,17 +1,25 @@
+use std::collections::VecDeque;
 use std::future::Future;
-use std::sync::Arc;
+use std::pin::{pin, Pin};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use alloy::eips::BlockNumberOrTag;
-use alloy::primitives::B256;
-use alloy::providers::FilterPollerBuilder;
-use alloy::pubsub::Subscription;
+use alloy::primitives::{B256, U256};
 use alloy::{
     providers::{Provider, RootProvider},
-    rpc::types::{Block, BlockTransactionsKind, Header},
-    transports::{BoxTransport, TransportError},
+    rpc::json_rpc::ErrorPayload,
+    rpc::types::{Block, BlockTransactionsKind},
+    transports::{BoxTransport, RpcError, TransportError},
 };
-use futures_util::{stream, StreamExt};
-use log::info;
+use futures_util::{stream, Stream, StreamExt};
+use serde::Deserialize;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+use crate::blockchain::retry::{OnExhaustion, RetryPolicy, Transient};
+use crate::metrics::{metrics, BLOCKS_PROCESSED, BLOCKS_SKIPPED, HEAD_LAG, RECONNECTS, REORGS};
 
 #[derive(Debug, thiserror::Error)]
 pub enum SubscriptionError {
@@ -23,102 +31,710 @@ pub enum SubscriptionError {
     BlockNotFoundForNumber(u64),
 }
 
+impl Transient for SubscriptionError {
+    /// A block announced by the node may not be served by it, or by every
+    /// node behind a load balancer, right away.
+    fn is_transient(&self) -> bool {
+        match self {
+            SubscriptionError::TransportError(err) => err.is_transient(),
+            SubscriptionError::BlockNotFound(_) | SubscriptionError::BlockNotFoundForNumber(_) => {
+                true
+            }
+        }
+    }
+}
+
+/// Progress of a subscriber as seen by health checks. It outlives a single
+/// subscriber so that a restarted one keeps reporting against the same
+/// state.
+#[derive(Debug)]
+pub struct SubscriberState {
+    progress: Mutex<SubscriberStatus>,
+}
+
+/// A snapshot of [`SubscriberState`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SubscriberStatus {
+    /// Whether the subscriber is currently receiving blocks from the node.
+    pub connected: bool,
+    /// Latest block that has enough confirmations to be processed.
+    pub head: Option<u64>,
+    /// Last block handed to the callback.
+    pub last_block: Option<u64>,
+    /// Last block processed successfully with no failed block before it
+    /// still pending; processing resumes after it on restart.
+    pub checkpoint: Option<u64>,
+    /// Hash of the checkpoint block, when known, so that a restarted
+    /// subscriber resumes after it without fetching it first.
+    pub checkpoint_hash: Option<B256>,
+    /// When the last block was processed, or the state was created if none
+    /// has been yet.
+    pub last_progress: Instant,
+}
+
+impl SubscriberStatus {
+    /// Confirmed blocks not processed yet, unknown until both the head and a
+    /// processed block have been seen.
+    pub fn lag(&self) -> Option<u64> {
+        Some(self.head?.saturating_sub(self.last_block?))
+    }
+}
+
+impl Default for SubscriberState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SubscriberState {
+    pub fn new() -> Self {
+        Self {
+            progress: Mutex::new(SubscriberStatus {
+                connected: false,
+                head: None,
+                last_block: None,
+                checkpoint: None,
+                checkpoint_hash: None,
+                last_progress: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn status(&self) -> SubscriberStatus {
+        *self.lock()
+    }
+
+    pub fn set_connected(&self, connected: bool) {
+        self.lock().connected = connected;
+    }
+
+    pub fn record_head(&self, head: u64) {
+        self.lock().head = Some(head);
+    }
+
+    pub fn record_block(&self, number: u64) {
+        let mut progress = self.lock();
+        progress.last_block = Some(number);
+        progress.last_progress = Instant::now();
+    }
+
+    pub fn record_checkpoint(&self, number: u64, hash: Option<B256>) {
+        let mut progress = self.lock();
+        progress.checkpoint = Some(number);
+        progress.checkpoint_hash = hash;
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, SubscriberStatus> {
+        self.progress.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// What a block subscription yields.
+#[derive(Debug, Clone)]
+pub enum BlockEvent {
+    /// The next confirmed block.
+    NewBlock(Box<Block>),
+    /// Blocks `from..=to` delivered earlier are no longer part of the
+    /// canonical chain. The canonical blocks from `from` on are delivered
+    /// next.
+    Rollback { from: u64, to: u64 },
+    /// The subscription to the node was lost and established again. Blocks
+    /// produced in between are delivered before the next new one.
+    Reconnected,
+}
+
 #[trait_variant::make(BlockSubscriber: Send)]
 pub trait LocalBlockSubscriber {
+    /// Confirmed blocks, rollbacks and reconnect notices. The stream ends
+    /// once cancelled or after yielding an error. A block counts as
+    /// processed once the item after it is requested.
+    fn blocks(&self) -> impl Stream<Item = Result<BlockEvent, SubscriptionError>> + Send + '_;
+
+    /// Calls `callback_fn` with every new block until the stream of
+    /// [`LocalBlockSubscriber::blocks`] ends, see [`for_each_block`].
     async fn subscribe<T, Fut>(&self, callback_fn: T) -> Result<(), SubscriptionError>
     where
         T: Fn(Block) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = ()> + Send + 'static;
 }
 
+/// Drives `events`, awaiting `callback_fn` with every new block before the
+/// next event is requested.
+pub async fn for_each_block<S, T, Fut>(events: S, callback_fn: T) -> Result<(), SubscriptionError>
+where
+    S: Stream<Item = Result<BlockEvent, SubscriptionError>>,
+    T: Fn(Block) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut events = pin!(events);
+    while let Some(event) = events.next().await {
+        if let BlockEvent::NewBlock(block) = event? {
+            callback_fn(*block).await;
+        }
+    }
+    Ok(())
+}
+
+/// A new head, either known by number only or already fetched in full.
+enum Head {
+    Number(u64),
+    Block(Box<Block>),
+}
+
+type Heads<'a> = Pin<Box<dyn Stream<Item = Result<Head, SubscriptionError>> + Send + 'a>>;
+
+/// Where a stream returned by [`EthereumBlockSubscriber::blocks`] is at.
+#[derive(Default)]
+struct Progress<'a> {
+    heads: Option<Heads<'a>>,
+    /// Blocks skipped between the last delivered block and a new one, or
+    /// replaced by a reorg.
+    gap: Option<Gap>,
+    /// The number of the block handed out last.
+    delivered: Option<u64>,
+    connected: bool,
+    done: bool,
+}
+
+/// Blocks `next..block.number` still to be fetched, before `block` itself
+/// is delivered.
+struct Gap {
+    next: u64,
+    block: Block,
+}
+
+/// How many delivered blocks are remembered to find where a reorg forks
+/// off. Deeper reorgs roll back to the oldest block remembered.
+const REORG_HISTORY: usize = 128;
+
+/// How new heads are found when polling.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PollMode {
+    /// Poll a block filter created with `eth_newBlockFilter`.
+    #[default]
+    Filter,
+    /// Poll `eth_blockNumber` and fetch new blocks by number, which needs no
+    /// state on the node.
+    Head,
+}
+
+/// Where a polled head stream is at.
+struct Polling {
+    filter: Option<U256>,
+    /// Block hashes returned by the last filter poll, not yet handed out.
+    hashes: VecDeque<B256>,
+    last_head: Option<u64>,
+}
+
+/// Whether the node no longer knows the polled filter, as happens when
+/// requests are spread over several nodes.
+fn is_filter_not_found(payload: &ErrorPayload) -> bool {
+    let message = payload.message.to_lowercase();
+    message.contains("filter") && (message.contains("not found") || message.contains("not exist"))
+}
+
 pub struct EthereumBlockSubscriber {
     provider: Arc<RootProvider<BoxTransport>>,
+    confirmations: u64,
+    poll_interval: Option<Duration>,
+    /// Cleared once the node loses or refuses the block filter.
+    filter_polling: AtomicBool,
+    network: String,
+    /// Number and hash of the blocks delivered last, oldest first.
+    delivered: Mutex<VecDeque<(u64, B256)>>,
+    state: Arc<SubscriberState>,
+    cancellation: CancellationToken,
+    retry: RetryPolicy,
 }
 
 impl EthereumBlockSubscriber {
     pub fn new(provider: Arc<RootProvider<BoxTransport>>) -> Self {
-        Self { provider }
+        Self {
+            provider,
+            confirmations: 0,
+            poll_interval: None,
+            filter_polling: AtomicBool::new(true),
+            network: String::new(),
+            delivered: Mutex::new(VecDeque::new()),
+            state: Arc::new(SubscriberState::new()),
+            cancellation: CancellationToken::new(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Stops taking new blocks once `cancellation` is cancelled. The block
+    /// being processed at that point is finished first.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = cancellation;
+        self
     }
 
-    async fn process_pubsub_block<T, Fut>(
+    /// Reports progress into `state` instead of a private one.
+    pub fn with_state(mut self, state: Arc<SubscriberState>) -> Self {
+        self.state = state;
+        self
+    }
+
+    pub fn state(&self) -> Arc<SubscriberState> {
+        self.state.clone()
+    }
+
+    /// Names the network in the metrics this subscriber records.
+    pub fn with_network(mut self, network: String) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Delays each block until `confirmations` further blocks are on top of it.
+    pub fn with_confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    /// Overrides the provider's default interval when polling for blocks.
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = Some(poll_interval);
+        self
+    }
+
+    /// Chooses how heads are polled when the transport has no pubsub
+    /// support. Filter polling falls back to head polling by itself when the
+    /// node loses the filter.
+    pub fn with_poll_mode(self, poll_mode: PollMode) -> Self {
+        self.filter_polling
+            .store(poll_mode == PollMode::Filter, Ordering::Relaxed);
+        self
+    }
+
+    /// Retries fetching a new block on transient errors before giving up on
+    /// the subscription, or on just the block when a block skipped between
+    /// two heads still fails and the policy skips exhausted blocks.
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Resumes after the block `number` with `hash`, as if it had just been
+    /// delivered: the blocks produced since are delivered before the next
+    /// new one.
+    pub fn with_last_block(mut self, number: u64, hash: B256) -> Self {
+        self.delivered = Mutex::new(VecDeque::from([(number, hash)]));
+        self
+    }
+
+    /// Fetches the block that becomes confirmed when `head` is the latest
+    /// block, if any.
+    async fn confirmed_block(&self, head: u64) -> Result<Option<Block>, SubscriptionError> {
+        let Some(number) = head.checked_sub(self.confirmations) else {
+            return Ok(None);
+        };
+        self.state.record_head(number);
+        self.block_by_number(number).await.map(Some)
+    }
+
+    async fn block_by_number(&self, number: u64) -> Result<Block, SubscriptionError> {
+        self.retry
+            .retry("fetch_block", || async move {
+                self.provider
+                    .get_block_by_number(
+                        BlockNumberOrTag::Number(number),
+                        BlockTransactionsKind::Full,
+                    )
+                    .await?
+                    .ok_or(SubscriptionError::BlockNotFoundForNumber(number))
+            })
+            .await
+    }
+
+    /// The hash of the canonical block before `number`.
+    async fn parent_hash(&self, number: u64) -> Result<B256, SubscriptionError> {
+        self.retry
+            .retry("fetch_block", || async move {
+                self.provider
+                    .get_block_by_number(
+                        BlockNumberOrTag::Number(number),
+                        BlockTransactionsKind::Hashes,
+                    )
+                    .await?
+                    .ok_or(SubscriptionError::BlockNotFoundForNumber(number))
+            })
+            .await
+            .map(|block| block.header.parent_hash)
+    }
+
+    fn delivered(&self) -> std::sync::MutexGuard<'_, VecDeque<(u64, B256)>> {
+        self.delivered.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Whether `block` was delivered already, as when the head has not moved
+    /// since the subscriber resumed.
+    fn is_delivered(&self, block: &Block) -> bool {
+        self.delivered()
+            .contains(&(block.header.number, block.header.hash))
+    }
+
+    /// The first block skipped between the previously delivered block and
+    /// `block`, if any.
+    fn gap_start(&self, block: &Block) -> Option<u64> {
+        let (previous, _) = *self.delivered().back()?;
+        (block.header.number > previous + 1).then_some(previous + 1)
+    }
+
+    /// The first delivered block that is no longer canonical, walking back
+    /// from `block` until the canonical chain meets the delivered one.
+    async fn fork_point(&self, block: &Block) -> Result<u64, SubscriptionError> {
+        let mut number = block.header.number;
+        let mut parent_hash = block.header.parent_hash;
+        loop {
+            let Some(previous) = number.checked_sub(1) else {
+                return Ok(0);
+            };
+            let delivered = self
+                .delivered()
+                .iter()
+                .find(|(delivered, _)| *delivered == previous)
+                .map(|(_, hash)| *hash);
+            match delivered {
+                Some(hash) if hash == parent_hash => return Ok(number),
+                Some(_) => {
+                    parent_hash = self.parent_hash(previous).await?;
+                    number = previous;
+                }
+                None => {
+                    warn!(
+                        "Reorg on network {} is deeper than the {} blocks remembered",
+                        self.network, REORG_HISTORY
+                    );
+                    return Ok(number);
+                }
+            }
+        }
+    }
+
+    /// The delivered blocks `block` replaces when it does not extend the
+    /// previously delivered block, if any.
+    async fn rollback(&self, block: &Block) -> Result<Option<(u64, u64)>, SubscriptionError> {
+        let Some((previous_number, previous_hash)) = self.delivered().back().copied() else {
+            return Ok(None);
+        };
+        // Past a block skipped while filling a gap, there is nothing to
+        // check the new block against.
+        if block.header.number > previous_number + 1
+            || (block.header.number == previous_number + 1
+                && block.header.parent_hash == previous_hash)
+        {
+            return Ok(None);
+        }
+
+        let from = self.fork_point(block).await?;
+        warn!(
+            "Reorg detected at block {} on network {}, rolling back blocks {} to {}",
+            block.header.number, self.network, from, previous_number
+        );
+        metrics().increment(REORGS, &[("network", self.network.as_str())]);
+        self.delivered().retain(|(number, _)| *number < from);
+        Ok(Some((from, previous_number)))
+    }
+
+    /// Records the block handed out last as processed.
+    fn record_processed(&self, number: u64) {
+        let labels = [("network", self.network.as_str())];
+        self.state.record_block(number);
+        metrics().increment(BLOCKS_PROCESSED, &labels);
+        if let Some(lag) = self.state.status().lag() {
+            metrics().set(HEAD_LAG, &labels, lag as i64);
+        }
+    }
+
+    /// Subscribes to new heads, falling back to polling when the transport
+    /// has no pubsub support.
+    async fn heads(&self) -> Result<Heads<'_>, SubscriptionError> {
+        if let Ok(subscription) = self.provider.subscribe_blocks().await {
+            return Ok(Box::pin(subscription.into_stream().map(|header| {
+                info!("Received block number: {}", header.number);
+                Ok(Head::Number(header.number))
+            })));
+        }
+
+        let mut filter = None;
+        if self.filter_polling.load(Ordering::Relaxed) {
+            match self.provider.new_block_filter().await {
+                Ok(id) => filter = Some(id),
+                Err(RpcError::ErrorResp(payload)) => self.stop_filter_polling(&payload),
+                Err(err) => return Err(err.into()),
+            }
+        }
+        match filter {
+            Some(_) => info!("Using HTTP provider, polling a block filter instead."),
+            None => info!("Using HTTP provider, polling eth_blockNumber instead."),
+        }
+        let poll_interval = self
+            .poll_interval
+            .unwrap_or_else(|| self.provider.client().poll_interval());
+        let polling = Polling {
+            filter,
+            hashes: VecDeque::new(),
+            last_head: None,
+        };
+        Ok(Box::pin(stream::unfold(
+            polling,
+            move |mut polling| async move {
+                let head = self.poll_head(&mut polling, poll_interval).await;
+                Some((head, polling))
+            },
+        )))
+    }
+
+    /// Waits for the next head, polling the block filter for new block
+    /// hashes or, without one, `eth_blockNumber` for a higher number.
+    async fn poll_head(
         &self,
-        subscription: Subscription<Header>,
-        callback_fn: T,
-    ) -> Result<(), SubscriptionError>
-    where
-        T: Fn(Block) -> Fut + Send + 'static,
-        Fut: Future<Output = ()> + Send + 'static,
-    {
-        let mut stream = subscription.into_stream();
-
-        while let Some(header) = stream.next().await {
-            info!("Received block number: {}", header.number);
-            match self
-                .provider
-                .get_block_by_number(
-                    BlockNumberOrTag::Number(header.number),
-                    BlockTransactionsKind::Full,
-                )
-                .await?
-            {
-                Some(block) => {
-                    callback_fn(block).await;
+        polling: &mut Polling,
+        poll_interval: Duration,
+    ) -> Result<Head, SubscriptionError> {
+        loop {
+            if let Some(block_hash) = polling.hashes.pop_front() {
+                return self.head_for_hash(block_hash).await;
+            }
+            tokio::time::sleep(poll_interval).await;
+            match polling.filter {
+                Some(id) => {
+                    match self
+                        .retry
+                        .retry("poll_filter", || {
+                            self.provider.get_filter_changes::<B256>(id)
+                        })
+                        .await
+                    {
+                        Ok(hashes) => polling.hashes.extend(hashes),
+                        Err(RpcError::ErrorResp(payload)) if is_filter_not_found(&payload) => {
+                            self.stop_filter_polling(&payload);
+                            polling.filter = None;
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
                 }
                 None => {
-                    return Err(SubscriptionError::BlockNotFoundForNumber(header.number));
+                    let number = self
+                        .retry
+                        .retry("poll_head", || self.provider.get_block_number())
+                        .await?;
+                    if polling
+                        .last_head
+                        .is_some_and(|last_head| number <= last_head)
+                    {
+                        continue;
+                    }
+                    polling.last_head = Some(number);
+                    info!("Received block number: {}", number);
+                    return Ok(Head::Number(number));
                 }
             }
         }
-        Ok(())
     }
 
-    async fn process_poll_block<T, Fut>(
+    async fn head_for_hash(&self, block_hash: B256) -> Result<Head, SubscriptionError> {
+        let transactions_kind = if self.confirmations == 0 {
+            BlockTransactionsKind::Full
+        } else {
+            BlockTransactionsKind::Hashes
+        };
+        let block = self
+            .retry
+            .retry("fetch_block", || async move {
+                self.provider
+                    .get_block_by_hash(block_hash, transactions_kind)
+                    .await?
+                    .ok_or_else(|| SubscriptionError::BlockNotFound(block_hash.to_string()))
+            })
+            .await?;
+        info!("Received block number: {}", block.header.number);
+        if self.confirmations == 0 {
+            Ok(Head::Block(Box::new(block)))
+        } else {
+            Ok(Head::Number(block.header.number))
+        }
+    }
+
+    /// Switches to polling `eth_blockNumber` for good after the node lost or
+    /// refused the block filter.
+    fn stop_filter_polling(&self, payload: &ErrorPayload) {
+        warn!(
+            "Block filter unavailable on network {} ({}), polling eth_blockNumber instead",
+            self.network, payload
+        );
+        self.filter_polling.store(false, Ordering::Relaxed);
+    }
+
+    /// The confirmed block for a new head.
+    async fn confirm(&self, head: Head) -> Result<Option<Block>, SubscriptionError> {
+        match head {
+            Head::Block(block) => {
+                self.state.record_head(block.header.number);
+                Ok(Some(*block))
+            }
+            Head::Number(head) => self.confirmed_block(head).await,
+        }
+    }
+
+    /// Hands out `block`, or the rollback it causes while holding it back
+    /// until the replaced blocks are fetched again.
+    async fn deliver(
         &self,
-        poller: FilterPollerBuilder<BoxTransport, B256>,
-        action: T,
-    ) -> Result<(), SubscriptionError>
-    where
-        T: Fn(Block) -> Fut + Send + 'static,
-        Fut: Future<Output = ()> + Send + 'static,
-    {
-        let mut stream = poller.into_stream().flat_map(stream::iter);
-
-        while let Some(block_hash) = stream.next().await {
-            match self
-                .provider
-                .get_block_by_hash(block_hash, BlockTransactionsKind::Full)
-                .await?
-            {
-                Some(block) => {
-                    info!("Received block number: {}", block.header.number);
-                    action(block).await;
+        progress: &mut Progress<'_>,
+        block: Block,
+    ) -> Result<BlockEvent, SubscriptionError> {
+        if let Some((from, to)) = self.rollback(&block).await? {
+            match progress.gap.as_mut() {
+                // A block of the gap being filled; the gap's own block follows.
+                Some(gap) => gap.next = from,
+                None => progress.gap = Some(Gap { next: from, block }),
+            }
+            return Ok(BlockEvent::Rollback { from, to });
+        }
+        let mut delivered = self.delivered();
+        if delivered.len() == REORG_HISTORY {
+            delivered.pop_front();
+        }
+        delivered.push_back((block.header.number, block.header.hash));
+        progress.delivered = Some(block.header.number);
+        Ok(BlockEvent::NewBlock(Box::new(block)))
+    }
+
+    /// The next block of the gap being filled, if any, or the block that
+    /// revealed the gap once it is filled. Blocks that cannot be fetched are
+    /// skipped when the retry policy says so.
+    async fn fill_gap(
+        &self,
+        progress: &mut Progress<'_>,
+    ) -> Option<Result<BlockEvent, SubscriptionError>> {
+        let result = loop {
+            let mut gap = progress.gap.take()?;
+            if gap.next >= gap.block.header.number {
+                break self.deliver(progress, gap.block).await;
+            }
+            let number = gap.next;
+            gap.next += 1;
+            progress.gap = Some(gap);
+            match self.block_by_number(number).await {
+                Ok(block) => break self.deliver(progress, block).await,
+                Err(err) if self.retry.on_exhaustion == OnExhaustion::Skip => {
+                    error!(
+                        "Skipping block {} on network {}: {}",
+                        number, self.network, err
+                    );
+                    metrics().increment(BLOCKS_SKIPPED, &[("network", self.network.as_str())]);
                 }
+                Err(err) => break Err(err),
+            }
+        };
+        if result.is_err() {
+            progress.done = true;
+            self.state.set_connected(false);
+        }
+        Some(result)
+    }
+
+    async fn next_event<'a>(
+        &'a self,
+        progress: &mut Progress<'a>,
+    ) -> Option<Result<BlockEvent, SubscriptionError>> {
+        if let Some(number) = progress.delivered.take() {
+            self.record_processed(number);
+        }
+        if !progress.done && !self.cancellation.is_cancelled() {
+            if let Some(event) = self.fill_gap(progress).await {
+                return Some(event);
+            }
+        }
+
+        loop {
+            if progress.done || self.cancellation.is_cancelled() {
+                progress.done = true;
+                self.state.set_connected(false);
+                return None;
+            }
+
+            let Some(heads) = progress.heads.as_mut() else {
+                match self.heads().await {
+                    Ok(heads) => {
+                        progress.heads = Some(heads);
+                        self.state.set_connected(true);
+                        if progress.connected {
+                            metrics().increment(RECONNECTS, &[("network", self.network.as_str())]);
+                            return Some(Ok(BlockEvent::Reconnected));
+                        }
+                        progress.connected = true;
+                        continue;
+                    }
+                    Err(err) => {
+                        progress.done = true;
+                        return Some(Err(err));
+                    }
+                }
+            };
+
+            let head = tokio::select! {
+                biased;
+                _ = self.cancellation.cancelled() => continue,
+                head = heads.next() => head,
+            };
+            let result = match head {
+                Some(Ok(head)) => self.confirm(head).await,
+                Some(Err(err)) => Err(err),
                 None => {
-                    return Err(SubscriptionError::BlockNotFound(block_hash.to_string()));
+                    warn!("Block subscription ended, subscribing again");
+                    progress.heads = None;
+                    self.state.set_connected(false);
+                    continue;
+                }
+            };
+
+            match result {
+                Ok(Some(block)) if self.is_delivered(&block) => continue,
+                Ok(Some(block)) => {
+                    let Some(next) = self.gap_start(&block) else {
+                        // An empty gap, delivering the block right away.
+                        progress.gap = Some(Gap {
+                            next: block.header.number,
+                            block,
+                        });
+                        return self.fill_gap(progress).await;
+                    };
+                    info!(
+                        "Blocks {} to {} were skipped on network {}, fetching them first",
+                        next,
+                        block.header.number - 1,
+                        self.network
+                    );
+                    progress.gap = Some(Gap { next, block });
+                    return self.fill_gap(progress).await;
+                }
+                Ok(None) => continue,
+                Err(err) => {
+                    progress.done = true;
+                    self.state.set_connected(false);
+                    return Some(Err(err));
                 }
             }
         }
-        Ok(())
     }
 }
 
 impl BlockSubscriber for EthereumBlockSubscriber {
+    fn blocks(&self) -> impl Stream<Item = Result<BlockEvent, SubscriptionError>> + Send + '_ {
+        stream::unfold(Progress::default(), move |mut progress| async move {
+            let event = self.next_event(&mut progress).await?;
+            Some((event, progress))
+        })
+    }
+
     async fn subscribe<T, Fut>(&self, callback_fn: T) -> Result<(), SubscriptionError>
     where
         T: Fn(Block) -> Fut + Send + Sync + 'static,
         Fut: Future<Output = ()> + Send + 'static,
     {
-        match self.provider.subscribe_blocks().await {
-            Ok(subscription) => {
-                self.process_pubsub_block(subscription, callback_fn).await?;
-            }
-            Err(_err) => {
-                info!("Using HTTP provider, switching to watch_blocks instead.");
-                self.process_poll_block(self.provider.watch_blocks().await?, callback_fn)
-                    .await?;
-            }
-        }
-
-        Ok(())
+        for_each_block(BlockSubscriber::blocks(self), callback_fn).await
     }
 }
@@ -1,9 +1,22 @@
 use std::{
+    collections::BTreeMap,
     fmt::{self, Display},
     str::FromStr,
+    time::Duration,
 };
 
-use alloy::primitives::{keccak256, Address, FixedBytes};
+use crate::blockchain::batch::BatchConfig;
+use crate::blockchain::block::PollMode;
+use crate::blockchain::rate_limit::RateLimitConfig;
+use crate::blockchain::retry::RetryPolicy;
+use crate::health::HealthConfig;
+use crate::metrics::MetricsConfig;
+use crate::shutdown::ShutdownConfig;
+use alloy::{
+    json_abi::{Error as AbiError, Function, Param},
+    primitives::{keccak256, Address, FixedBytes},
+    transports::Authorization,
+};
 use regex::Regex;
 use serde::{
     de::{self, MapAccess, Visitor},
@@ -11,32 +24,129 @@ use serde::{
 };
 use thiserror::Error;
 
+#[derive(Debug, Error)]
+pub enum ConfigurationError {
+    #[error("At least one network is required")]
+    NoNetworks,
+    #[error("Subscription to {0} references unknown network '{1}'")]
+    UnknownNetwork(Address, String),
+    #[error("Subscription to {0} must name its network when several are configured")]
+    MissingNetwork(Address),
+}
+
+/// Networks by name, and the subscriptions that reference them. A
+/// subscription without a `network` belongs to the only configured network.
 #[derive(Debug, Deserialize)]
+#[serde(try_from = "ConfigurationDefinition")]
 pub struct Configuration {
-    pub network: Network,
+    pub networks: BTreeMap<String, Network>,
     pub subscriptions: Vec<EventSubscription>,
+    pub metrics: MetricsConfig,
+    pub health: HealthConfig,
+    pub shutdown: ShutdownConfig,
+}
+
+#[derive(Deserialize)]
+struct ConfigurationDefinition {
+    networks: BTreeMap<String, Network>,
+    subscriptions: Vec<EventSubscription>,
+    #[serde(default)]
+    metrics: MetricsConfig,
+    #[serde(default)]
+    health: HealthConfig,
+    #[serde(default)]
+    shutdown: ShutdownConfig,
+}
+
+impl TryFrom<ConfigurationDefinition> for Configuration {
+    type Error = ConfigurationError;
+
+    fn try_from(definition: ConfigurationDefinition) -> Result<Self, Self::Error> {
+        let networks = definition.networks;
+        let default_network = match networks.keys().collect::<Vec<_>>().as_slice() {
+            [] => return Err(ConfigurationError::NoNetworks),
+            [name] => Some((*name).clone()),
+            _ => None,
+        };
+
+        let mut subscriptions = definition.subscriptions;
+        for subscription in &mut subscriptions {
+            if subscription.network.is_empty() {
+                subscription.network =
+                    default_network
+                        .clone()
+                        .ok_or(ConfigurationError::MissingNetwork(
+                            subscription.contract_address,
+                        ))?;
+            } else if !networks.contains_key(&subscription.network) {
+                return Err(ConfigurationError::UnknownNetwork(
+                    subscription.contract_address,
+                    subscription.network.clone(),
+                ));
+            }
+        }
+
+        Ok(Configuration::new(networks, subscriptions)
+            .with_metrics(definition.metrics)
+            .with_health(definition.health)
+            .with_shutdown(definition.shutdown))
+    }
 }
 
 impl Configuration {
-    pub fn new(network: Network, subscriptions: Vec<EventSubscription>) -> Self {
+    pub fn new(networks: BTreeMap<String, Network>, subscriptions: Vec<EventSubscription>) -> Self {
         Self {
-            network,
+            networks,
             subscriptions,
+            metrics: MetricsConfig::default(),
+            health: HealthConfig::default(),
+            shutdown: ShutdownConfig::default(),
         }
     }
+
+    pub fn with_metrics(mut self, metrics: MetricsConfig) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    pub fn with_health(mut self, health: HealthConfig) -> Self {
+        self.health = health;
+        self
+    }
+
+    pub fn with_shutdown(mut self, shutdown: ShutdownConfig) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Subscriptions that reference the network called `name`.
+    pub fn subscriptions_for(&self, name: &str) -> Vec<EventSubscription> {
+        self.subscriptions
+            .iter()
+            .filter(|subscription| subscription.network == name)
+            .cloned()
+            .collect()
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum NetworkProtocolError {
     #[error("Invalid network protocol: {0}")]
     InvalidProtocol(String),
+    #[error("Either url or protocol is required")]
+    MissingEndpoint,
+    #[error("url cannot be combined with protocol, host, port or path")]
+    ConflictingEndpoint,
+    #[error("path to the IPC socket is required")]
+    MissingSocketPath,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum NetworkProtocol {
     Http,
     Https,
     WebSocket,
+    SecureWebSocket,
     Ipc,
 }
 
@@ -46,78 +156,341 @@ impl Display for NetworkProtocol {
             NetworkProtocol::Http => write!(f, "http"),
             NetworkProtocol::Https => write!(f, "https"),
             NetworkProtocol::WebSocket => write!(f, "ws"),
+            NetworkProtocol::SecureWebSocket => write!(f, "wss"),
             NetworkProtocol::Ipc => write!(f, "ipc"),
         }
     }
 }
 
+impl FromStr for NetworkProtocol {
+    type Err = NetworkProtocolError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "http" => Ok(NetworkProtocol::Http),
+            "https" => Ok(NetworkProtocol::Https),
+            "ws" => Ok(NetworkProtocol::WebSocket),
+            "wss" => Ok(NetworkProtocol::SecureWebSocket),
+            "ipc" => Ok(NetworkProtocol::Ipc),
+            _ => Err(NetworkProtocolError::InvalidProtocol(s.to_string())),
+        }
+    }
+}
+
 impl<'de> Deserialize<'de> for NetworkProtocol {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        match s.as_str() {
-            "http" => Ok(NetworkProtocol::Http),
-            "https" => Ok(NetworkProtocol::Https),
-            "ws" => Ok(NetworkProtocol::WebSocket),
-            "ipc" => Ok(NetworkProtocol::Ipc),
-            _ => Err(de::Error::custom(NetworkProtocolError::InvalidProtocol(s))),
+        NetworkProtocol::from_str(&s).map_err(de::Error::custom)
+    }
+}
+
+/// Credentials sent with every request to the node. A bearer token takes
+/// precedence over basic auth; credentials embedded in the URL are used when
+/// neither is configured.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct NetworkAuth {
+    #[serde(default)]
+    pub bearer_token: Option<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+impl NetworkAuth {
+    pub fn authorization(&self) -> Option<Authorization> {
+        match (&self.bearer_token, &self.username) {
+            (Some(token), _) => Some(Authorization::bearer(token)),
+            (None, Some(username)) => Some(Authorization::basic(
+                username,
+                self.password.as_deref().unwrap_or_default(),
+            )),
+            (None, None) => None,
         }
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.authorization().is_none() && self.headers.is_empty()
+    }
 }
 
-#[derive(Debug, Deserialize)]
+/// Receipts fetched at a time when the node does not support
+/// `eth_getBlockReceipts`.
+pub const DEFAULT_RECEIPT_CONCURRENCY: usize = 16;
+
+/// Node endpoint, given either as a full `url` or as `protocol`, `host`,
+/// `port` and `path`. For IPC the URL (or `path`) is the socket file. Blocks
+/// are processed once `confirmations` further blocks have been produced, with
+/// up to `receipt_concurrency` receipts fetched at a time.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(try_from = "NetworkDefinition")]
 pub struct Network {
     pub protocol: NetworkProtocol,
+    pub url: Option<String>,
     pub host: String,
-    pub port: u16,
+    pub port: Option<u16>,
     pub path: String,
+    pub auth: NetworkAuth,
+    pub confirmations: u64,
+    pub poll_interval: Option<Duration>,
+    /// How new heads are polled when the endpoint has no subscription
+    /// support.
+    pub poll_mode: PollMode,
+    pub receipt_concurrency: usize,
+    /// Coalesces concurrent HTTP requests into JSON-RPC batches when set.
+    pub batch: Option<BatchConfig>,
+    /// Throttles the requests sent to the endpoint when set.
+    pub rate_limit: Option<RateLimitConfig>,
+    /// How failed calls are retried and what happens to blocks that still
+    /// fail.
+    pub retry: RetryPolicy,
+}
+
+#[derive(Deserialize)]
+struct NetworkDefinition {
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    protocol: Option<NetworkProtocol>,
+    #[serde(default)]
+    host: String,
+    #[serde(default)]
+    port: Option<u16>,
+    #[serde(default)]
+    path: String,
+    #[serde(default)]
+    auth: NetworkAuth,
+    #[serde(default)]
+    confirmations: u64,
+    #[serde(default)]
+    poll_interval_ms: Option<u64>,
+    #[serde(default)]
+    poll_mode: PollMode,
+    #[serde(default)]
+    receipt_concurrency: Option<usize>,
+    #[serde(default)]
+    batch: Option<BatchConfig>,
+    #[serde(default)]
+    rate_limit: Option<RateLimitConfig>,
+    #[serde(default)]
+    retry: RetryPolicy,
+}
+
+impl TryFrom<NetworkDefinition> for Network {
+    type Error = NetworkProtocolError;
+
+    fn try_from(definition: NetworkDefinition) -> Result<Self, Self::Error> {
+        let network = match (definition.url, definition.protocol) {
+            (Some(url), None) => {
+                if !definition.host.is_empty()
+                    || definition.port.is_some()
+                    || !definition.path.is_empty()
+                {
+                    return Err(NetworkProtocolError::ConflictingEndpoint);
+                }
+                Network::from_url(url)?
+            }
+            (None, Some(protocol)) => Network {
+                protocol,
+                url: None,
+                host: definition.host,
+                port: definition.port,
+                path: definition.path,
+                auth: NetworkAuth::default(),
+                confirmations: 0,
+                poll_interval: None,
+                poll_mode: PollMode::default(),
+                receipt_concurrency: DEFAULT_RECEIPT_CONCURRENCY,
+                batch: None,
+                rate_limit: None,
+                retry: RetryPolicy::default(),
+            },
+            (Some(_), Some(_)) => return Err(NetworkProtocolError::ConflictingEndpoint),
+            (None, None) => return Err(NetworkProtocolError::MissingEndpoint),
+        };
+        if network.protocol == NetworkProtocol::Ipc && network.url().is_empty() {
+            return Err(NetworkProtocolError::MissingSocketPath);
+        }
+        let mut network = network
+            .with_auth(definition.auth)
+            .with_confirmations(definition.confirmations)
+            .with_poll_mode(definition.poll_mode)
+            .with_retry(definition.retry);
+        if let Some(poll_interval_ms) = definition.poll_interval_ms {
+            network = network.with_poll_interval(Duration::from_millis(poll_interval_ms));
+        }
+        if let Some(receipt_concurrency) = definition.receipt_concurrency {
+            network = network.with_receipt_concurrency(receipt_concurrency);
+        }
+        if let Some(batch) = definition.batch {
+            network = network.with_batch(batch);
+        }
+        if let Some(rate_limit) = definition.rate_limit {
+            network = network.with_rate_limit(rate_limit);
+        }
+        Ok(network)
+    }
 }
 
 impl Network {
     pub fn new(protocol: NetworkProtocol, host: String, port: u16, path: String) -> Self {
         Self {
             protocol,
+            url: None,
             host,
-            port,
+            port: Some(port),
             path,
+            auth: NetworkAuth::default(),
+            confirmations: 0,
+            poll_interval: None,
+            poll_mode: PollMode::default(),
+            receipt_concurrency: DEFAULT_RECEIPT_CONCURRENCY,
+            batch: None,
+            rate_limit: None,
+            retry: RetryPolicy::default(),
         }
     }
 
+    /// Uses `url` as is. A value without a scheme, or with the `ipc://`
+    /// scheme, is taken as the path of an IPC socket.
+    pub fn from_url(url: String) -> Result<Self, NetworkProtocolError> {
+        let (protocol, url) = match url.split_once("://") {
+            Some(("ipc", path)) => (NetworkProtocol::Ipc, path.to_string()),
+            Some((scheme, _)) => (NetworkProtocol::from_str(scheme)?, url),
+            None => (NetworkProtocol::Ipc, url),
+        };
+        Ok(Self {
+            protocol,
+            url: Some(url),
+            host: String::new(),
+            port: None,
+            path: String::new(),
+            auth: NetworkAuth::default(),
+            confirmations: 0,
+            poll_interval: None,
+            poll_mode: PollMode::default(),
+            receipt_concurrency: DEFAULT_RECEIPT_CONCURRENCY,
+            batch: None,
+            rate_limit: None,
+            retry: RetryPolicy::default(),
+        })
+    }
+
+    pub fn with_auth(mut self, auth: NetworkAuth) -> Self {
+        self.auth = auth;
+        self
+    }
+
+    pub fn with_confirmations(mut self, confirmations: u64) -> Self {
+        self.confirmations = confirmations;
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = Some(poll_interval);
+        self
+    }
+
+    pub fn with_poll_mode(mut self, poll_mode: PollMode) -> Self {
+        self.poll_mode = poll_mode;
+        self
+    }
+
+    pub fn with_receipt_concurrency(mut self, receipt_concurrency: usize) -> Self {
+        self.receipt_concurrency = receipt_concurrency;
+        self
+    }
+
+    pub fn with_batch(mut self, batch: BatchConfig) -> Self {
+        self.batch = Some(batch);
+        self
+    }
+
+    pub fn with_rate_limit(mut self, rate_limit: RateLimitConfig) -> Self {
+        self.rate_limit = Some(rate_limit);
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
     pub fn url(&self) -> String {
+        if let Some(url) = &self.url {
+            return url.clone();
+        }
+        if self.protocol == NetworkProtocol::Ipc {
+            return self.path.clone();
+        }
+
+        let mut url = format!("{}://{}", self.protocol, self.host);
+        if let Some(port) = self.port {
+            url.push_str(&format!(":{}", port));
+        }
         if !self.path.is_empty() {
-            format!(
-                "{}://{}:{}/{}",
-                self.protocol, self.host, self.port, self.path
-            )
-        } else {
-            format!("{}://{}:{}", self.protocol, self.host, self.port)
+            url.push_str(&format!("/{}", self.path.trim_start_matches('/')));
         }
+        url
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EventSubscription {
+    /// Name of the network the contract lives on.
+    pub network: String,
     pub contract_address: Address,
+    /// Block to backfill from when the subscription is added by a reload.
+    pub start_block: Option<u64>,
     pub events: Vec<EventFilter>,
+    pub functions: Vec<FunctionFilter>,
+    pub errors: Vec<ErrorFilter>,
 }
 
 impl EventSubscription {
     pub fn new(contract_address: Address, events: Vec<EventFilter>) -> Self {
         Self {
+            network: String::new(),
             contract_address,
+            start_block: None,
             events,
+            functions: Vec::new(),
+            errors: Vec::new(),
         }
     }
+
+    pub fn with_network(mut self, network: String) -> Self {
+        self.network = network;
+        self
+    }
+
+    pub fn with_start_block(mut self, start_block: Option<u64>) -> Self {
+        self.start_block = start_block;
+        self
+    }
+
+    pub fn with_functions(mut self, functions: Vec<FunctionFilter>) -> Self {
+        self.functions = functions;
+        self
+    }
+
+    pub fn with_errors(mut self, errors: Vec<ErrorFilter>) -> Self {
+        self.errors = errors;
+        self
+    }
 }
 
 impl Display for EventSubscription {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Contract: {}, Events: {:?}",
-            self.contract_address, self.events
+            "Contract: {}, Events: {:?}, Functions: {:?}",
+            self.contract_address, self.events, self.functions
         )
     }
 }
@@ -140,29 +513,57 @@ impl<'de> Deserialize<'de> for EventSubscription {
             where
                 V: MapAccess<'de>,
             {
+                let mut network: Option<String> = None;
+                let mut start_block: Option<u64> = None;
                 let mut contract_address = None;
                 let mut events = None;
+                let mut functions = None;
+                let mut errors = None;
 
-                while let Some(key) = map.next_key()? {
-                    match key {
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
                         "contract_address" => {
                             if contract_address.is_some() {
                                 return Err(de::Error::duplicate_field("contract_address"));
                             }
-                            let value: &str = map.next_value()?;
-                            contract_address = Some(Address::from_str(value).map_err(|_| {
+                            let value: String = map.next_value()?;
+                            contract_address = Some(Address::from_str(&value).map_err(|_| {
                                 de::Error::invalid_value(
-                                    de::Unexpected::Str(value),
+                                    de::Unexpected::Str(&value),
                                     &"a valid address",
                                 )
                             })?);
                         }
+                        "network" => {
+                            if network.is_some() {
+                                return Err(de::Error::duplicate_field("network"));
+                            }
+                            network = Some(map.next_value()?);
+                        }
+                        "start_block" => {
+                            if start_block.is_some() {
+                                return Err(de::Error::duplicate_field("start_block"));
+                            }
+                            start_block = map.next_value()?;
+                        }
                         "events" => {
                             if events.is_some() {
                                 return Err(de::Error::duplicate_field("events"));
                             }
                             events = Some(map.next_value()?);
                         }
+                        "functions" => {
+                            if functions.is_some() {
+                                return Err(de::Error::duplicate_field("functions"));
+                            }
+                            functions = Some(map.next_value()?);
+                        }
+                        "errors" => {
+                            if errors.is_some() {
+                                return Err(de::Error::duplicate_field("errors"));
+                            }
+                            errors = Some(map.next_value()?);
+                        }
                         _ => {
                             let _: de::IgnoredAny = map.next_value()?;
                         }
@@ -171,9 +572,18 @@ impl<'de> Deserialize<'de> for EventSubscription {
 
                 let contract_address =
                     contract_address.ok_or_else(|| de::Error::missing_field("contract_address"))?;
-                let events = events.ok_or_else(|| de::Error::missing_field("events"))?;
+                let (events, functions) = match (events, functions) {
+                    (None, None) => return Err(de::Error::missing_field("events")),
+                    (events, functions) => {
+                        (events.unwrap_or_default(), functions.unwrap_or_default())
+                    }
+                };
 
-                Ok(EventSubscription::new(contract_address, events))
+                Ok(EventSubscription::new(contract_address, events)
+                    .with_network(network.unwrap_or_default())
+                    .with_start_block(start_block)
+                    .with_functions(functions)
+                    .with_errors(errors.unwrap_or_default()))
             }
         }
 
@@ -187,7 +597,7 @@ pub enum ParamTypeError {
     UnsupportedDataType(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ParamType {
     Address,
     UInt(usize),
@@ -247,6 +657,14 @@ impl FromStr for ParamType {
 }
 
 impl ParamType {
+    pub fn is_dynamic(&self) -> bool {
+        match self {
+            ParamType::String | ParamType::Bytes | ParamType::Array(_) => true,
+            ParamType::Struct(fields) => fields.iter().any(ParamType::is_dynamic),
+            _ => false,
+        }
+    }
+
     pub fn name(&self) -> String {
         match self {
             ParamType::Address => "address".to_string(),
@@ -294,7 +712,7 @@ pub enum EventFilterError {
     UnsupportedDataType(String),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct EventFilter {
     pub signature: String,
     pub hash: FixedBytes<32>,
@@ -330,18 +748,11 @@ impl EventFilter {
 
         if let Some(captures) = re.captures(event_signature) {
             let event_name = captures[1].to_string();
-            let types = &captures[2];
-
-            let data_types: Result<Vec<ParamType>, EventFilterError> = types
-                .split(',')
-                .map(|s| s.trim())
-                .map(|type_str| {
-                    ParamType::from_str(type_str.trim())
-                        .map_err(|_| EventFilterError::UnsupportedDataType(type_str.to_string()))
-                })
-                .collect();
-
-            Ok((event_name, data_types?))
+            let data_types = parse_param_types(&captures[2]).map_err(
+                |ParamTypeError::UnsupportedDataType(t)| EventFilterError::UnsupportedDataType(t),
+            )?;
+
+            Ok((event_name, data_types))
         } else {
             Err(EventFilterError::InvalidSignatureFormat)
         }
@@ -354,6 +765,128 @@ impl<'de> Deserialize<'de> for EventFilter {
         D: serde::Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        EventFilter::new(s).map_err(|err| serde::de::Error::custom(err))
+        EventFilter::new(s).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Error)]
+pub enum FunctionFilterError {
+    #[error("Invalid function signature format.")]
+    InvalidSignatureFormat,
+    #[error("Unsupported data type: {0}")]
+    UnsupportedDataType(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionFilter {
+    pub signature: String,
+    pub selector: FixedBytes<4>,
+    pub function_name: String,
+    pub data_types: Vec<ParamType>,
+}
+
+impl FunctionFilter {
+    pub fn new(signature: String) -> Result<Self, FunctionFilterError> {
+        let re = Regex::new(r"^(\w+)\(([^)]*)\)$").unwrap();
+        let captures = re
+            .captures(&signature)
+            .ok_or(FunctionFilterError::InvalidSignatureFormat)?;
+
+        let function_name = captures[1].to_string();
+        let data_types = if captures[2].trim().is_empty() {
+            Vec::new()
+        } else {
+            parse_param_types(&captures[2]).map_err(|ParamTypeError::UnsupportedDataType(t)| {
+                FunctionFilterError::UnsupportedDataType(t)
+            })?
+        };
+
+        Ok(Self {
+            selector: FixedBytes::from_slice(&keccak256(signature.as_bytes())[..4]),
+            function_name,
+            data_types,
+            signature,
+        })
+    }
+
+    /// Builds a filter from a function entry of a contract's JSON ABI.
+    pub fn from_abi(function: &Function) -> Result<Self, FunctionFilterError> {
+        Self::from_abi_inputs(
+            function.signature(),
+            function.selector(),
+            &function.name,
+            &function.inputs,
+        )
+    }
+
+    /// Builds a filter from an error entry of a contract's JSON ABI.
+    pub fn from_abi_error(error: &AbiError) -> Result<Self, FunctionFilterError> {
+        Self::from_abi_inputs(
+            error.signature(),
+            error.selector(),
+            &error.name,
+            &error.inputs,
+        )
+    }
+
+    fn from_abi_inputs(
+        signature: String,
+        selector: FixedBytes<4>,
+        name: &str,
+        inputs: &[Param],
+    ) -> Result<Self, FunctionFilterError> {
+        let data_types = inputs
+            .iter()
+            .map(|param| {
+                let type_str = param.selector_type();
+                ParamType::from_str(&type_str)
+                    .map_err(|_| FunctionFilterError::UnsupportedDataType(type_str.into_owned()))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self {
+            signature,
+            selector,
+            function_name: name.to_string(),
+            data_types,
+        })
+    }
+
+    /// Returns the call arguments if `input` starts with this function's selector.
+    pub fn match_input<'a>(&self, input: &'a [u8]) -> Option<&'a [u8]> {
+        input.strip_prefix(self.selector.as_slice())
     }
 }
+
+/// Custom errors share the function selector scheme: the first four bytes of
+/// the keccak256 hash of the signature.
+pub type ErrorFilter = FunctionFilter;
+
+impl<'de> Deserialize<'de> for FunctionFilter {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum FunctionDefinition {
+            Signature(String),
+            Abi(Function),
+            AbiError(AbiError),
+        }
+
+        match FunctionDefinition::deserialize(deserializer)? {
+            FunctionDefinition::Signature(s) => FunctionFilter::new(s),
+            FunctionDefinition::Abi(function) => FunctionFilter::from_abi(&function),
+            FunctionDefinition::AbiError(error) => FunctionFilter::from_abi_error(&error),
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+fn parse_param_types(types: &str) -> Result<Vec<ParamType>, ParamTypeError> {
+    types
+        .split(',')
+        .map(|type_str| ParamType::from_str(type_str.trim()))
+        .collect()
+}
@@ -1,24 +1,147 @@
-use super::configuration::Configuration;
+use super::batch::BatchLayer;
+use super::configuration::{Network, NetworkProtocol};
+use super::http::HttpTransport;
+use super::rate_limit::RateLimitLayer;
+use super::rpc_metrics::RpcMetricsLayer;
 use alloy::{
-    providers::{ProviderBuilder, RootProvider},
-    transports::{BoxTransport, TransportError},
+    providers::{IpcConnect, ProviderBuilder, RootProvider, WsConnect},
+    rpc::client::ClientBuilder,
+    transports::{Authorization, BoxTransport, TransportError},
 };
-use log::info;
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION},
+    Url,
+};
+use std::path::PathBuf;
+use tower::util::option_layer;
+use tracing::info;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ConnectionError {
     #[error("Transport error: {0}")]
     TransportError(#[from] TransportError),
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    #[error("Invalid header: {0}")]
+    InvalidHeader(String),
+    #[error("HTTP client error: {0}")]
+    HttpClientError(#[from] reqwest::Error),
+    #[error("{0} is not supported by the {1} protocol")]
+    UnsupportedAuth(&'static str, NetworkProtocol),
 }
 
+/// Connects to `network`, labelling its RPC metrics with `name` and
+/// throttling requests through `rate_limit`, if any.
 pub async fn build_connection(
-    config: &Configuration,
+    name: &str,
+    network: &Network,
+    rate_limit: Option<RateLimitLayer>,
 ) -> Result<RootProvider<BoxTransport>, ConnectionError> {
-    let url = config.network.url();
-    info!("Connecting to network at URL: {}", url);
+    let rate_limit = option_layer(rate_limit);
+    let url = network.url();
+
+    let client = match network.protocol {
+        NetworkProtocol::Http | NetworkProtocol::Https => {
+            let url = parse_url(&url)?;
+            info!("Connecting to network at URL: {}", redact(&url));
+            let client = reqwest::Client::builder()
+                .default_headers(headers(network, &url)?)
+                .build()?;
+            let http = HttpTransport::new(client, url);
+            let is_local = http.guess_local();
+            ClientBuilder::default()
+                .layer(RpcMetricsLayer::new(name))
+                .layer(option_layer(network.batch.clone().map(BatchLayer::new)))
+                .layer(rate_limit)
+                .transport(http, is_local)
+                .boxed()
+        }
+        NetworkProtocol::WebSocket | NetworkProtocol::SecureWebSocket => {
+            if !network.auth.headers.is_empty() {
+                return Err(ConnectionError::UnsupportedAuth(
+                    "Custom headers",
+                    network.protocol,
+                ));
+            }
+            let parsed_url = parse_url(&url)?;
+            info!("Connecting to network at URL: {}", redact(&parsed_url));
+            let mut ws = WsConnect::new(url);
+            if let Some(auth) = authorization(network, &parsed_url) {
+                ws = ws.with_auth(auth);
+            }
+            ClientBuilder::default()
+                .layer(RpcMetricsLayer::new(name))
+                .layer(rate_limit)
+                .ws(ws)
+                .await?
+                .boxed()
+        }
+        NetworkProtocol::Ipc => {
+            if !network.auth.is_empty() {
+                return Err(ConnectionError::UnsupportedAuth(
+                    "Authentication",
+                    network.protocol,
+                ));
+            }
+            info!("Connecting to network at IPC socket: {}", url);
+            ClientBuilder::default()
+                .layer(RpcMetricsLayer::new(name))
+                .layer(rate_limit)
+                .ipc(IpcConnect::new(PathBuf::from(url)))
+                .await?
+                .boxed()
+        }
+    };
+
+    Ok(ProviderBuilder::new().on_client(client))
+}
+
+/// Builds the rate limiter configured for `network`, if any. Requests are
+/// throttled per endpoint and clones of the layer share its token bucket, so
+/// build it once and pass it to every connection to the network.
+pub fn rate_limit_layer(name: &str, network: &Network) -> Option<RateLimitLayer> {
+    network
+        .rate_limit
+        .clone()
+        .map(|config| RateLimitLayer::new(config).with_network(name))
+}
+
+fn parse_url(url: &str) -> Result<Url, ConnectionError> {
+    Url::parse(url).map_err(|err| ConnectionError::InvalidUrl(format!("{}: {}", url, err)))
+}
+
+fn authorization(network: &Network, url: &Url) -> Option<Authorization> {
+    network
+        .auth
+        .authorization()
+        .or_else(|| Authorization::extract_from_url(url))
+}
+
+fn headers(network: &Network, url: &Url) -> Result<HeaderMap, ConnectionError> {
+    let mut headers = HeaderMap::new();
+    if let Some(auth) = authorization(network, url) {
+        let value = HeaderValue::from_str(&auth.to_string())
+            .map_err(|_| ConnectionError::InvalidHeader(AUTHORIZATION.to_string()))?;
+        headers.insert(AUTHORIZATION, value);
+    }
+    for (name, value) in &network.auth.headers {
+        let header_name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|_| ConnectionError::InvalidHeader(name.clone()))?;
+        let header_value = HeaderValue::from_str(value)
+            .map_err(|_| ConnectionError::InvalidHeader(name.clone()))?;
+        headers.insert(header_name, header_value);
+    }
+    Ok(headers)
+}
 
-    ProviderBuilder::new()
-        .on_builtin(&url)
-        .await
-        .map_err(ConnectionError::TransportError)
+/// Hides credentials and query parameters, which commonly carry API keys,
+/// before the URL is logged.
+fn redact(url: &Url) -> String {
+    let mut url = url.clone();
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    if url.query().is_some() {
+        url.set_query(Some("..."));
+    }
+    url.to_string()
 }
@@ -1,8 +1,8 @@
 use crate::blockchain::configuration::ParamType;
-use alloy::primitives::{Address, Bytes, Signed};
+use alloy::primitives::{Address, Bytes, Signed, U256};
 use thiserror::Error;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Parameter {
     Address(Address),
     Uint(usize),
@@ -16,6 +16,24 @@ pub enum Parameter {
     Struct(Vec<Parameter>),
 }
 
+impl Parameter {
+    /// The ABI kind of the parameter, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Parameter::Address(_) => "address",
+            Parameter::Uint(_) => "uint",
+            Parameter::Int(_) => "int",
+            Parameter::Bool(_) => "bool",
+            Parameter::String(_) => "string",
+            Parameter::Bytes(_) => "bytes",
+            Parameter::FixedBytes(_) => "fixed bytes",
+            Parameter::FixedArray(_) => "fixed array",
+            Parameter::Array(_) => "array",
+            Parameter::Struct(_) => "struct",
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum DecodeError {
     #[error("Out of bounds")]
@@ -28,11 +46,24 @@ pub enum DecodeError {
     Utf8Error(#[from] std::string::FromUtf8Error),
     #[error("Memory allocation error")]
     MemoryAllocationError,
+    #[error("Non-zero padding at offset {0}")]
+    InvalidPadding(usize),
+    #[error("Invalid boolean value at offset {0}")]
+    InvalidBool(usize),
+    #[error("Value out of range for {0} at offset {1}")]
+    ValueOutOfRange(String, usize),
+    #[error("Length or offset out of range at offset {0}")]
+    OffsetOutOfRange(usize),
+    #[error("Trailing data: expected {expected} bytes, found {found}")]
+    TrailingData { expected: usize, found: usize },
 }
 
 pub struct DecodeResult {
     pub parameter: Parameter,
     pub new_offset: usize,
+    /// End of the region of `data` covered by the parameter, including any
+    /// dynamic tail and its padding.
+    pub data_end: usize,
 }
 
 pub trait Decoder {
@@ -45,85 +76,166 @@ pub trait Decoder {
     ) -> Result<DecodeResult, DecodeError>;
 }
 
+#[derive(Default)]
 pub struct EthereumDecoder {
     pub param_types: Vec<ParamType>,
+    /// Rejects non-canonical encodings (dirty padding, non-canonical bools,
+    /// out-of-range integers and trailing data) instead of ignoring them.
+    pub strict: bool,
 }
 
 impl EthereumDecoder {
     pub fn new(param_types: Vec<ParamType>) -> Self {
-        Self { param_types }
-    }
-
-    pub fn default() -> Self {
         Self {
-            param_types: Vec::new(),
+            param_types,
+            strict: false,
         }
     }
 
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
     fn decode_address(&self, data: &Bytes, offset: usize) -> Result<DecodeResult, DecodeError> {
         let slice = peek_32_bytes(data, offset)?;
+        if self.strict && !is_zero(&slice[..12]) {
+            return Err(DecodeError::InvalidPadding(offset));
+        }
         let address = Address::from_slice(&slice[12..]);
         let result = DecodeResult {
             parameter: Parameter::Address(address),
             new_offset: offset + 32,
+            data_end: offset + 32,
         };
         Ok(result)
     }
 
-    fn decode_uint(&self, data: &Bytes, offset: usize) -> Result<DecodeResult, DecodeError> {
+    fn decode_uint(
+        &self,
+        data: &Bytes,
+        offset: usize,
+        size: usize,
+    ) -> Result<DecodeResult, DecodeError> {
         let slice = peek_32_bytes(data, offset)?;
+        if self.strict && !is_zero(&slice[..high_bytes(size)]) {
+            return Err(DecodeError::ValueOutOfRange(
+                ParamType::UInt(size).name(),
+                offset,
+            ));
+        }
         let value = Signed::<256, 4>::try_from_be_slice(slice.as_slice())
             .ok_or(DecodeError::InvalidSignedInteger)?;
+        let value = match usize::try_from(value) {
+            Ok(value) => value,
+            Err(_) if self.strict => {
+                return Err(DecodeError::ValueOutOfRange("usize".to_string(), offset))
+            }
+            Err(_) => value.low_usize(),
+        };
         let result = DecodeResult {
-            parameter: Parameter::Uint(value.as_usize()),
+            parameter: Parameter::Uint(value),
             new_offset: offset + 32,
+            data_end: offset + 32,
         };
         Ok(result)
     }
 
-    fn decode_int(&self, data: &Bytes, offset: usize) -> Result<DecodeResult, DecodeError> {
+    fn decode_int(
+        &self,
+        data: &Bytes,
+        offset: usize,
+        size: usize,
+    ) -> Result<DecodeResult, DecodeError> {
         let slice = peek_32_bytes(data, offset)?;
+        if self.strict && !is_sign_extended(&slice, size) {
+            return Err(DecodeError::ValueOutOfRange(
+                ParamType::Int(size).name(),
+                offset,
+            ));
+        }
         let value = Signed::<256, 4>::try_from_be_slice(slice.as_slice())
             .ok_or(DecodeError::InvalidSignedInteger)?;
+        let value = match isize::try_from(value) {
+            Ok(value) => value,
+            Err(_) if self.strict => {
+                return Err(DecodeError::ValueOutOfRange("isize".to_string(), offset))
+            }
+            Err(_) => value.low_isize(),
+        };
         let result = DecodeResult {
-            parameter: Parameter::Int(value.as_isize()),
+            parameter: Parameter::Int(value),
             new_offset: offset + 32,
+            data_end: offset + 32,
         };
         Ok(result)
     }
 
     fn decode_bool(&self, data: &Bytes, offset: usize) -> Result<DecodeResult, DecodeError> {
         let slice = peek_32_bytes(data, offset)?;
+        if self.strict && (!is_zero(&slice[..31]) || slice[31] > 1) {
+            return Err(DecodeError::InvalidBool(offset));
+        }
         let value = slice[31] == 1;
         let result = DecodeResult {
             parameter: Parameter::Bool(value),
             new_offset: offset + 32,
+            data_end: offset + 32,
         };
         Ok(result)
     }
 
     fn decode_string(&self, data: &Bytes, offset: usize) -> Result<DecodeResult, DecodeError> {
-        let dynamic_offset = as_usize(&peek_32_bytes(data, offset)?)?;
-        let len = as_usize(&peek_32_bytes(data, dynamic_offset)?)?;
-        let bytes = take_bytes(data, dynamic_offset + 32, len)?;
+        let (bytes, data_end) = self.decode_dynamic_bytes(data, offset)?;
         let result = DecodeResult {
             parameter: Parameter::String(String::from_utf8(bytes)?),
             new_offset: offset + 32,
+            data_end,
         };
         Ok(result)
     }
 
     fn decode_bytes(&self, data: &Bytes, offset: usize) -> Result<DecodeResult, DecodeError> {
-        let dynamic_offset = as_usize(&peek_32_bytes(data, offset)?)?;
-        let len = as_usize(&peek_32_bytes(data, dynamic_offset)?)?;
-        let bytes = take_bytes(data, dynamic_offset + 32, len)?;
+        let (bytes, data_end) = self.decode_dynamic_bytes(data, offset)?;
         let result = DecodeResult {
             parameter: Parameter::Bytes(bytes),
             new_offset: offset + 32,
+            data_end,
         };
         Ok(result)
     }
 
+    fn decode_dynamic_bytes(
+        &self,
+        data: &Bytes,
+        offset: usize,
+    ) -> Result<(Vec<u8>, usize), DecodeError> {
+        let dynamic_offset = self.read_usize(data, offset)?;
+        let len = self.read_usize(data, dynamic_offset)?;
+        let bytes = take_bytes(data, dynamic_offset + 32, len)?;
+        let padding_offset = dynamic_offset + 32 + len;
+        let data_end = dynamic_offset + 32 + padded_len(len);
+        if self.strict && !is_zero(peek(data, padding_offset, data_end - padding_offset)?) {
+            return Err(DecodeError::InvalidPadding(padding_offset));
+        }
+        Ok((bytes, data_end))
+    }
+
+    /// Reads the length or offset word at `offset`. Strict decoding rejects
+    /// words that do not fit in a `usize` or exceed the length of `data`,
+    /// rather than truncating them.
+    fn read_usize(&self, data: &Bytes, offset: usize) -> Result<usize, DecodeError> {
+        let slice = peek_32_bytes(data, offset)?;
+        if !self.strict {
+            return as_usize(&slice);
+        }
+        let value = U256::from_be_bytes(slice);
+        usize::try_from(value)
+            .ok()
+            .filter(|value| *value <= data.len())
+            .ok_or(DecodeError::OffsetOutOfRange(offset))
+    }
+
     fn decode_fixed_bytes(
         &self,
         data: &Bytes,
@@ -131,9 +243,13 @@ impl EthereumDecoder {
         length: usize,
     ) -> Result<DecodeResult, DecodeError> {
         let bytes = take_bytes(data, offset, length)?;
+        if self.strict && (length > 32 || !is_zero(&peek_32_bytes(data, offset)?[length..])) {
+            return Err(DecodeError::InvalidPadding(offset + length));
+        }
         let result = DecodeResult {
             parameter: Parameter::FixedBytes(bytes),
             new_offset: offset + 32,
+            data_end: offset + 32,
         };
         Ok(result)
     }
@@ -142,10 +258,10 @@ impl EthereumDecoder {
         &self,
         data: &Bytes,
         offset: usize,
-        t: &Box<ParamType>,
+        t: &ParamType,
     ) -> Result<DecodeResult, DecodeError> {
-        let len_offset = as_usize(&peek_32_bytes(data, offset)?)?;
-        let len = as_usize(&peek_32_bytes(data, len_offset)?)?;
+        let len_offset = self.read_usize(data, offset)?;
+        let len = self.read_usize(data, len_offset)?;
 
         let tail_offset = len_offset + 32;
         let tail = &data[tail_offset..];
@@ -155,16 +271,19 @@ impl EthereumDecoder {
             .try_reserve_exact(len)
             .map_err(|_| DecodeError::MemoryAllocationError)?;
         let mut new_offset = 0;
+        let mut tail_end = 0;
 
         for _ in 0..len {
             let res = self.decode_parameter(t, &Bytes::copy_from_slice(tail), new_offset)?;
             new_offset = res.new_offset;
+            tail_end = tail_end.max(res.data_end);
             parameters.push(res.parameter);
         }
 
         let result = DecodeResult {
             parameter: Parameter::Array(parameters),
             new_offset: offset + 32,
+            data_end: tail_offset + tail_end,
         };
 
         Ok(result)
@@ -174,20 +293,60 @@ impl EthereumDecoder {
         &self,
         data: &Bytes,
         offset: usize,
-        t: &Vec<ParamType>,
+        t: &[ParamType],
     ) -> Result<DecodeResult, DecodeError> {
+        if t.iter().any(ParamType::is_dynamic) {
+            return self.decode_dynamic_struct(data, offset, t);
+        }
         let len = t.len();
         let mut parameters = Vec::with_capacity(len);
         let mut new_offset = offset;
+        let mut data_end = offset;
         for param in t {
             let res = self.decode_parameter(param, data, new_offset)?;
             new_offset = res.new_offset;
+            data_end = data_end.max(res.data_end);
             parameters.push(res.parameter);
         }
 
         let result = DecodeResult {
             parameter: Parameter::Struct(parameters),
             new_offset,
+            data_end,
+        };
+
+        Ok(result)
+    }
+
+    /// A struct with a dynamic field is stored in the tail like any other
+    /// dynamic value: the head holds an offset to its fields, whose own
+    /// offsets are relative to the start of the struct.
+    fn decode_dynamic_struct(
+        &self,
+        data: &Bytes,
+        offset: usize,
+        t: &[ParamType],
+    ) -> Result<DecodeResult, DecodeError> {
+        let struct_offset = self.read_usize(data, offset)?;
+        if struct_offset > data.len() {
+            return Err(DecodeError::OutOfBounds);
+        }
+        let fields = Bytes::copy_from_slice(&data[struct_offset..]);
+
+        let mut parameters = Vec::with_capacity(t.len());
+        let mut new_offset = 0;
+        let mut fields_end = 0;
+        for param in t {
+            let res = self.decode_parameter(param, &fields, new_offset)?;
+            new_offset = res.new_offset;
+            fields_end = fields_end.max(res.data_end);
+            parameters.push(res.parameter);
+        }
+
+        let result = DecodeResult {
+            parameter: Parameter::Struct(parameters),
+            new_offset: offset + 32,
+            data_end: struct_offset + fields_end,
         };
 
         Ok(result)
@@ -198,10 +357,18 @@ impl Decoder for EthereumDecoder {
     fn decode(&self, data: &Bytes) -> Result<Vec<Parameter>, DecodeError> {
         let mut parameters: Vec<Parameter> = Vec::new();
         let mut offset = 0;
+        let mut data_end = 0;
         for param_type in &self.param_types {
             let result = self.decode_parameter(param_type, data, offset)?;
             parameters.push(result.parameter);
             offset = result.new_offset;
+            data_end = data_end.max(result.data_end);
+        }
+        if self.strict && data_end != data.len() {
+            return Err(DecodeError::TrailingData {
+                expected: data_end,
+                found: data.len(),
+            });
         }
         Ok(parameters)
     }
@@ -214,8 +381,8 @@ impl Decoder for EthereumDecoder {
     ) -> Result<DecodeResult, DecodeError> {
         match param_type {
             ParamType::Address => self.decode_address(data, offset),
-            ParamType::UInt(_) => self.decode_uint(data, offset),
-            ParamType::Int(_) => self.decode_int(data, offset),
+            ParamType::UInt(size) => self.decode_uint(data, offset, *size),
+            ParamType::Int(size) => self.decode_int(data, offset, *size),
             ParamType::Bool => self.decode_bool(data, offset),
             ParamType::String => self.decode_string(data, offset),
             ParamType::Bytes => self.decode_bytes(data, offset),
@@ -262,6 +429,25 @@ fn take_bytes(data: &[u8], offset: usize, len: usize) -> Result<Vec<u8>, DecodeE
     Ok(data[offset..(offset + len)].to_vec())
 }
 
+fn is_zero(slice: &[u8]) -> bool {
+    slice.iter().all(|x| *x == 0)
+}
+
+fn padded_len(len: usize) -> usize {
+    len.div_ceil(32) * 32
+}
+
+/// Number of leading bytes of a 32-byte word that lie outside an integer of `size` bits.
+fn high_bytes(size: usize) -> usize {
+    32 - (size / 8).clamp(1, 32)
+}
+
+fn is_sign_extended(slice: &[u8; 32], size: usize) -> bool {
+    let high = high_bytes(size);
+    let fill = if slice[high] & 0x80 != 0 { 0xff } else { 0x00 };
+    slice[..high].iter().all(|x| *x == fill)
+}
+
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
@@ -445,4 +631,182 @@ mod tests {
             panic!("Type of parameter incorrect");
         }
     }
+
+    #[test]
+    fn test_strict_rejects_dirty_address_padding() {
+        let data = Bytes::from(hex!(
+            "000000000000000000000001910ED056Ee239ae7e25f50F1E99255DC76d72E1C"
+        ));
+        let param_type = ParamType::Address;
+
+        assert!(EthereumDecoder::default()
+            .decode_parameter(&param_type, &data, 0)
+            .is_ok());
+        let result = EthereumDecoder::default()
+            .with_strict(true)
+            .decode_parameter(&param_type, &data, 0);
+        assert!(matches!(result, Err(DecodeError::InvalidPadding(0))));
+    }
+
+    #[test]
+    fn test_strict_rejects_non_canonical_bool() {
+        let data = Bytes::from(hex!(
+            "0000000000000000000000000000000000000000000000000000000000000002"
+        ));
+        let param_type = ParamType::Bool;
+
+        let result = EthereumDecoder::default()
+            .with_strict(true)
+            .decode_parameter(&param_type, &data, 0);
+        assert!(matches!(result, Err(DecodeError::InvalidBool(0))));
+    }
+
+    #[test]
+    fn test_strict_integer_range() {
+        let decoder = EthereumDecoder::default().with_strict(true);
+        let uint = Bytes::from(hex!(
+            "0000000000000000000000000000000000000000000000000000000000000100"
+        ));
+        assert!(matches!(
+            decoder.decode_parameter(&ParamType::UInt(8), &uint, 0),
+            Err(DecodeError::ValueOutOfRange(_, 0))
+        ));
+        assert!(decoder
+            .decode_parameter(&ParamType::UInt(16), &uint, 0)
+            .is_ok());
+
+        let negative = Bytes::from(hex!(
+            "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffd6"
+        ));
+        let result = decoder
+            .decode_parameter(&ParamType::Int(8), &negative, 0)
+            .expect("Decoding failed");
+        assert_eq!(result.parameter, Parameter::Int(-42));
+
+        let not_sign_extended = Bytes::from(hex!(
+            "00000000000000000000000000000000000000000000000000000000000000d6"
+        ));
+        assert!(matches!(
+            decoder.decode_parameter(&ParamType::Int(8), &not_sign_extended, 0),
+            Err(DecodeError::ValueOutOfRange(_, 0))
+        ));
+    }
+
+    #[test]
+    fn test_strict_rejects_dirty_fixed_bytes_padding() {
+        let data = Bytes::from(hex!(
+            "deadbeef00000000000000000000000000000000000000000000000000000001"
+        ));
+        let param_type = ParamType::FixedBytes(4);
+
+        let result = EthereumDecoder::default()
+            .with_strict(true)
+            .decode_parameter(&param_type, &data, 0);
+        assert!(matches!(result, Err(DecodeError::InvalidPadding(4))));
+    }
+
+    #[test]
+    fn test_strict_rejects_dirty_string_padding() {
+        let data = Bytes::from(hex!(
+            "0000000000000000000000000000000000000000000000000000000000000020\
+            000000000000000000000000000000000000000000000000000000000000000d\
+            48656c6c6f2c20576f726c6421000000000000000000000000000000000000ff"
+        ));
+
+        let result = EthereumDecoder::new(vec![ParamType::String])
+            .with_strict(true)
+            .decode(&data);
+        assert!(matches!(result, Err(DecodeError::InvalidPadding(77))));
+    }
+
+    #[test]
+    fn test_strict_rejects_oversized_lengths_and_values() {
+        let decoder = EthereumDecoder::default().with_strict(true);
+        let huge_offset = Bytes::from(hex!(
+            "
+            0000000000000000000000000000000000000001000000000000000000000020
+            0000000000000000000000000000000000000000000000000000000000000000
+            "
+        ));
+        assert!(matches!(
+            decoder.decode_parameter(&ParamType::Bytes, &huge_offset, 0),
+            Err(DecodeError::OffsetOutOfRange(0))
+        ));
+
+        let huge_length = Bytes::from(hex!(
+            "
+            0000000000000000000000000000000000000000000000000000000000000020
+            00000000000000000000000000000000000000000000000000000000ffffffff
+            "
+        ));
+        assert!(matches!(
+            decoder.decode_parameter(
+                &ParamType::Array(Box::new(ParamType::Bool)),
+                &huge_length,
+                0
+            ),
+            Err(DecodeError::OffsetOutOfRange(32))
+        ));
+
+        let huge_uint = Bytes::from(hex!(
+            "0000000000000000000000000000000100000000000000000000000000000000"
+        ));
+        assert!(matches!(
+            decoder.decode_parameter(&ParamType::UInt(256), &huge_uint, 0),
+            Err(DecodeError::ValueOutOfRange(_, 0))
+        ));
+        assert!(EthereumDecoder::default()
+            .decode_parameter(&ParamType::UInt(256), &huge_uint, 0)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_strict_rejects_trailing_data() {
+        let data = Bytes::from(hex!(
+            "0000000000000000000000000000000000000000000000000000000000000020\
+             0000000000000000000000000000000000000000000000000000000000000002\
+             0000000000000000000000000000000000000000000000000000000000000001\
+             0000000000000000000000000000000000000000000000000000000000000002\
+             0000000000000000000000000000000000000000000000000000000000000003"
+        ));
+        let param_types = vec![ParamType::Array(Box::new(ParamType::UInt(256)))];
+
+        assert!(EthereumDecoder::new(param_types.clone())
+            .decode(&data)
+            .is_ok());
+        let result = EthereumDecoder::new(param_types)
+            .with_strict(true)
+            .decode(&data);
+        assert!(matches!(
+            result,
+            Err(DecodeError::TrailingData {
+                expected: 128,
+                found: 160
+            })
+        ));
+    }
+
+    #[test]
+    fn test_strict_accepts_canonical_encoding() {
+        let data = Bytes::from(hex!(
+            "000000000000000000000000000000000000000000000000000000000000002a\
+             0000000000000000000000000000000000000000000000000000000000000060\
+             0000000000000000000000000000000000000000000000000000000000000001\
+             000000000000000000000000000000000000000000000000000000000000000d\
+             48656c6c6f2c20576f726c642100000000000000000000000000000000000000"
+        ));
+        let decoder =
+            EthereumDecoder::new(vec![ParamType::UInt(8), ParamType::String, ParamType::Bool])
+                .with_strict(true);
+
+        let parameters = decoder.decode(&data).expect("Decoding failed");
+        assert_eq!(
+            parameters,
+            vec![
+                Parameter::Uint(42),
+                Parameter::String("Hello, World!".to_string()),
+                Parameter::Bool(true)
+            ]
+        );
+    }
 }
@@ -0,0 +1,644 @@
+use crate::blockchain::configuration::ParamType;
+use crate::blockchain::decoder::Parameter;
+use alloy::hex;
+use alloy::primitives::{keccak256, Address, Bytes, B256, U256};
+use std::str::FromStr;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum EncodeError {
+    #[error("Expected {expected} parameters, found {found}")]
+    ParameterCountMismatch { expected: usize, found: usize },
+    #[error("Parameter {parameter} does not match type {expected}")]
+    TypeMismatch { expected: String, parameter: String },
+    #[error("Value out of range for {0}")]
+    ValueOutOfRange(String),
+    #[error("Invalid length for {expected}: {found}")]
+    InvalidLength { expected: String, found: usize },
+    #[error("Invalid value for {expected}: {value}")]
+    InvalidValue { expected: String, value: String },
+}
+
+pub trait Encoder {
+    fn encode(&self, parameters: &[Parameter]) -> Result<Bytes, EncodeError>;
+    fn encode_parameter(
+        &self,
+        param_type: &ParamType,
+        parameter: &Parameter,
+    ) -> Result<Vec<u8>, EncodeError>;
+    fn encode_topic(
+        &self,
+        param_type: &ParamType,
+        parameter: &Parameter,
+    ) -> Result<B256, EncodeError>;
+}
+
+#[derive(Default)]
+pub struct EthereumEncoder {
+    pub param_types: Vec<ParamType>,
+}
+
+impl EthereumEncoder {
+    pub fn new(param_types: Vec<ParamType>) -> Self {
+        Self { param_types }
+    }
+
+    /// Builds the topic list for an event: the event hash followed by one
+    /// topic per indexed argument, in `param_types` order.
+    pub fn encode_topics(
+        &self,
+        event_hash: B256,
+        parameters: &[Parameter],
+    ) -> Result<Vec<B256>, EncodeError> {
+        check_count(&self.param_types, parameters)?;
+        let mut topics = Vec::with_capacity(parameters.len() + 1);
+        topics.push(event_hash);
+        for (param_type, parameter) in self.param_types.iter().zip(parameters) {
+            topics.push(self.encode_topic(param_type, parameter)?);
+        }
+        Ok(topics)
+    }
+
+    fn encode_sequence(
+        &self,
+        param_types: &[ParamType],
+        parameters: &[Parameter],
+    ) -> Result<Vec<u8>, EncodeError> {
+        check_count(param_types, parameters)?;
+        let head_len: usize = param_types.iter().map(head_size).sum();
+        let mut head = Vec::with_capacity(head_len);
+        let mut tail = Vec::new();
+
+        for (param_type, parameter) in param_types.iter().zip(parameters) {
+            let encoded = self.encode_parameter(param_type, parameter)?;
+            if param_type.is_dynamic() {
+                head.extend_from_slice(&encode_usize(head_len + tail.len()));
+                tail.extend_from_slice(&encoded);
+            } else {
+                head.extend_from_slice(&encoded);
+            }
+        }
+
+        head.extend_from_slice(&tail);
+        Ok(head)
+    }
+
+    fn encode_uint(&self, size: usize, value: usize) -> Result<Vec<u8>, EncodeError> {
+        if size < usize::BITS as usize && value >> size != 0 {
+            return Err(EncodeError::ValueOutOfRange(ParamType::UInt(size).name()));
+        }
+        Ok(encode_usize(value).to_vec())
+    }
+
+    fn encode_int(&self, size: usize, value: isize) -> Result<Vec<u8>, EncodeError> {
+        if size == 0 {
+            return Err(EncodeError::ValueOutOfRange(ParamType::Int(size).name()));
+        }
+        if size < isize::BITS as usize {
+            let bound = 1isize << (size - 1);
+            if value < -bound || value >= bound {
+                return Err(EncodeError::ValueOutOfRange(ParamType::Int(size).name()));
+            }
+        }
+        let fill = if value < 0 { 0xff } else { 0x00 };
+        let mut word = [fill; 32];
+        word[16..].copy_from_slice(&(value as i128).to_be_bytes());
+        Ok(word.to_vec())
+    }
+
+    fn encode_fixed_bytes(&self, size: usize, value: &[u8]) -> Result<Vec<u8>, EncodeError> {
+        if value.len() != size || size > 32 {
+            return Err(EncodeError::InvalidLength {
+                expected: ParamType::FixedBytes(size).name(),
+                found: value.len(),
+            });
+        }
+        Ok(pad_right(value))
+    }
+
+    fn encode_dynamic_bytes(&self, value: &[u8]) -> Vec<u8> {
+        let mut encoded = encode_usize(value.len()).to_vec();
+        encoded.extend_from_slice(&pad_right(value));
+        encoded
+    }
+
+    fn encode_array(
+        &self,
+        param_type: &ParamType,
+        parameters: &[Parameter],
+    ) -> Result<Vec<u8>, EncodeError> {
+        let param_types = vec![param_type.clone(); parameters.len()];
+        let mut encoded = encode_usize(parameters.len()).to_vec();
+        encoded.extend_from_slice(&self.encode_sequence(&param_types, parameters)?);
+        Ok(encoded)
+    }
+
+    /// In-place encoding used when hashing indexed arrays and structs into a
+    /// topic: elements are padded to 32 bytes with no offsets or lengths.
+    fn encode_in_place(
+        &self,
+        param_type: &ParamType,
+        parameter: &Parameter,
+    ) -> Result<Vec<u8>, EncodeError> {
+        match (param_type, parameter) {
+            (ParamType::String, Parameter::String(value)) => Ok(pad_right(value.as_bytes())),
+            (ParamType::Bytes, Parameter::Bytes(value)) => Ok(pad_right(value)),
+            (ParamType::Array(t), Parameter::Array(values)) => {
+                let mut encoded = Vec::new();
+                for value in values {
+                    encoded.extend_from_slice(&self.encode_in_place(t, value)?);
+                }
+                Ok(encoded)
+            }
+            (ParamType::Struct(types), Parameter::Struct(values)) => {
+                check_count(types, values)?;
+                let mut encoded = Vec::new();
+                for (t, value) in types.iter().zip(values) {
+                    encoded.extend_from_slice(&self.encode_in_place(t, value)?);
+                }
+                Ok(encoded)
+            }
+            _ => self.encode_parameter(param_type, parameter),
+        }
+    }
+}
+
+impl Encoder for EthereumEncoder {
+    fn encode(&self, parameters: &[Parameter]) -> Result<Bytes, EncodeError> {
+        self.encode_sequence(&self.param_types, parameters)
+            .map(Bytes::from)
+    }
+
+    fn encode_parameter(
+        &self,
+        param_type: &ParamType,
+        parameter: &Parameter,
+    ) -> Result<Vec<u8>, EncodeError> {
+        match (param_type, parameter) {
+            (ParamType::Address, Parameter::Address(address)) => Ok(address.into_word().to_vec()),
+            (ParamType::UInt(size), Parameter::Uint(value)) => self.encode_uint(*size, *value),
+            (ParamType::Int(size), Parameter::Int(value)) => self.encode_int(*size, *value),
+            (ParamType::Bool, Parameter::Bool(value)) => Ok(encode_usize(*value as usize).to_vec()),
+            (ParamType::String, Parameter::String(value)) => {
+                Ok(self.encode_dynamic_bytes(value.as_bytes()))
+            }
+            (ParamType::Bytes, Parameter::Bytes(value)) => Ok(self.encode_dynamic_bytes(value)),
+            (ParamType::FixedBytes(size), Parameter::FixedBytes(value)) => {
+                self.encode_fixed_bytes(*size, value)
+            }
+            (ParamType::Array(t), Parameter::Array(values)) => self.encode_array(t, values),
+            (ParamType::Struct(types), Parameter::Struct(values)) => {
+                self.encode_sequence(types, values)
+            }
+            _ => Err(EncodeError::TypeMismatch {
+                expected: param_type.name(),
+                parameter: format!("{:?}", parameter),
+            }),
+        }
+    }
+
+    fn encode_topic(
+        &self,
+        param_type: &ParamType,
+        parameter: &Parameter,
+    ) -> Result<B256, EncodeError> {
+        match (param_type, parameter) {
+            (ParamType::String, Parameter::String(value)) => Ok(keccak256(value.as_bytes())),
+            (ParamType::Bytes, Parameter::Bytes(value)) => Ok(keccak256(value)),
+            (ParamType::Array(_), _) | (ParamType::Struct(_), _) => {
+                Ok(keccak256(self.encode_in_place(param_type, parameter)?))
+            }
+            _ => Ok(B256::from_slice(
+                &self.encode_parameter(param_type, parameter)?,
+            )),
+        }
+    }
+}
+
+/// Converts a human-entered value into a [`Parameter`] of `param_type`.
+///
+/// Integers are decimal or `0x`-prefixed hex, booleans are `true` or
+/// `false`, addresses and byte strings are `0x`-prefixed hex. Arrays are
+/// written as `[a, b]` and structs as `(a, b)`; strings inside them may be
+/// double-quoted to keep commas and brackets.
+pub fn parse_parameter(param_type: &ParamType, value: &str) -> Result<Parameter, EncodeError> {
+    let value = value.trim();
+    let invalid = || EncodeError::InvalidValue {
+        expected: param_type.name(),
+        value: value.to_string(),
+    };
+    match param_type {
+        ParamType::Address => Address::from_str(value)
+            .map(Parameter::Address)
+            .map_err(|_| invalid()),
+        ParamType::UInt(_) => match value.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16),
+            None => value.parse(),
+        }
+        .map(Parameter::Uint)
+        .map_err(|_| invalid()),
+        ParamType::Int(_) => match value.strip_prefix("0x") {
+            Some(hex) => isize::from_str_radix(hex, 16),
+            None => value.parse(),
+        }
+        .map(Parameter::Int)
+        .map_err(|_| invalid()),
+        ParamType::Bool => value.parse().map(Parameter::Bool).map_err(|_| invalid()),
+        ParamType::String => Ok(Parameter::String(unquote(value).to_string())),
+        ParamType::Bytes => hex::decode(value)
+            .map(Parameter::Bytes)
+            .map_err(|_| invalid()),
+        ParamType::FixedBytes(_) => hex::decode(value)
+            .map(Parameter::FixedBytes)
+            .map_err(|_| invalid()),
+        ParamType::Array(t) => {
+            let inner = value
+                .strip_prefix('[')
+                .and_then(|v| v.strip_suffix(']'))
+                .ok_or_else(invalid)?;
+            split_list(inner)
+                .into_iter()
+                .map(|item| parse_parameter(t, item))
+                .collect::<Result<_, _>>()
+                .map(Parameter::Array)
+        }
+        ParamType::Struct(types) => {
+            let inner = value
+                .strip_prefix('(')
+                .and_then(|v| v.strip_suffix(')'))
+                .ok_or_else(invalid)?;
+            let items = split_list(inner);
+            if items.len() != types.len() {
+                return Err(invalid());
+            }
+            types
+                .iter()
+                .zip(items)
+                .map(|(t, item)| parse_parameter(t, item))
+                .collect::<Result<_, _>>()
+                .map(Parameter::Struct)
+        }
+    }
+}
+
+/// Splits the items of an array or struct literal on the commas that are
+/// not nested in brackets, parentheses or quotes.
+fn split_list(value: &str) -> Vec<&str> {
+    if value.trim().is_empty() {
+        return vec![];
+    }
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    let mut quoted = false;
+    let mut start = 0;
+    for (i, c) in value.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '[' | '(' if !quoted => depth += 1,
+            ']' | ')' if !quoted => depth = depth.saturating_sub(1),
+            ',' if !quoted && depth == 0 => {
+                items.push(&value[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&value[start..]);
+    items
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+fn check_count(param_types: &[ParamType], parameters: &[Parameter]) -> Result<(), EncodeError> {
+    if param_types.len() != parameters.len() {
+        return Err(EncodeError::ParameterCountMismatch {
+            expected: param_types.len(),
+            found: parameters.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Size of a parameter in the head of a sequence: a single offset word for
+/// dynamic types, the full inline encoding otherwise.
+fn head_size(param_type: &ParamType) -> usize {
+    match param_type {
+        ParamType::Struct(fields) if !param_type.is_dynamic() => fields.iter().map(head_size).sum(),
+        _ => 32,
+    }
+}
+
+fn encode_usize(value: usize) -> [u8; 32] {
+    U256::from(value).to_be_bytes::<32>()
+}
+
+fn pad_right(value: &[u8]) -> Vec<u8> {
+    let mut padded = value.to_vec();
+    padded.resize(value.len().div_ceil(32) * 32, 0);
+    padded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::blockchain::configuration::EventFilter;
+    use crate::blockchain::decoder::{Decoder, EthereumDecoder};
+    use alloy::primitives::Address;
+    use hex_literal::hex;
+
+    #[test]
+    fn test_encode_static_parameters() {
+        let encoder = EthereumEncoder::new(vec![
+            ParamType::Address,
+            ParamType::UInt(256),
+            ParamType::Int(256),
+            ParamType::Bool,
+            ParamType::FixedBytes(4),
+        ]);
+        let parameters = vec![
+            Parameter::Address(
+                Address::from_str("0x910ED056Ee239ae7e25f50F1E99255DC76d72E1C").unwrap(),
+            ),
+            Parameter::Uint(42),
+            Parameter::Int(-42),
+            Parameter::Bool(true),
+            Parameter::FixedBytes(vec![0xde, 0xad, 0xbe, 0xef]),
+        ];
+
+        let encoded = encoder.encode(&parameters).expect("Encoding failed");
+        assert_eq!(
+            encoded,
+            Bytes::from(hex!(
+                "000000000000000000000000910ED056Ee239ae7e25f50F1E99255DC76d72E1C\
+                 000000000000000000000000000000000000000000000000000000000000002a\
+                 ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffd6\
+                 0000000000000000000000000000000000000000000000000000000000000001\
+                 deadbeef00000000000000000000000000000000000000000000000000000000"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_encode_dynamic_parameters() {
+        let encoder = EthereumEncoder::new(vec![
+            ParamType::UInt(256),
+            ParamType::String,
+            ParamType::Array(Box::new(ParamType::UInt(256))),
+        ]);
+        let parameters = vec![
+            Parameter::Uint(42),
+            Parameter::String("Hello, World!".to_string()),
+            Parameter::Array(vec![Parameter::Uint(1), Parameter::Uint(2)]),
+        ];
+
+        let encoded = encoder.encode(&parameters).expect("Encoding failed");
+        assert_eq!(
+            encoded,
+            Bytes::from(hex!(
+                "000000000000000000000000000000000000000000000000000000000000002a\
+                 0000000000000000000000000000000000000000000000000000000000000060\
+                 00000000000000000000000000000000000000000000000000000000000000a0\
+                 000000000000000000000000000000000000000000000000000000000000000d\
+                 48656c6c6f2c20576f726c642100000000000000000000000000000000000000\
+                 0000000000000000000000000000000000000000000000000000000000000002\
+                 0000000000000000000000000000000000000000000000000000000000000001\
+                 0000000000000000000000000000000000000000000000000000000000000002"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_encode_array_of_strings() {
+        let encoder = EthereumEncoder::new(vec![ParamType::Array(Box::new(ParamType::String))]);
+        let parameters = vec![Parameter::Array(vec![
+            Parameter::String("a".to_string()),
+            Parameter::String("b".to_string()),
+        ])];
+
+        let encoded = encoder.encode(&parameters).expect("Encoding failed");
+        assert_eq!(
+            encoded,
+            Bytes::from(hex!(
+                "0000000000000000000000000000000000000000000000000000000000000020\
+                 0000000000000000000000000000000000000000000000000000000000000002\
+                 0000000000000000000000000000000000000000000000000000000000000040\
+                 0000000000000000000000000000000000000000000000000000000000000080\
+                 0000000000000000000000000000000000000000000000000000000000000001\
+                 6100000000000000000000000000000000000000000000000000000000000000\
+                 0000000000000000000000000000000000000000000000000000000000000001\
+                 6200000000000000000000000000000000000000000000000000000000000000"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_encode_rejects_mismatches() {
+        let encoder = EthereumEncoder::new(vec![ParamType::UInt(8)]);
+        assert!(matches!(
+            encoder.encode(&[Parameter::Uint(256)]),
+            Err(EncodeError::ValueOutOfRange(_))
+        ));
+        assert!(matches!(
+            encoder.encode(&[Parameter::Bool(true)]),
+            Err(EncodeError::TypeMismatch { .. })
+        ));
+        assert!(matches!(
+            encoder.encode(&[]),
+            Err(EncodeError::ParameterCountMismatch {
+                expected: 1,
+                found: 0
+            })
+        ));
+    }
+
+    #[test]
+    fn test_encode_rejects_zero_sized_int() {
+        let encoder = EthereumEncoder::new(vec![ParamType::Int(0)]);
+        assert!(matches!(
+            encoder.encode(&[Parameter::Int(0)]),
+            Err(EncodeError::ValueOutOfRange(_))
+        ));
+    }
+
+    #[test]
+    fn test_encode_topics() {
+        let event_filter = EventFilter::new("Transfer(address,address,uint256)".to_string())
+            .expect("Invalid event signature");
+        let from = Address::from_str("0x910ED056Ee239ae7e25f50F1E99255DC76d72E1C").unwrap();
+        let encoder = EthereumEncoder::new(vec![ParamType::Address, ParamType::String]);
+
+        let topics = encoder
+            .encode_topics(
+                event_filter.hash,
+                &[
+                    Parameter::Address(from),
+                    Parameter::String("Hello".to_string()),
+                ],
+            )
+            .expect("Encoding failed");
+        assert_eq!(topics.len(), 3);
+        assert_eq!(topics[0], event_filter.hash);
+        assert_eq!(topics[1], from.into_word());
+        assert_eq!(topics[2], keccak256("Hello"));
+    }
+
+    #[test]
+    fn test_round_trip_with_decoder() {
+        let param_types = vec![
+            ParamType::Address,
+            ParamType::UInt(64),
+            ParamType::Int(32),
+            ParamType::Bool,
+            ParamType::String,
+            ParamType::Bytes,
+            ParamType::FixedBytes(32),
+            ParamType::Array(Box::new(ParamType::Int(256))),
+            ParamType::Array(Box::new(ParamType::Bytes)),
+            ParamType::Struct(vec![ParamType::UInt(256), ParamType::Bool]),
+        ];
+        let cases = [
+            (0, 0, false, ""),
+            (1, -1, true, "x"),
+            (
+                usize::MAX >> 1,
+                i32::MIN as isize,
+                true,
+                "a string longer than thirty-two bytes",
+            ),
+        ];
+
+        for (uint, int, flag, text) in cases {
+            let parameters = vec![
+                Parameter::Address(Address::repeat_byte(uint as u8)),
+                Parameter::Uint(uint & u64::MAX as usize),
+                Parameter::Int(int),
+                Parameter::Bool(flag),
+                Parameter::String(text.to_string()),
+                Parameter::Bytes(text.as_bytes().to_vec()),
+                Parameter::FixedBytes(vec![uint as u8; 32]),
+                Parameter::Array(vec![Parameter::Int(int), Parameter::Int(-int)]),
+                Parameter::Array(vec![
+                    Parameter::Bytes(text.as_bytes().to_vec()),
+                    Parameter::Bytes(vec![]),
+                ]),
+                Parameter::Struct(vec![Parameter::Uint(uint), Parameter::Bool(!flag)]),
+            ];
+
+            let encoded = EthereumEncoder::new(param_types.clone())
+                .encode(&parameters)
+                .expect("Encoding failed");
+            let decoded = EthereumDecoder::new(param_types.clone())
+                .with_strict(true)
+                .decode(&encoded)
+                .expect("Decoding failed");
+            assert_eq!(decoded, parameters);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_dynamic_struct() {
+        let param_types = vec![
+            ParamType::Bool,
+            ParamType::Struct(vec![
+                ParamType::UInt(256),
+                ParamType::String,
+                ParamType::Struct(vec![ParamType::Bytes, ParamType::Address]),
+            ]),
+            ParamType::Array(Box::new(ParamType::Struct(vec![
+                ParamType::String,
+                ParamType::UInt(8),
+            ]))),
+        ];
+        let parameters = vec![
+            Parameter::Bool(true),
+            Parameter::Struct(vec![
+                Parameter::Uint(42),
+                Parameter::String("a string longer than thirty-two bytes".to_string()),
+                Parameter::Struct(vec![
+                    Parameter::Bytes(vec![0xde, 0xad]),
+                    Parameter::Address(Address::repeat_byte(0x11)),
+                ]),
+            ]),
+            Parameter::Array(vec![
+                Parameter::Struct(vec![Parameter::String("a".to_string()), Parameter::Uint(1)]),
+                Parameter::Struct(vec![Parameter::String("b".to_string()), Parameter::Uint(2)]),
+            ]),
+        ];
+
+        let encoded = EthereumEncoder::new(param_types.clone())
+            .encode(&parameters)
+            .expect("Encoding failed");
+        assert_eq!(
+            encoded[32..64],
+            hex!("0000000000000000000000000000000000000000000000000000000000000060")
+        );
+        let decoded = EthereumDecoder::new(param_types)
+            .with_strict(true)
+            .decode(&encoded)
+            .expect("Decoding failed");
+        assert_eq!(decoded, parameters);
+    }
+
+    #[test]
+    fn test_parse_parameter() {
+        let address = "0x910ED056Ee239ae7e25f50F1E99255DC76d72E1C";
+        assert_eq!(
+            parse_parameter(&ParamType::Address, address).unwrap(),
+            Parameter::Address(Address::from_str(address).unwrap())
+        );
+        assert_eq!(
+            parse_parameter(&ParamType::UInt(256), "0x2a").unwrap(),
+            Parameter::Uint(42)
+        );
+        assert_eq!(
+            parse_parameter(&ParamType::Int(256), "-42").unwrap(),
+            Parameter::Int(-42)
+        );
+        assert_eq!(
+            parse_parameter(&ParamType::FixedBytes(4), "0xdeadbeef").unwrap(),
+            Parameter::FixedBytes(vec![0xde, 0xad, 0xbe, 0xef])
+        );
+        assert_eq!(
+            parse_parameter(
+                &ParamType::Array(Box::new(ParamType::Struct(vec![
+                    ParamType::String,
+                    ParamType::Bool
+                ]))),
+                r#"[("a, b", true), ("c", false)]"#
+            )
+            .unwrap(),
+            Parameter::Array(vec![
+                Parameter::Struct(vec![
+                    Parameter::String("a, b".to_string()),
+                    Parameter::Bool(true)
+                ]),
+                Parameter::Struct(vec![
+                    Parameter::String("c".to_string()),
+                    Parameter::Bool(false)
+                ]),
+            ])
+        );
+        assert_eq!(
+            parse_parameter(&ParamType::Array(Box::new(ParamType::UInt(8))), "[]").unwrap(),
+            Parameter::Array(vec![])
+        );
+        assert!(matches!(
+            parse_parameter(&ParamType::Bool, "yes"),
+            Err(EncodeError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            parse_parameter(
+                &ParamType::Struct(vec![ParamType::Bool, ParamType::Bool]),
+                "(true)"
+            ),
+            Err(EncodeError::InvalidValue { .. })
+        ));
+    }
+}
@@ -0,0 +1,45 @@
+use crate::blockchain::configuration::EventFilter;
+use crate::blockchain::decoder::Parameter;
+use alloy::primitives::{Address, Bytes, LogData, B256};
+
+/// A subscribed event and everything known about where it was emitted, as
+/// delivered to handlers and sinks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedEvent {
+    pub chain_id: u64,
+    pub block_number: u64,
+    pub block_hash: B256,
+    /// Seconds since the Unix epoch.
+    pub block_timestamp: u64,
+    pub transaction_hash: B256,
+    pub transaction_index: Option<u64>,
+    /// Sender of the transaction.
+    pub from: Address,
+    /// Recipient of the transaction, the subscribed contract.
+    pub to: Address,
+    pub gas_used: u64,
+    /// Contract that emitted the event.
+    pub address: Address,
+    pub log_index: Option<u64>,
+    /// Set when the log was removed by a reorganization.
+    pub removed: bool,
+    pub event_name: String,
+    pub signature: String,
+    pub topics: Vec<B256>,
+    pub data: Bytes,
+    /// Non-indexed parameters decoded with the types of the signature.
+    pub parameters: Vec<Parameter>,
+}
+
+impl DecodedEvent {
+    /// Whether the first topic of the log is the signature hash of
+    /// `event_filter`.
+    pub fn matches(&self, event_filter: &EventFilter) -> bool {
+        self.topics.first() == Some(&event_filter.hash)
+    }
+
+    /// The raw topics and data of the log.
+    pub fn log_data(&self) -> LogData {
+        LogData::new_unchecked(self.topics.clone(), self.data.clone())
+    }
+}
@@ -0,0 +1,91 @@
+use alloy::rpc::json_rpc::{RequestPacket, ResponsePacket};
+use alloy::transports::{
+    utils::guess_local_url, RpcError, TransportError, TransportErrorKind, TransportFut,
+};
+use reqwest::{header::RETRY_AFTER, StatusCode, Url};
+use std::fmt;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::Service;
+
+/// Returned for HTTP 429 responses, with the delay requested by the
+/// `Retry-After` header when it gives a number of seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimited {
+    pub retry_after: Option<Duration>,
+    pub body: String,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Matches the message alloy looks for when classifying retryable errors.
+        write!(f, "429 Too Many Requests: {}", self.body)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+impl RateLimited {
+    /// The rate limit error carried by `err`, if any.
+    pub fn from_error(err: &TransportError) -> Option<&RateLimited> {
+        match err {
+            RpcError::Transport(TransportErrorKind::Custom(err)) => err.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+/// JSON-RPC over HTTP through `reqwest`, like alloy's `Http` transport but
+/// keeping the `Retry-After` header of rate limited responses.
+#[derive(Debug, Clone)]
+pub struct HttpTransport {
+    client: reqwest::Client,
+    url: Url,
+}
+
+impl HttpTransport {
+    pub fn new(client: reqwest::Client, url: Url) -> Self {
+        Self { client, url }
+    }
+
+    pub fn guess_local(&self) -> bool {
+        guess_local_url(&self.url)
+    }
+}
+
+impl Service<RequestPacket> for HttpTransport {
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let request = self.client.post(self.url.clone()).json(&request);
+        Box::pin(async move {
+            let response = request.send().await.map_err(TransportErrorKind::custom)?;
+            let status = response.status();
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs);
+            let body = response.bytes().await.map_err(TransportErrorKind::custom)?;
+            let text = || String::from_utf8_lossy(&body).into_owned();
+
+            if status == StatusCode::TOO_MANY_REQUESTS {
+                return Err(TransportErrorKind::custom(RateLimited {
+                    retry_after,
+                    body: text(),
+                }));
+            }
+            if status != StatusCode::OK {
+                return Err(TransportErrorKind::http_error(status.as_u16(), text()));
+            }
+            serde_json::from_slice(&body).map_err(|err| TransportError::deser_err(err, text()))
+        })
+    }
+}
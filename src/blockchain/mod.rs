@@ -1,5 +1,15 @@
+pub mod batch;
 pub mod block;
 pub mod configuration;
 pub mod connection;
 pub mod decoder;
+pub mod encoder;
+pub mod event;
+pub mod http;
+pub mod parameters;
+pub mod pipeline;
+pub mod rate_limit;
+pub mod retry;
+pub mod revert;
+pub mod rpc_metrics;
 pub mod transaction;
@@ -0,0 +1,283 @@
+use crate::blockchain::decoder::Parameter;
+use alloy::primitives::{Address, Bytes, FixedBytes, I256, U256};
+use thiserror::Error;
+
+pub use blockstream_derive::FromParameters;
+
+#[derive(Debug, Clone, PartialEq, Error)]
+pub enum ParameterError {
+    #[error("Expected {expected}, found {found}")]
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    #[error("Value {value} out of range for {expected}")]
+    OutOfRange {
+        expected: &'static str,
+        value: String,
+    },
+    #[error("Expected {expected} bytes, found {found}")]
+    InvalidLength { expected: usize, found: usize },
+    #[error("Missing parameter at position {0}")]
+    MissingPosition(usize),
+    #[error("Missing parameter named {0}")]
+    MissingName(String),
+    #[error("{path}: {source}")]
+    Field {
+        path: String,
+        source: Box<ParameterError>,
+    },
+}
+
+impl ParameterError {
+    pub fn mismatch(expected: &'static str, found: &Parameter) -> Self {
+        ParameterError::TypeMismatch {
+            expected,
+            found: found.type_name(),
+        }
+    }
+
+    /// Prefixes the path of the failing value with a field name.
+    pub fn at_field(self, name: &str) -> Self {
+        self.at(name.to_string())
+    }
+
+    /// Prefixes the path of the failing value with an array index.
+    pub fn at_index(self, index: usize) -> Self {
+        self.at(format!("[{}]", index))
+    }
+
+    fn at(self, segment: String) -> Self {
+        match self {
+            ParameterError::Field { path, source } => {
+                let separator = if path.starts_with('[') { "" } else { "." };
+                ParameterError::Field {
+                    path: format!("{}{}{}", segment, separator, path),
+                    source,
+                }
+            }
+            other => ParameterError::Field {
+                path: segment,
+                source: Box::new(other),
+            },
+        }
+    }
+}
+
+/// Converts a single decoded parameter into a Rust value.
+pub trait FromParameter: Sized {
+    fn from_parameter(parameter: &Parameter) -> Result<Self, ParameterError>;
+}
+
+/// Converts the parameters returned by a [`Decoder`] into a struct, usually
+/// through `#[derive(FromParameters)]`.
+///
+/// [`Decoder`]: crate::blockchain::decoder::Decoder
+pub trait FromParameters: Sized {
+    /// Reads the fields in declaration order.
+    fn from_parameters(parameters: &[Parameter]) -> Result<Self, ParameterError>;
+
+    /// Reads each field from the parameter at the same position as its name
+    /// in `names`.
+    fn from_named_parameters(
+        names: &[&str],
+        parameters: &[Parameter],
+    ) -> Result<Self, ParameterError>;
+}
+
+/// Converts the parameter at `index`, reporting errors under `field`.
+pub fn positional<T: FromParameter>(
+    parameters: &[Parameter],
+    index: usize,
+    field: &str,
+) -> Result<T, ParameterError> {
+    parameters
+        .get(index)
+        .ok_or(ParameterError::MissingPosition(index))
+        .and_then(T::from_parameter)
+        .map_err(|err| err.at_field(field))
+}
+
+/// Converts the parameter called `field` in `names`.
+pub fn named<T: FromParameter>(
+    names: &[&str],
+    parameters: &[Parameter],
+    field: &str,
+) -> Result<T, ParameterError> {
+    let index = names
+        .iter()
+        .position(|name| *name == field)
+        .ok_or_else(|| ParameterError::MissingName(field.to_string()))?;
+    positional(parameters, index, field)
+}
+
+impl FromParameter for Address {
+    fn from_parameter(parameter: &Parameter) -> Result<Self, ParameterError> {
+        match parameter {
+            Parameter::Address(address) => Ok(*address),
+            other => Err(ParameterError::mismatch("address", other)),
+        }
+    }
+}
+
+impl FromParameter for U256 {
+    fn from_parameter(parameter: &Parameter) -> Result<Self, ParameterError> {
+        match parameter {
+            Parameter::Uint(value) => Ok(U256::from(*value)),
+            other => Err(ParameterError::mismatch("uint", other)),
+        }
+    }
+}
+
+impl FromParameter for I256 {
+    fn from_parameter(parameter: &Parameter) -> Result<Self, ParameterError> {
+        match parameter {
+            Parameter::Int(value) => {
+                I256::try_from(*value).map_err(|_| ParameterError::OutOfRange {
+                    expected: "int256",
+                    value: value.to_string(),
+                })
+            }
+            other => Err(ParameterError::mismatch("int", other)),
+        }
+    }
+}
+
+macro_rules! from_uint {
+    ($($ty:ty),*) => {$(
+        impl FromParameter for $ty {
+            fn from_parameter(parameter: &Parameter) -> Result<Self, ParameterError> {
+                match parameter {
+                    Parameter::Uint(value) => {
+                        <$ty>::try_from(*value).map_err(|_| ParameterError::OutOfRange {
+                            expected: stringify!($ty),
+                            value: value.to_string(),
+                        })
+                    }
+                    other => Err(ParameterError::mismatch("uint", other)),
+                }
+            }
+        }
+    )*};
+}
+
+macro_rules! from_int {
+    ($($ty:ty),*) => {$(
+        impl FromParameter for $ty {
+            fn from_parameter(parameter: &Parameter) -> Result<Self, ParameterError> {
+                match parameter {
+                    Parameter::Int(value) => {
+                        <$ty>::try_from(*value).map_err(|_| ParameterError::OutOfRange {
+                            expected: stringify!($ty),
+                            value: value.to_string(),
+                        })
+                    }
+                    other => Err(ParameterError::mismatch("int", other)),
+                }
+            }
+        }
+    )*};
+}
+
+from_uint!(u8, u16, u32, u64, u128, usize);
+from_int!(i8, i16, i32, i64, i128, isize);
+
+impl FromParameter for bool {
+    fn from_parameter(parameter: &Parameter) -> Result<Self, ParameterError> {
+        match parameter {
+            Parameter::Bool(value) => Ok(*value),
+            other => Err(ParameterError::mismatch("bool", other)),
+        }
+    }
+}
+
+impl FromParameter for String {
+    fn from_parameter(parameter: &Parameter) -> Result<Self, ParameterError> {
+        match parameter {
+            Parameter::String(value) => Ok(value.clone()),
+            other => Err(ParameterError::mismatch("string", other)),
+        }
+    }
+}
+
+impl FromParameter for Bytes {
+    fn from_parameter(parameter: &Parameter) -> Result<Self, ParameterError> {
+        match parameter {
+            Parameter::Bytes(value) | Parameter::FixedBytes(value) => {
+                Ok(Bytes::copy_from_slice(value))
+            }
+            other => Err(ParameterError::mismatch("bytes", other)),
+        }
+    }
+}
+
+impl<const N: usize> FromParameter for FixedBytes<N> {
+    fn from_parameter(parameter: &Parameter) -> Result<Self, ParameterError> {
+        match parameter {
+            Parameter::FixedBytes(value) if value.len() == N => Ok(FixedBytes::from_slice(value)),
+            Parameter::FixedBytes(value) => Err(ParameterError::InvalidLength {
+                expected: N,
+                found: value.len(),
+            }),
+            other => Err(ParameterError::mismatch("fixed bytes", other)),
+        }
+    }
+}
+
+impl<T: FromParameter> FromParameter for Vec<T> {
+    fn from_parameter(parameter: &Parameter) -> Result<Self, ParameterError> {
+        match parameter {
+            Parameter::Array(values) | Parameter::FixedArray(values) => values
+                .iter()
+                .enumerate()
+                .map(|(index, value)| T::from_parameter(value).map_err(|err| err.at_index(index)))
+                .collect(),
+            other => Err(ParameterError::mismatch("array", other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scalar_conversions() {
+        assert_eq!(
+            U256::from_parameter(&Parameter::Uint(7)).unwrap(),
+            U256::from(7)
+        );
+        assert_eq!(u8::from_parameter(&Parameter::Uint(255)).unwrap(), 255);
+        assert_eq!(
+            u8::from_parameter(&Parameter::Uint(256)),
+            Err(ParameterError::OutOfRange {
+                expected: "u8",
+                value: "256".to_string()
+            })
+        );
+        assert_eq!(
+            I256::from_parameter(&Parameter::Int(-3)).unwrap(),
+            I256::try_from(-3).unwrap()
+        );
+        assert_eq!(
+            FixedBytes::<4>::from_parameter(&Parameter::FixedBytes(vec![1, 2])),
+            Err(ParameterError::InvalidLength {
+                expected: 4,
+                found: 2
+            })
+        );
+    }
+
+    #[test]
+    fn test_errors_carry_the_path() {
+        let parameter = Parameter::Array(vec![Parameter::Uint(1), Parameter::Bool(true)]);
+        let err = Vec::<u64>::from_parameter(&parameter)
+            .unwrap_err()
+            .at_field("amounts")
+            .at_field("transfer");
+        assert_eq!(
+            err.to_string(),
+            "transfer.amounts[1]: Expected uint, found bool"
+        );
+    }
+}
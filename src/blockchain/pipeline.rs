@@ -0,0 +1,234 @@
+use crate::blockchain::block::{BlockEvent, BlockSubscriber, SubscriptionError};
+use crate::blockchain::configuration::{EventFilter, EventSubscription};
+use crate::blockchain::event::DecodedEvent;
+use crate::blockchain::transaction::{
+    EthereumTransactionProcessor, SharedSubscriptions, TransactionError,
+};
+use crate::metrics::{metrics, EVENTS_FAILED};
+use alloy::primitives::Address;
+use alloy::providers::RootProvider;
+use alloy::rpc::types::Block;
+use alloy::sol_types::SolEvent;
+use alloy::transports::BoxTransport;
+use futures_util::StreamExt;
+use std::future::Future;
+use std::pin::{pin, Pin};
+use std::sync::Arc;
+use tracing::{error, info_span, Instrument};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PipelineError {
+    #[error("Subscription error: {0}")]
+    SubscriptionError(#[from] SubscriptionError),
+    #[error("Transaction error: {0}")]
+    TransactionError(#[from] TransactionError),
+}
+
+/// Receives the events of type `E` registered with [`Pipeline::register`].
+/// Any `Fn(E, DecodedEvent) -> impl Future<Output = ()>` closure is a handler.
+#[trait_variant::make(EventHandler: Send)]
+pub trait LocalEventHandler<E: SolEvent> {
+    async fn handle(&self, event: E, envelope: DecodedEvent);
+}
+
+impl<E, F, Fut> EventHandler<E> for F
+where
+    E: SolEvent + Send,
+    F: Fn(E, DecodedEvent) -> Fut + Send + Sync,
+    Fut: Future<Output = ()> + Send,
+{
+    async fn handle(&self, event: E, envelope: DecodedEvent) {
+        self(event, envelope).await
+    }
+}
+
+/// Adapts a synchronous closure registered with [`Pipeline::on`].
+struct SyncHandler<F>(F);
+
+impl<E, F> EventHandler<E> for SyncHandler<F>
+where
+    E: SolEvent + Send,
+    F: Fn(E, DecodedEvent) + Send + Sync,
+{
+    async fn handle(&self, event: E, envelope: DecodedEvent) {
+        (self.0)(event, envelope)
+    }
+}
+
+type HandlerFuture = Pin<Box<dyn Future<Output = Result<(), alloy::sol_types::Error>> + Send>>;
+type ErasedHandler = Arc<dyn Fn(DecodedEvent) -> HandlerFuture + Send + Sync>;
+
+struct Registration {
+    address: Address,
+    event: EventFilter,
+    handler: ErasedHandler,
+}
+
+/// Dispatches the logs of each block to the typed handlers registered for
+/// their contract and event. Receipts are fetched for transactions sent to
+/// a registered contract only.
+pub struct Pipeline {
+    processor: EthereumTransactionProcessor,
+    subscriptions: SharedSubscriptions,
+    registrations: Vec<Registration>,
+}
+
+impl Pipeline {
+    pub fn new(provider: Arc<RootProvider<BoxTransport>>) -> Self {
+        let subscriptions = SharedSubscriptions::default();
+        Self {
+            processor: EthereumTransactionProcessor::with_shared_subscriptions(
+                provider,
+                subscriptions.clone(),
+            ),
+            subscriptions,
+            registrations: Vec::new(),
+        }
+    }
+
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.processor = self.processor.with_chain_id(chain_id);
+        self
+    }
+
+    /// Names the network in the metrics this pipeline records.
+    pub fn with_network(mut self, network: String) -> Self {
+        self.processor = self.processor.with_network(network);
+        self
+    }
+
+    /// Calls `handler` with every `E` event emitted by `address`, decoded by
+    /// alloy, e.g. `pipeline.on::<IERC20::Transfer>(address, |event, envelope| ...)`.
+    pub fn on<E>(
+        &mut self,
+        address: Address,
+        handler: impl Fn(E, DecodedEvent) + Send + Sync + 'static,
+    ) -> &mut Self
+    where
+        E: SolEvent + Send + 'static,
+    {
+        self.register(address, SyncHandler(handler))
+    }
+
+    /// Like [`Pipeline::on`] for asynchronous handlers.
+    pub fn register<E>(
+        &mut self,
+        address: Address,
+        handler: impl EventHandler<E> + Sync + 'static,
+    ) -> &mut Self
+    where
+        E: SolEvent + Send + 'static,
+    {
+        let handler = Arc::new(handler);
+        let handler: ErasedHandler = Arc::new(move |envelope: DecodedEvent| {
+            let handler = handler.clone();
+            Box::pin(async move {
+                let event = E::decode_log_data(&envelope.log_data(), true)?;
+                handler.handle(event, envelope).await;
+                Ok(())
+            })
+        });
+        self.registrations.push(Registration {
+            address,
+            event: event_filter::<E>(),
+            handler,
+        });
+        self.subscriptions.store(self.subscriptions());
+        self
+    }
+
+    /// One subscription per registered contract, with each registered event
+    /// listed once.
+    fn subscriptions(&self) -> Vec<EventSubscription> {
+        let mut subscriptions: Vec<EventSubscription> = Vec::new();
+        for registration in &self.registrations {
+            match subscriptions
+                .iter_mut()
+                .find(|subscription| subscription.contract_address == registration.address)
+            {
+                Some(subscription) => {
+                    if !subscription.events.contains(&registration.event) {
+                        subscription.events.push(registration.event.clone());
+                    }
+                }
+                None => subscriptions.push(EventSubscription::new(
+                    registration.address,
+                    vec![registration.event.clone()],
+                )),
+            }
+        }
+        subscriptions
+    }
+
+    /// Hands the registered events of `block` to their handlers. Events that
+    /// fail to decode are logged and skipped.
+    pub async fn process_block(&self, block: Block) -> Result<(), TransactionError> {
+        let span = info_span!(
+            parent: None,
+            "block",
+            network = self.processor.network(),
+            number = block.header.number,
+            hash = %block.header.hash
+        );
+        let network = self.processor.network();
+        self.processor
+            .process_transactions(
+                &block,
+                |envelope| async move {
+                    for registration in self.registrations.iter().filter(|registration| {
+                        registration.address == envelope.address
+                            && registration.event.signature == envelope.signature
+                            && envelope.matches(&registration.event)
+                    }) {
+                        if let Err(err) = (registration.handler)(envelope.clone())
+                            .instrument(info_span!("handle", event = %envelope.event_name))
+                            .await
+                        {
+                            let subscription = envelope.address.to_string();
+                            metrics().increment(
+                                EVENTS_FAILED,
+                                &[
+                                    ("network", network),
+                                    ("subscription", subscription.as_str()),
+                                    ("event", envelope.event_name.as_str()),
+                                ],
+                            );
+                            error!(
+                                "Error decoding event {} on chain {}: {}",
+                                envelope.event_name, envelope.chain_id, err
+                            );
+                        }
+                    }
+                },
+                |_, _| async {},
+                |_| async {},
+            )
+            .instrument(span)
+            .await
+    }
+
+    /// Processes every new block of `subscriber` until its stream ends.
+    pub async fn run<S: BlockSubscriber>(&self, subscriber: &S) -> Result<(), PipelineError> {
+        let mut events = pin!(subscriber.blocks());
+        while let Some(event) = events.next().await {
+            if let BlockEvent::NewBlock(block) = event? {
+                self.process_block(*block).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Matches `E` by its signature hash. Decoding goes through alloy, so no
+/// parameter types are recorded.
+fn event_filter<E: SolEvent>() -> EventFilter {
+    EventFilter {
+        signature: E::SIGNATURE.to_string(),
+        hash: E::SIGNATURE_HASH,
+        event_name: E::SIGNATURE
+            .split_once('(')
+            .map_or(E::SIGNATURE, |(name, _)| name)
+            .to_string(),
+        data_types: Vec::new(),
+    }
+}
@@ -0,0 +1,338 @@
+use super::http::RateLimited;
+use crate::metrics::{metrics, RPC_RATE_LIMITED};
+use alloy::rpc::json_rpc::{RequestPacket, ResponsePacket};
+use alloy::transports::{RpcError, TransportError, TransportErrorKind, TransportFut};
+use serde::{de, Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::future::poll_fn;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+use tower::{Layer, Service};
+use tracing::warn;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Client-side budget for an endpoint. Each request costs the weight of its
+/// method in `method_costs`, 1 by default, and `requests_per_second` units
+/// are refilled every second up to `burst`. Requests rejected with HTTP 429
+/// are retried up to `max_retries` times.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RateLimitConfig {
+    #[serde(deserialize_with = "deserialize_rate")]
+    pub requests_per_second: f64,
+    #[serde(default = "default_burst")]
+    pub burst: u32,
+    #[serde(default)]
+    pub method_costs: BTreeMap<String, u32>,
+    #[serde(default = "default_max_retries")]
+    pub max_retries: u32,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RateLimitError {
+    #[error("requests_per_second must be a positive number, got {0}")]
+    InvalidRate(f64),
+}
+
+impl RateLimitConfig {
+    pub fn new(requests_per_second: f64) -> Result<Self, RateLimitError> {
+        Ok(Self {
+            requests_per_second: check_rate(requests_per_second)?,
+            burst: default_burst(),
+            method_costs: BTreeMap::new(),
+            max_retries: default_max_retries(),
+        })
+    }
+
+    pub fn with_burst(mut self, burst: u32) -> Self {
+        self.burst = burst;
+        self
+    }
+
+    pub fn with_method_cost(mut self, method: &str, cost: u32) -> Self {
+        self.method_costs.insert(method.to_string(), cost);
+        self
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Units spent by `request`, summed over the calls of a batch.
+    fn cost(&self, request: &RequestPacket) -> u32 {
+        let cost = |method: &str| self.method_costs.get(method).copied().unwrap_or(1);
+        match request {
+            RequestPacket::Single(request) => cost(request.method()),
+            RequestPacket::Batch(requests) => {
+                requests.iter().map(|request| cost(request.method())).sum()
+            }
+        }
+    }
+}
+
+/// Tokens are refilled at `rate` per second, so it must be positive for
+/// requests to ever go out.
+fn check_rate(rate: f64) -> Result<f64, RateLimitError> {
+    if rate > 0.0 {
+        Ok(rate)
+    } else {
+        Err(RateLimitError::InvalidRate(rate))
+    }
+}
+
+fn deserialize_rate<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    check_rate(f64::deserialize(deserializer)?).map_err(de::Error::custom)
+}
+
+fn default_burst() -> u32 {
+    10
+}
+
+fn default_max_retries() -> u32 {
+    5
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    paused_until: Option<Instant>,
+}
+
+/// Tokens shared by every request sent to one endpoint.
+#[derive(Debug)]
+struct TokenBucket {
+    rate: f64,
+    burst: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl TokenBucket {
+    fn new(rate: f64, burst: u32) -> Self {
+        let burst = f64::from(burst.max(1));
+        Self {
+            rate,
+            burst,
+            bucket: Mutex::new(Bucket {
+                tokens: burst,
+                refilled: Instant::now(),
+                paused_until: None,
+            }),
+        }
+    }
+
+    /// Waits until `cost` tokens are available and takes them. Costs above
+    /// the burst wait for a full bucket.
+    async fn acquire(&self, cost: u32) {
+        let cost = f64::from(cost).min(self.burst);
+        loop {
+            let wait = {
+                let mut bucket = self.bucket.lock().unwrap_or_else(|err| err.into_inner());
+                let now = Instant::now();
+                match bucket.paused_until {
+                    Some(until) if until > now => until - now,
+                    _ => {
+                        bucket.paused_until = None;
+                        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+                        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.burst);
+                        bucket.refilled = now;
+                        if bucket.tokens >= cost {
+                            bucket.tokens -= cost;
+                            return;
+                        }
+                        Duration::from_secs_f64((cost - bucket.tokens) / self.rate)
+                    }
+                }
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Stops handing out tokens for `duration`, after the endpoint asked us
+    /// to back off, and resumes with an empty bucket.
+    fn pause(&self, duration: Duration) {
+        let mut bucket = self.bucket.lock().unwrap_or_else(|err| err.into_inner());
+        let until = Instant::now() + duration;
+        bucket.paused_until = Some(
+            bucket
+                .paused_until
+                .map_or(until, |paused| paused.max(until)),
+        );
+        bucket.tokens = 0.0;
+        bucket.refilled = bucket.paused_until.unwrap_or(until);
+    }
+}
+
+/// Spends the budget of [`RateLimitConfig`] before each request, and retries
+/// requests rejected with HTTP 429 after their `Retry-After` delay, or an
+/// exponential backoff when the endpoint gives none.
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer {
+    config: Arc<RateLimitConfig>,
+    bucket: Arc<TokenBucket>,
+    network: Arc<str>,
+}
+
+impl RateLimitLayer {
+    pub fn new(config: RateLimitConfig) -> Self {
+        let bucket = TokenBucket::new(config.requests_per_second, config.burst);
+        Self {
+            config: Arc::new(config),
+            bucket: Arc::new(bucket),
+            network: "".into(),
+        }
+    }
+
+    /// Labels the rate limiting metrics with the name of the network.
+    pub fn with_network(mut self, network: &str) -> Self {
+        self.network = network.into();
+        self
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimitService {
+            inner,
+            config: self.config.clone(),
+            bucket: self.bucket.clone(),
+            network: self.network.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimitService<S> {
+    inner: S,
+    config: Arc<RateLimitConfig>,
+    bucket: Arc<TokenBucket>,
+    network: Arc<str>,
+}
+
+impl<S> Service<RequestPacket> for RateLimitService<S>
+where
+    S: Service<RequestPacket, Response = ResponsePacket, Error = TransportError>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let mut inner = self.inner.clone();
+        let config = self.config.clone();
+        let bucket = self.bucket.clone();
+        let network = self.network.clone();
+        Box::pin(async move {
+            let cost = config.cost(&request);
+            let mut backoff = INITIAL_BACKOFF;
+            let mut attempt = 0;
+            loop {
+                bucket.acquire(cost).await;
+                poll_fn(|cx| inner.poll_ready(cx)).await?;
+                let err = match inner.call(request.clone()).await {
+                    Err(err) => err,
+                    response => return response,
+                };
+                let Some(retry_after) = rate_limit_delay(&err) else {
+                    return Err(err);
+                };
+                for method in methods(&request) {
+                    metrics().increment(
+                        RPC_RATE_LIMITED,
+                        &[("network", &*network), ("method", method)],
+                    );
+                }
+                if attempt >= config.max_retries {
+                    return Err(err);
+                }
+                let delay = retry_after.unwrap_or(backoff);
+                warn!(
+                    "Rate limited by the node, retrying {} in {:?} (attempt {} of {})",
+                    methods(&request).collect::<Vec<_>>().join(", "),
+                    delay,
+                    attempt + 1,
+                    config.max_retries
+                );
+                bucket.pause(delay);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+                attempt += 1;
+            }
+        })
+    }
+}
+
+/// `Some` for errors asking to slow down, with the delay requested by the
+/// node if it gave one.
+fn rate_limit_delay(err: &TransportError) -> Option<Option<Duration>> {
+    if let Some(rate_limited) = RateLimited::from_error(err) {
+        return Some(rate_limited.retry_after);
+    }
+    match err {
+        RpcError::Transport(TransportErrorKind::HttpError(http_err))
+            if http_err.is_rate_limit_err() =>
+        {
+            Some(None)
+        }
+        _ => None,
+    }
+}
+
+fn methods(request: &RequestPacket) -> impl Iterator<Item = &str> {
+    let requests = match request {
+        RequestPacket::Single(request) => std::slice::from_ref(request),
+        RequestPacket::Batch(requests) => requests.as_slice(),
+    };
+    requests.iter().map(|request| request.method())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn test_token_bucket_spends_burst_then_refills() {
+        let bucket = TokenBucket::new(2.0, 4);
+        let started = Instant::now();
+        bucket.acquire(3).await;
+        bucket.acquire(1).await;
+        assert_eq!(started.elapsed(), Duration::ZERO);
+
+        bucket.acquire(2).await;
+        assert_eq!(started.elapsed(), Duration::from_secs(1));
+
+        bucket.pause(Duration::from_secs(5));
+        bucket.acquire(1).await;
+        assert_eq!(started.elapsed(), Duration::from_millis(6500));
+    }
+
+    #[test]
+    fn test_rejects_rates_that_never_refill() {
+        assert!(RateLimitConfig::new(0.5).is_ok());
+        for rate in [0.0, -1.0, f64::NAN] {
+            assert!(matches!(
+                RateLimitConfig::new(rate),
+                Err(RateLimitError::InvalidRate(_))
+            ));
+        }
+        let err = serde_yaml::from_str::<RateLimitConfig>("requests_per_second: 0").unwrap_err();
+        assert!(
+            err.to_string().contains("must be a positive number"),
+            "{}",
+            err
+        );
+    }
+}
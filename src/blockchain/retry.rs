@@ -0,0 +1,219 @@
+use crate::metrics::{metrics, RETRIES};
+use alloy::transports::{RpcError, TransportError, TransportErrorKind};
+use serde::Deserialize;
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+/// Errors that may go away when the same call is made again.
+pub trait Transient {
+    fn is_transient(&self) -> bool;
+}
+
+impl Transient for TransportError {
+    /// Connection failures, server errors, rate limits and empty responses
+    /// from a node that is catching up are transient. Errors the node
+    /// answered with, such as reverts or unsupported methods, are not.
+    fn is_transient(&self) -> bool {
+        match self {
+            RpcError::Transport(TransportErrorKind::HttpError(err)) => {
+                err.status >= 500 || err.is_rate_limit_err()
+            }
+            RpcError::Transport(
+                TransportErrorKind::Custom(_)
+                | TransportErrorKind::BackendGone
+                | TransportErrorKind::PubsubUnavailable
+                | TransportErrorKind::MissingBatchResponse(_),
+            ) => true,
+            RpcError::ErrorResp(payload) => payload.is_retry_err() || payload.code == -32603,
+            RpcError::NullResp => true,
+            _ => false,
+        }
+    }
+}
+
+/// What becomes of a block that still fails once its calls are out of
+/// retries.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OnExhaustion {
+    /// Stop processing the network.
+    Halt,
+    /// Log an error and move on to the next block.
+    #[default]
+    Skip,
+    /// Keep the block aside and try it again with each following block.
+    Park,
+}
+
+/// Transient failures are retried up to `max_attempts` calls in total,
+/// waiting `initial_backoff_ms` before the first retry and twice as long
+/// before each next one, up to `max_backoff_ms`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RetryPolicy {
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    #[serde(default)]
+    pub on_exhaustion: OnExhaustion,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: default_max_attempts(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_ms: default_max_backoff_ms(),
+            on_exhaustion: OnExhaustion::default(),
+        }
+    }
+}
+
+fn default_max_attempts() -> u32 {
+    5
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    500
+}
+
+fn default_max_backoff_ms() -> u64 {
+    10_000
+}
+
+impl RetryPolicy {
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff_ms = initial.as_millis() as u64;
+        self.max_backoff_ms = max.as_millis() as u64;
+        self
+    }
+
+    pub fn with_on_exhaustion(mut self, on_exhaustion: OnExhaustion) -> Self {
+        self.on_exhaustion = on_exhaustion;
+        self
+    }
+
+    /// Delay before the retry following the failed `attempt`, counted from 1.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+        Duration::from_millis(
+            self.initial_backoff_ms
+                .saturating_mul(factor)
+                .min(self.max_backoff_ms),
+        )
+    }
+
+    /// Calls `call` until it succeeds, fails with a permanent error or has
+    /// been made `max_attempts` times, and returns its last result.
+    pub async fn retry<T, E, F, Fut>(&self, operation: &str, mut call: F) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Transient + Display,
+    {
+        let mut attempt = 1;
+        loop {
+            match call().await {
+                Err(err) if err.is_transient() && attempt < self.max_attempts => {
+                    let delay = self.backoff(attempt);
+                    metrics().increment(RETRIES, &[("operation", operation)]);
+                    warn!(
+                        "{} failed, retrying in {:?} (attempt {} of {}): {}",
+                        operation, delay, attempt, self.max_attempts, err
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloy::rpc::json_rpc::ErrorPayload;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tokio::time::Instant;
+
+    fn error_response(code: i64, message: &'static str) -> TransportError {
+        RpcError::ErrorResp(ErrorPayload {
+            code,
+            message: message.into(),
+            data: None,
+        })
+    }
+
+    #[test]
+    fn test_classifies_transport_errors() {
+        assert!(TransportErrorKind::custom_str("connection refused").is_transient());
+        assert!(TransportErrorKind::http_error(503, String::new()).is_transient());
+        assert!(TransportErrorKind::http_error(429, String::new()).is_transient());
+        assert!(!TransportErrorKind::http_error(401, String::new()).is_transient());
+        assert!(TransportError::NullResp.is_transient());
+        assert!(error_response(-32000, "header not found").is_transient());
+        assert!(!error_response(-32601, "method not found").is_transient());
+        assert!(!error_response(3, "execution reverted").is_transient());
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_maximum() {
+        let policy = RetryPolicy::default()
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(500));
+        let delays: Vec<_> = (1..=5).map(|attempt| policy.backoff(attempt)).collect();
+        assert_eq!(delays, [100, 200, 400, 500, 500].map(Duration::from_millis));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retries_transient_errors_until_exhausted() {
+        let policy = RetryPolicy::default()
+            .with_max_attempts(3)
+            .with_backoff(Duration::from_secs(1), Duration::from_secs(10));
+        let calls = AtomicU32::new(0);
+        let started = Instant::now();
+        let result: Result<(), _> = policy
+            .retry("test", || async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err(TransportError::NullResp)
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::Relaxed), 3);
+        assert_eq!(started.elapsed(), Duration::from_secs(3));
+
+        calls.store(0, Ordering::Relaxed);
+        let result = policy
+            .retry("test", || async {
+                match calls.fetch_add(1, Ordering::Relaxed) {
+                    0 => Err(TransportError::NullResp),
+                    _ => Ok(42),
+                }
+            })
+            .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_does_not_retry_permanent_errors() {
+        let calls = AtomicU32::new(0);
+        let result: Result<(), _> = RetryPolicy::default()
+            .retry("test", || async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err(error_response(-32601, "method not found"))
+            })
+            .await;
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::blockchain::configuration::{ErrorFilter, ParamType};
+use crate::blockchain::decoder::{DecodeError, Decoder, EthereumDecoder, Parameter};
+use alloy::primitives::Bytes;
+use hex_literal::hex;
+
+/// Selector of `Error(string)`.
+pub const ERROR_SELECTOR: [u8; 4] = hex!("08c379a0");
+/// Selector of `Panic(uint256)`.
+pub const PANIC_SELECTOR: [u8; 4] = hex!("4e487b71");
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RevertReason {
+    Error(String),
+    Panic {
+        code: usize,
+        meaning: &'static str,
+    },
+    Custom {
+        error_name: String,
+        parameters: Vec<Parameter>,
+    },
+    Unknown(Bytes),
+}
+
+/// Decodes revert data as `Error(string)`, `Panic(uint256)` or one of the
+/// custom errors configured for the subscription.
+pub fn decode_revert(data: &Bytes, errors: &[ErrorFilter]) -> Result<RevertReason, DecodeError> {
+    let Some((selector, args)) = data.split_first_chunk::<4>() else {
+        return Ok(RevertReason::Unknown(data.clone()));
+    };
+    let args = Bytes::copy_from_slice(args);
+
+    if *selector == ERROR_SELECTOR {
+        return match EthereumDecoder::new(vec![ParamType::String])
+            .decode(&args)?
+            .pop()
+        {
+            Some(Parameter::String(message)) => Ok(RevertReason::Error(message)),
+            _ => Ok(RevertReason::Unknown(data.clone())),
+        };
+    }
+
+    if *selector == PANIC_SELECTOR {
+        return match EthereumDecoder::new(vec![ParamType::UInt(256)])
+            .decode(&args)?
+            .pop()
+        {
+            Some(Parameter::Uint(code)) => Ok(RevertReason::Panic {
+                code,
+                meaning: panic_meaning(code),
+            }),
+            _ => Ok(RevertReason::Unknown(data.clone())),
+        };
+    }
+
+    match errors
+        .iter()
+        .find(|error| error.selector.as_slice() == selector)
+    {
+        Some(error) => Ok(RevertReason::Custom {
+            error_name: error.function_name.clone(),
+            parameters: EthereumDecoder::new(error.data_types.clone()).decode(&args)?,
+        }),
+        None => Ok(RevertReason::Unknown(data.clone())),
+    }
+}
+
+/// Describes a Solidity panic code.
+pub fn panic_meaning(code: usize) -> &'static str {
+    match code {
+        0x00 => "generic compiler inserted panic",
+        0x01 => "assertion failed",
+        0x11 => "arithmetic overflow or underflow",
+        0x12 => "division or modulo by zero",
+        0x21 => "invalid enum value",
+        0x22 => "invalid storage byte array encoding",
+        0x31 => "pop on empty array",
+        0x32 => "array index out of bounds",
+        0x41 => "out of memory",
+        0x51 => "call to zero-initialized internal function",
+        _ => "unknown panic code",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_error_string() {
+        let data = Bytes::from(hex!(
+            "08c379a0\
+             0000000000000000000000000000000000000000000000000000000000000020\
+             000000000000000000000000000000000000000000000000000000000000000d\
+             48656c6c6f2c20576f726c642100000000000000000000000000000000000000"
+        ));
+
+        let reason = decode_revert(&data, &[]).expect("Decoding failed");
+        assert_eq!(reason, RevertReason::Error("Hello, World!".to_string()));
+    }
+
+    #[test]
+    fn test_decode_panic() {
+        let data = Bytes::from(hex!(
+            "4e487b71\
+             0000000000000000000000000000000000000000000000000000000000000011"
+        ));
+
+        let reason = decode_revert(&data, &[]).expect("Decoding failed");
+        assert_eq!(
+            reason,
+            RevertReason::Panic {
+                code: 0x11,
+                meaning: "arithmetic overflow or underflow"
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_custom_error() {
+        let error = ErrorFilter::new("InsufficientBalance(uint256,uint256)".to_string())
+            .expect("Invalid error signature");
+        let mut data = error.selector.to_vec();
+        data.extend_from_slice(&hex!(
+            "0000000000000000000000000000000000000000000000000000000000000001\
+             000000000000000000000000000000000000000000000000000000000000002a"
+        ));
+
+        let reason = decode_revert(&Bytes::from(data), &[error]).expect("Decoding failed");
+        assert_eq!(
+            reason,
+            RevertReason::Custom {
+                error_name: "InsufficientBalance".to_string(),
+                parameters: vec![Parameter::Uint(1), Parameter::Uint(42)],
+            }
+        );
+    }
+
+    #[test]
+    fn test_decode_unknown_revert() {
+        let data = Bytes::from(hex!("deadbeef"));
+        assert_eq!(
+            decode_revert(&data, &[]).expect("Decoding failed"),
+            RevertReason::Unknown(data)
+        );
+        assert_eq!(
+            decode_revert(&Bytes::new(), &[]).expect("Decoding failed"),
+            RevertReason::Unknown(Bytes::new())
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use crate::metrics::{metrics, RPC_ERRORS, RPC_LATENCY, RPC_REQUESTS};
+use alloy::rpc::json_rpc::{RequestPacket, ResponsePacket};
+use alloy::transports::{TransportError, TransportFut};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+
+/// Records the count, failures and latency of JSON-RPC requests per network
+/// and method. Every request of a batch is recorded with the latency of the
+/// whole batch.
+#[derive(Debug, Clone)]
+pub struct RpcMetricsLayer {
+    network: Arc<str>,
+}
+
+impl RpcMetricsLayer {
+    pub fn new(network: &str) -> Self {
+        Self {
+            network: network.into(),
+        }
+    }
+}
+
+impl<S> Layer<S> for RpcMetricsLayer {
+    type Service = RpcMetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RpcMetricsService {
+            inner,
+            network: self.network.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RpcMetricsService<S> {
+    inner: S,
+    network: Arc<str>,
+}
+
+impl<S> Service<RequestPacket> for RpcMetricsService<S>
+where
+    S: Service<RequestPacket, Response = ResponsePacket, Error = TransportError> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = ResponsePacket;
+    type Error = TransportError;
+    type Future = TransportFut<'static>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: RequestPacket) -> Self::Future {
+        let methods: Vec<String> = match &request {
+            RequestPacket::Single(request) => vec![request.method().to_string()],
+            RequestPacket::Batch(requests) => requests
+                .iter()
+                .map(|request| request.method().to_string())
+                .collect(),
+        };
+        let network = self.network.clone();
+        let started = Instant::now();
+        let response = self.inner.call(request);
+
+        Box::pin(async move {
+            let response = response.await;
+            let elapsed = started.elapsed();
+            let failed = match &response {
+                Ok(response) => response.is_error(),
+                Err(_) => true,
+            };
+            for method in &methods {
+                let labels = [("network", &*network), ("method", method.as_str())];
+                metrics().increment(RPC_REQUESTS, &labels);
+                metrics().observe(RPC_LATENCY, &labels, elapsed);
+                if failed {
+                    metrics().increment(RPC_ERRORS, &labels);
+                }
+            }
+            response
+        })
+    }
+}
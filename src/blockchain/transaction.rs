@@ -1,12 +1,25 @@
-use crate::blockchain::configuration::{EventFilter, EventSubscription};
-use alloy::consensus::Transaction;
+use crate::blockchain::configuration::{
+    EventSubscription, FunctionFilter, DEFAULT_RECEIPT_CONCURRENCY,
+};
+use crate::blockchain::decoder::{Decoder, EthereumDecoder, Parameter};
+use crate::blockchain::event::DecodedEvent;
+use crate::blockchain::retry::{RetryPolicy, Transient};
+use crate::blockchain::revert::{decode_revert, RevertReason};
+use crate::metrics::{metrics, EVENTS_DECODED, EVENTS_FAILED, EVENTS_MATCHED, RECEIPTS_FETCHED};
+use alloy::consensus::Transaction as _;
+use alloy::eips::BlockId;
 use alloy::network::TransactionResponse;
+use alloy::primitives::{Address, Bytes, B256, U256};
 use alloy::providers::{Provider, RootProvider};
-use alloy::rpc::types::{Block, Log};
-use alloy::transports::{BoxTransport, TransportError};
-use log::{error, info};
+use alloy::rpc::json_rpc::ErrorPayload;
+use alloy::rpc::types::{Block, Transaction, TransactionReceipt};
+use alloy::transports::{BoxTransport, RpcError, TransportError};
+use futures_util::{stream, StreamExt, TryStreamExt};
+use std::collections::HashMap;
 use std::future::Future;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tracing::{error, info, info_span, Instrument};
 
 #[derive(Debug, thiserror::Error)]
 pub enum TransactionError {
@@ -16,68 +29,472 @@ pub enum TransactionError {
     ReceiptNotFound(String),
 }
 
+impl Transient for TransactionError {
+    /// A missing receipt is usually a node that has not indexed the block
+    /// yet.
+    fn is_transient(&self) -> bool {
+        match self {
+            TransactionError::TransportError(err) => err.is_transient(),
+            TransactionError::ReceiptNotFound(_) => true,
+        }
+    }
+}
+
+/// Whether the node rejected a call because it does not implement the
+/// method, as opposed to failing to answer it.
+fn is_method_not_supported(payload: &ErrorPayload) -> bool {
+    let message = payload.message.to_lowercase();
+    payload.code == -32601
+        || (message.contains("method")
+            && [
+                "not supported",
+                "unsupported",
+                "not found",
+                "does not exist",
+                "not available",
+            ]
+            .iter()
+            .any(|reason| message.contains(reason)))
+}
+
+#[derive(Debug, Clone)]
+pub struct FunctionCall {
+    pub chain_id: u64,
+    pub block_number: u64,
+    pub block_hash: B256,
+    pub transaction_hash: B256,
+    pub from: Address,
+    pub to: Address,
+    pub value: U256,
+    pub success: bool,
+    /// Calldata following the 4-byte selector.
+    pub input: Bytes,
+    /// The arguments decoded from `input`.
+    pub parameters: Vec<Parameter>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FailedTransaction {
+    pub chain_id: u64,
+    pub transaction_hash: B256,
+    pub block_number: u64,
+    pub from: Address,
+    pub to: Address,
+    pub value: U256,
+    pub revert_reason: RevertReason,
+}
+
+/// Set of subscriptions that can be replaced while a processor is running.
+/// Each block is processed against the set as it was when the block started.
+#[derive(Debug, Clone, Default)]
+pub struct SharedSubscriptions(Arc<RwLock<Arc<Vec<EventSubscription>>>>);
+
+impl SharedSubscriptions {
+    pub fn new(subscriptions: Vec<EventSubscription>) -> Self {
+        Self(Arc::new(RwLock::new(Arc::new(subscriptions))))
+    }
+
+    pub fn load(&self) -> Arc<Vec<EventSubscription>> {
+        self.0.read().unwrap_or_else(|err| err.into_inner()).clone()
+    }
+
+    pub fn store(&self, subscriptions: Vec<EventSubscription>) {
+        *self.0.write().unwrap_or_else(|err| err.into_inner()) = Arc::new(subscriptions);
+    }
+}
+
 pub struct EthereumTransactionProcessor {
     provider: Arc<RootProvider<BoxTransport>>,
-    subscriptions: Vec<EventSubscription>,
+    subscriptions: SharedSubscriptions,
+    chain_id: u64,
+    network: String,
+    receipt_concurrency: usize,
+    /// Cleared once the node rejects `eth_getBlockReceipts`.
+    block_receipts: AtomicBool,
+    retry: RetryPolicy,
 }
 
 impl EthereumTransactionProcessor {
     pub fn new(
         provider: Arc<RootProvider<BoxTransport>>,
         subscriptions: Vec<EventSubscription>,
+    ) -> Self {
+        Self::with_shared_subscriptions(provider, SharedSubscriptions::new(subscriptions))
+    }
+
+    /// Creates a processor whose subscriptions follow `subscriptions`, so
+    /// they can be updated from outside without a restart.
+    pub fn with_shared_subscriptions(
+        provider: Arc<RootProvider<BoxTransport>>,
+        subscriptions: SharedSubscriptions,
     ) -> Self {
         Self {
             provider,
             subscriptions,
+            chain_id: 0,
+            network: String::new(),
+            receipt_concurrency: DEFAULT_RECEIPT_CONCURRENCY,
+            block_receipts: AtomicBool::new(true),
+            retry: RetryPolicy::default(),
         }
     }
 
+    /// Names the network in the metrics this processor records.
+    pub fn with_network(mut self, network: String) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Limits the receipts fetched at a time when the node does not support
+    /// `eth_getBlockReceipts`.
+    pub fn with_receipt_concurrency(mut self, receipt_concurrency: usize) -> Self {
+        self.receipt_concurrency = receipt_concurrency;
+        self
+    }
+
+    /// Retries the receipt fetch and revert replay of a block on transient
+    /// errors.
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn network(&self) -> &str {
+        &self.network
+    }
+
+    /// Sets the chain ID reported with every function call and failed
+    /// transaction, as returned by `eth_chainId`.
+    pub fn with_chain_id(mut self, chain_id: u64) -> Self {
+        self.chain_id = chain_id;
+        self
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
     pub async fn process_transaction_logs<T, Fut>(
         &self,
-        block: Block,
-        process_event_log: T,
+        block: &Block,
+        process_event: T,
     ) -> Result<(), TransactionError>
     where
-        T: Fn(EventFilter, Log) -> Fut + Send + Sync,
+        T: Fn(DecodedEvent) -> Fut + Send + Sync,
         Fut: Future<Output = ()> + Send,
     {
-        for transaction in block.transactions.into_transactions() {
-            if let Some(to) = transaction.to() {
-                if let Some(event_filters) = self
-                    .subscriptions
+        self.process_transactions(block, process_event, |_, _| async {}, |_| async {})
+            .await
+    }
+
+    /// Fetches the receipt of every transaction sent to a subscribed
+    /// contract. Subscribed events are decoded and passed to `process_event`
+    /// with the block, transaction and log they come from; events that fail
+    /// to decode are logged and skipped.
+    pub async fn process_transactions<T, Fut, C, CFut, F, FFut>(
+        &self,
+        block: &Block,
+        process_event: T,
+        process_function_call: C,
+        process_failed_transaction: F,
+    ) -> Result<(), TransactionError>
+    where
+        T: Fn(DecodedEvent) -> Fut + Send + Sync,
+        Fut: Future<Output = ()> + Send,
+        C: Fn(FunctionFilter, FunctionCall) -> CFut + Send + Sync,
+        CFut: Future<Output = ()> + Send,
+        F: Fn(FailedTransaction) -> FFut + Send + Sync,
+        FFut: Future<Output = ()> + Send,
+    {
+        let block_number = block.header.number;
+        let block_hash = block.header.hash;
+        let block_timestamp = block.header.timestamp;
+        let subscriptions = self.subscriptions.load();
+        let transactions: Vec<(&Transaction, Address, &EventSubscription)> = block
+            .transactions
+            .txns()
+            .filter_map(|transaction| {
+                let to = transaction.to()?;
+                let subscription = subscriptions
                     .iter()
-                    .find(|subscription| subscription.contract_address == to)
-                    .map(|subscription| &subscription.events)
-                {
-                    match self
-                        .provider
-                        .get_transaction_receipt(transaction.tx_hash())
-                        .await?
-                    {
-                        Some(tx_receipt) => {
-                            for log in tx_receipt.inner.logs() {
-                                for event_filter in event_filters {
-                                    if log.inner.topics().contains(&event_filter.hash) {
-                                        info!(
-                                            "Event {} found in transaction {}",
-                                            event_filter.event_name,
-                                            transaction.tx_hash()
-                                        );
-                                        process_event_log(event_filter.clone(), log.clone()).await;
-                                    }
+                    .find(|subscription| subscription.contract_address == to)?;
+                Some((transaction, to, subscription))
+            })
+            .collect();
+        if transactions.is_empty() {
+            return Ok(());
+        }
+
+        let hashes: Vec<B256> = transactions
+            .iter()
+            .map(|(transaction, _, _)| transaction.tx_hash())
+            .collect();
+        let receipts = self.fetch_receipts(block_hash, hashes).await?;
+        for ((transaction, to, subscription), tx_receipt) in transactions.into_iter().zip(receipts)
+        {
+            let span = info_span!(
+                "transaction",
+                tx_hash = %transaction.tx_hash(),
+                subscription = %to
+            );
+            async {
+                metrics().increment(RECEIPTS_FETCHED, &[("network", self.network.as_str())]);
+                if !tx_receipt.status() {
+                    let revert_data = self
+                        .retry
+                        .retry("replay_transaction", move || {
+                            self.replay_transaction(transaction, block_number)
+                                .instrument(info_span!("replay_transaction"))
+                        })
+                        .await?;
+                    let revert_reason = decode_revert(&revert_data, &subscription.errors)
+                        .unwrap_or_else(|err| {
+                            error!(
+                                "Error decoding revert data of transaction {}: {}",
+                                transaction.tx_hash(),
+                                err
+                            );
+                            RevertReason::Unknown(revert_data.clone())
+                        });
+                    let failed_transaction = FailedTransaction {
+                        chain_id: self.chain_id,
+                        transaction_hash: transaction.tx_hash(),
+                        block_number,
+                        from: transaction.from(),
+                        to,
+                        value: transaction.value(),
+                        revert_reason,
+                    };
+                    info!(
+                        "Failed transaction {} found for contract {}",
+                        transaction.tx_hash(),
+                        to
+                    );
+                    process_failed_transaction(failed_transaction).await;
+                }
+
+                for log in tx_receipt.inner.logs() {
+                    for event_filter in &subscription.events {
+                        if log.inner.topics().contains(&event_filter.hash) {
+                            let subscription = to.to_string();
+                            let labels = [
+                                ("network", self.network.as_str()),
+                                ("subscription", subscription.as_str()),
+                                ("event", event_filter.event_name.as_str()),
+                            ];
+                            metrics().increment(EVENTS_MATCHED, &labels);
+                            info!(
+                                "Event {} found in transaction {}",
+                                event_filter.event_name,
+                                transaction.tx_hash()
+                            );
+                            let decoded = info_span!(
+                                "decode",
+                                event = %event_filter.event_name
+                            )
+                            .in_scope(|| {
+                                EthereumDecoder::new(event_filter.data_types.clone())
+                                    .decode(&log.data().data)
+                            });
+                            match decoded {
+                                Ok(parameters) => {
+                                    metrics().increment(EVENTS_DECODED, &labels);
+                                    process_event(DecodedEvent {
+                                        chain_id: self.chain_id,
+                                        block_number,
+                                        block_hash,
+                                        block_timestamp,
+                                        transaction_hash: transaction.tx_hash(),
+                                        transaction_index: tx_receipt.transaction_index,
+                                        from: transaction.from(),
+                                        to,
+                                        gas_used: tx_receipt.gas_used as u64,
+                                        address: log.address(),
+                                        log_index: log.log_index,
+                                        removed: log.removed,
+                                        event_name: event_filter.event_name.clone(),
+                                        signature: event_filter.signature.clone(),
+                                        topics: log.topics().to_vec(),
+                                        data: log.data().data.clone(),
+                                        parameters,
+                                    })
+                                    .await;
+                                }
+                                Err(err) => {
+                                    metrics().increment(EVENTS_FAILED, &labels);
+                                    error!(
+                                        "Error decoding event {} on chain {}: {}",
+                                        event_filter.event_name, self.chain_id, err
+                                    );
                                 }
                             }
                         }
-                        None => {
-                            info!("No receipt found for transaction {}", transaction.tx_hash());
-                            return Err(TransactionError::ReceiptNotFound(
-                                transaction.tx_hash().to_string(),
-                            ));
+                    }
+                }
+
+                for function_filter in &subscription.functions {
+                    if let Some(input) = function_filter.match_input(transaction.input()) {
+                        let input = Bytes::copy_from_slice(input);
+                        info!(
+                            "Function {} called in transaction {}",
+                            function_filter.function_name,
+                            transaction.tx_hash()
+                        );
+                        let decoded = info_span!(
+                            "decode",
+                            function = %function_filter.function_name
+                        )
+                        .in_scope(|| {
+                            EthereumDecoder::new(function_filter.data_types.clone()).decode(&input)
+                        });
+                        match decoded {
+                            Ok(parameters) => {
+                                let function_call = FunctionCall {
+                                    chain_id: self.chain_id,
+                                    block_number,
+                                    block_hash,
+                                    transaction_hash: transaction.tx_hash(),
+                                    from: transaction.from(),
+                                    to,
+                                    value: transaction.value(),
+                                    success: tx_receipt.status(),
+                                    input,
+                                    parameters,
+                                };
+                                process_function_call(function_filter.clone(), function_call)
+                                    .await;
+                            }
+                            Err(err) => {
+                                let subscription = to.to_string();
+                                metrics().increment(
+                                    EVENTS_FAILED,
+                                    &[
+                                        ("network", self.network.as_str()),
+                                        ("subscription", subscription.as_str()),
+                                        ("event", function_filter.function_name.as_str()),
+                                    ],
+                                );
+                                error!(
+                                    "Error decoding function {} called in transaction {} on chain {}: {}",
+                                    function_filter.function_name,
+                                    transaction.tx_hash(),
+                                    self.chain_id,
+                                    err
+                                );
+                            }
                         }
                     }
                 }
+                Ok::<(), TransactionError>(())
             }
+            .instrument(span)
+            .await?;
         }
         Ok(())
     }
+
+    /// Receipts of the transactions with `hashes`, in the same order. The
+    /// whole block is fetched with `eth_getBlockReceipts` unless the node
+    /// rejected it as unsupported before; otherwise receipts are fetched one
+    /// by one, up to `receipt_concurrency` at a time.
+    async fn fetch_receipts(
+        &self,
+        block_hash: B256,
+        hashes: Vec<B256>,
+    ) -> Result<Vec<TransactionReceipt>, TransactionError> {
+        if self.block_receipts.load(Ordering::Relaxed) {
+            match self
+                .retry
+                .retry("fetch_block_receipts", || {
+                    self.fetch_block_receipts(block_hash, &hashes)
+                })
+                .await
+            {
+                Ok(Some(receipts)) => return Ok(receipts),
+                Ok(None) => {}
+                Err(TransactionError::TransportError(RpcError::ErrorResp(payload)))
+                    if is_method_not_supported(&payload) =>
+                {
+                    info!(
+                        "eth_getBlockReceipts is not supported on network {} ({}), fetching receipts per transaction",
+                        self.network, payload
+                    );
+                    self.block_receipts.store(false, Ordering::Relaxed);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        stream::iter(hashes)
+            .map(|hash| {
+                self.retry
+                    .retry("fetch_receipt", move || self.fetch_receipt(hash))
+            })
+            .buffered(self.receipt_concurrency.max(1))
+            .try_collect()
+            .await
+    }
+
+    /// Receipts of the transactions with `hashes` out of the block's
+    /// receipts, or `None` when the node does not know the block.
+    async fn fetch_block_receipts(
+        &self,
+        block_hash: B256,
+        hashes: &[B256],
+    ) -> Result<Option<Vec<TransactionReceipt>>, TransactionError> {
+        let Some(receipts) = self
+            .provider
+            .get_block_receipts(BlockId::hash(block_hash))
+            .instrument(info_span!("fetch_block_receipts"))
+            .await?
+        else {
+            return Ok(None);
+        };
+        let mut receipts: HashMap<B256, TransactionReceipt> = receipts
+            .into_iter()
+            .map(|receipt| (receipt.transaction_hash, receipt))
+            .collect();
+        hashes
+            .iter()
+            .map(|hash| {
+                receipts
+                    .remove(hash)
+                    .ok_or_else(|| TransactionError::ReceiptNotFound(hash.to_string()))
+            })
+            .collect::<Result<_, _>>()
+            .map(Some)
+    }
+
+    async fn fetch_receipt(&self, hash: B256) -> Result<TransactionReceipt, TransactionError> {
+        self.provider
+            .get_transaction_receipt(hash)
+            .instrument(info_span!("fetch_receipt", tx_hash = %hash))
+            .await?
+            .ok_or_else(|| {
+                info!("No receipt found for transaction {}", hash);
+                TransactionError::ReceiptNotFound(hash.to_string())
+            })
+    }
+
+    /// Replays a transaction with `eth_call` on top of its parent block to
+    /// recover the revert data, which receipts do not carry. Transactions
+    /// earlier in the same block are not applied, so the result is a best
+    /// effort when they touch the same state.
+    async fn replay_transaction(
+        &self,
+        transaction: &Transaction,
+        block_number: u64,
+    ) -> Result<Bytes, TransactionError> {
+        let request = transaction.clone().into_request();
+        match self
+            .provider
+            .call(&request)
+            .block(BlockId::number(block_number.saturating_sub(1)))
+            .await
+        {
+            Ok(_) => Ok(Bytes::new()),
+            Err(RpcError::ErrorResp(payload)) => Ok(payload.as_revert_data().unwrap_or_default()),
+            Err(err) => Err(TransactionError::TransportError(err)),
+        }
+    }
 }
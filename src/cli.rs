@@ -0,0 +1,48 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CliError {
+    #[error("Missing value for argument: {0}")]
+    MissingValue(String),
+    #[error("Unknown argument: {0}")]
+    UnknownArgument(String),
+}
+
+/// Command line arguments. Besides the flags below, any `--path.to.key=value`
+/// argument overrides the matching configuration value. The `validate`
+/// command checks the configuration and exits instead of starting.
+#[derive(Debug, Default, PartialEq)]
+pub struct CliArgs {
+    pub validate: bool,
+    pub profile: Option<String>,
+    pub print_effective_config: bool,
+    pub overrides: Vec<String>,
+}
+
+impl CliArgs {
+    pub fn parse<I>(args: I) -> Result<Self, CliError>
+    where
+        I: IntoIterator<Item = String>,
+    {
+        let mut cli_args = CliArgs::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "validate" => cli_args.validate = true,
+                "--print-effective-config" => cli_args.print_effective_config = true,
+                "--profile" => {
+                    cli_args.profile =
+                        Some(args.next().ok_or(CliError::MissingValue(arg.clone()))?);
+                }
+                _ => match arg.strip_prefix("--").and_then(|arg| arg.split_once('=')) {
+                    Some(("profile", profile)) => cli_args.profile = Some(profile.to_string()),
+                    Some((key, value)) => cli_args.overrides.push(format!("{}={}", key, value)),
+                    None => return Err(CliError::UnknownArgument(arg)),
+                },
+            }
+        }
+
+        Ok(cli_args)
+    }
+}
@@ -1,38 +1,491 @@
 use crate::blockchain::configuration::Configuration;
-use regex::Regex;
+use crate::validation::line_numbers;
+use serde_yaml::{Mapping, Value};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
 use std::{env, fs};
 use thiserror::Error;
 
+const BASE_CONFIG_FILE: &str = "resources/application.yml";
+const ENV_PREFIX: &str = "BLOCKSTREAM_";
+
 #[derive(Debug, Error)]
 pub enum ConfigError {
     #[error("Error reading the file: {0}")]
     FileReadError(String),
     #[error("Error parsing YAML: {0}")]
     YamlParseError(String),
+    #[error("Invalid override: {0}")]
+    InvalidOverride(String),
+    #[error("Missing required variable {name}: {message}")]
+    MissingVariable { name: String, message: String },
+    #[error("Error reading secret file: {0}")]
+    SecretFileError(String),
+    #[error("Unterminated placeholder: {0}")]
+    UnterminatedPlaceholder(String),
+}
+
+/// Where an effective configuration value was taken from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValueSource {
+    File(String),
+    Environment(String),
+    CommandLine,
+}
+
+impl Display for ValueSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValueSource::File(filename) => write!(f, "{}", filename),
+            ValueSource::Environment(var_name) => write!(f, "env {}", var_name),
+            ValueSource::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// Configuration tree built by layering the base file, the profile file,
+/// `BLOCKSTREAM_*` environment variables and command line overrides, in that
+/// order. Mappings are merged key by key; any other value replaces the one
+/// from the previous layer.
+#[derive(Debug)]
+pub struct EffectiveConfig {
+    pub value: Value,
+    pub sources: BTreeMap<String, ValueSource>,
+    files: Vec<String>,
+    lines: BTreeMap<String, BTreeMap<String, usize>>,
 }
 
-fn substitute_env_variables(contents: &str) -> String {
-    let re = Regex::new(r"\$\{([^:}]+):?([^}]*)\}").unwrap();
-    re.replace_all(contents, |caps: &regex::Captures| {
-        let var_name = &caps[1];
-        let default_value = &caps[2];
-        env::var(var_name).unwrap_or_else(|_| default_value.to_string())
+impl Default for EffectiveConfig {
+    fn default() -> Self {
+        Self {
+            value: Value::Mapping(Mapping::new()),
+            sources: BTreeMap::new(),
+            files: Vec::new(),
+            lines: BTreeMap::new(),
+        }
+    }
+}
+
+impl EffectiveConfig {
+    pub fn merge_file(&mut self, filename: &str) -> Result<(), ConfigError> {
+        let contents = fs::read_to_string(filename)
+            .map_err(|_| ConfigError::FileReadError(filename.to_string()))?;
+        let mut value: Value = serde_yaml::from_str(&contents)
+            .map_err(|err| ConfigError::YamlParseError(format!("{}: {}", filename, err)))?;
+        let lines = line_numbers(&contents, &value);
+        substitute_placeholders(&mut value)?;
+        null_to_empty_string(&mut value);
+
+        self.files.push(filename.to_string());
+        self.lines.insert(filename.to_string(), lines);
+        self.merge(value, ValueSource::File(filename.to_string()));
+        Ok(())
+    }
+
+    /// Applies variables such as `BLOCKSTREAM_NETWORK__HOST`: the prefix is
+    /// stripped, `__` separates path segments and names are lowercased.
+    pub fn merge_environment<I>(&mut self, vars: I) -> Result<(), ConfigError>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (var_name, raw_value) in vars {
+            if let Some(key) = var_name.strip_prefix(ENV_PREFIX) {
+                let path: Vec<String> = key.split("__").map(str::to_lowercase).collect();
+                if path.iter().any(String::is_empty) {
+                    return Err(ConfigError::InvalidOverride(var_name));
+                }
+                self.merge(
+                    nest(&path, parse_scalar(&raw_value)),
+                    ValueSource::Environment(var_name.clone()),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies `path.to.key=value` overrides, as given on the command line.
+    pub fn merge_overrides(&mut self, overrides: &[String]) -> Result<(), ConfigError> {
+        for entry in overrides {
+            let (key, raw_value) = entry
+                .split_once('=')
+                .ok_or_else(|| ConfigError::InvalidOverride(entry.clone()))?;
+            let path: Vec<String> = key.split('.').map(str::to_string).collect();
+            if path.iter().any(String::is_empty) {
+                return Err(ConfigError::InvalidOverride(entry.clone()));
+            }
+            self.merge(
+                nest(&path, parse_scalar(raw_value)),
+                ValueSource::CommandLine,
+            );
+        }
+        Ok(())
+    }
+
+    pub fn configuration(&self) -> Result<Configuration, ConfigError> {
+        serde_yaml::from_value(self.value.clone()).map_err(|err| {
+            ConfigError::YamlParseError(format!("{}: {}", self.files.join(", "), err))
+        })
+    }
+
+    /// Describes where the value at `path` was defined, as `file:line`, or
+    /// the environment variable or command line that set it.
+    pub fn location(&self, path: &str) -> Option<String> {
+        let prefix = format!("{}.", path);
+        let source = self.sources.get(path).or_else(|| {
+            self.sources
+                .iter()
+                .find(|(key, _)| key.starts_with(&prefix))
+                .map(|(_, source)| source)
+        });
+        let filename = match source {
+            Some(ValueSource::File(filename)) => filename,
+            Some(source) => return Some(source.to_string()),
+            // Empty mappings and lists have no leaves, look for the key itself.
+            None => self
+                .files
+                .iter()
+                .rev()
+                .find(|filename| self.lines[*filename].contains_key(path))?,
+        };
+        match self.lines.get(filename).and_then(|lines| lines.get(path)) {
+            Some(line) => Some(format!("{}:{}", filename, line)),
+            None => Some(filename.clone()),
+        }
+    }
+
+    /// Renders every leaf value with the layer it was taken from.
+    pub fn describe(&self) -> String {
+        let mut lines = Vec::new();
+        self.describe_value(&self.value, String::new(), &mut lines);
+        lines.join("\n")
+    }
+
+    fn describe_value(&self, value: &Value, path: String, lines: &mut Vec<String>) {
+        match value {
+            Value::Mapping(mapping) => {
+                for (key, child) in mapping {
+                    self.describe_value(child, join_path(&path, &key_to_string(key)), lines);
+                }
+            }
+            Value::Sequence(sequence) => {
+                for (index, child) in sequence.iter().enumerate() {
+                    self.describe_value(child, join_path(&path, &index.to_string()), lines);
+                }
+            }
+            _ => {
+                let rendered = serde_yaml::to_string(value).unwrap_or_default();
+                let source = self
+                    .sources
+                    .get(&path)
+                    .map(ValueSource::to_string)
+                    .unwrap_or_default();
+                lines.push(format!("{} = {} ({})", path, rendered.trim_end(), source));
+            }
+        }
+    }
+
+    fn merge(&mut self, overlay: Value, source: ValueSource) {
+        let mut value = std::mem::take(&mut self.value);
+        merge_value(
+            &mut value,
+            overlay,
+            String::new(),
+            &source,
+            &mut self.sources,
+        );
+        self.value = value;
+    }
+}
+
+fn merge_value(
+    target: &mut Value,
+    overlay: Value,
+    path: String,
+    source: &ValueSource,
+    sources: &mut BTreeMap<String, ValueSource>,
+) {
+    match (target, overlay) {
+        (Value::Mapping(target), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                let child_path = join_path(&path, &key_to_string(&key));
+                match target.get_mut(&key) {
+                    Some(existing) => merge_value(existing, value, child_path, source, sources),
+                    None => {
+                        record_sources(&value, child_path, source, sources);
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+        // Indexed overrides such as `subscriptions.0.contract_address`.
+        (Value::Sequence(target), Value::Mapping(overlay))
+            if overlay
+                .keys()
+                .all(|key| sequence_index(key, target.len()).is_some()) =>
+        {
+            for (key, value) in overlay {
+                let index = sequence_index(&key, target.len()).unwrap_or_default();
+                let child_path = join_path(&path, &index.to_string());
+                merge_value(&mut target[index], value, child_path, source, sources);
+            }
+        }
+        (target, overlay) => {
+            let prefix = format!("{}.", path);
+            sources.retain(|key, _| *key != path && !key.starts_with(&prefix));
+            record_sources(&overlay, path, source, sources);
+            *target = overlay;
+        }
+    }
+}
+
+fn record_sources(
+    value: &Value,
+    path: String,
+    source: &ValueSource,
+    sources: &mut BTreeMap<String, ValueSource>,
+) {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, child) in mapping {
+                record_sources(
+                    child,
+                    join_path(&path, &key_to_string(key)),
+                    source,
+                    sources,
+                );
+            }
+        }
+        Value::Sequence(sequence) => {
+            for (index, child) in sequence.iter().enumerate() {
+                record_sources(child, join_path(&path, &index.to_string()), source, sources);
+            }
+        }
+        _ => {
+            sources.insert(path, source.clone());
+        }
+    }
+}
+
+fn sequence_index(key: &Value, len: usize) -> Option<usize> {
+    key.as_str()
+        .and_then(|key| key.parse::<usize>().ok())
+        .filter(|index| *index < len)
+}
+
+fn nest(path: &[String], value: Value) -> Value {
+    path.iter().rev().fold(value, |value, key| {
+        let mut mapping = Mapping::new();
+        mapping.insert(Value::String(key.clone()), value);
+        Value::Mapping(mapping)
     })
-    .to_string()
 }
 
-fn load_config_by_filename(filename: &str) -> Result<Configuration, ConfigError> {
-    let contents = fs::read_to_string(filename)
-        .map_err(|_| ConfigError::FileReadError(filename.to_string()))?;
-    let substituted = substitute_env_variables(&contents);
-    serde_yaml::from_str(&substituted)
-        .map_err(|_| ConfigError::YamlParseError(filename.to_string()))
+/// Parses an override the same way a value written in the YAML file would be,
+/// except that hex literals are kept as strings since they are addresses.
+fn parse_scalar(raw_value: &str) -> Value {
+    match serde_yaml::from_str::<Value>(raw_value) {
+        Ok(Value::Number(_)) if raw_value.starts_with("0x") => Value::String(raw_value.to_string()),
+        Ok(Value::Null) | Err(_) => Value::String(raw_value.to_string()),
+        Ok(value) => value,
+    }
 }
 
-pub fn load_config(env: Option<String>) -> Result<Configuration, ConfigError> {
-    let filename = match env {
-        Some(env) => format!("resources/application-{}.yml", env),
-        None => "resources/application.yml".to_string(),
+/// Substitutes placeholders in every string value of a parsed file, so that
+/// placeholders in comments are left alone and substituted values are never
+/// parsed as YAML themselves. A value made of a single placeholder takes the
+/// type of what it resolves to, e.g. `port: ${PORT:8545}` is a number.
+fn substitute_placeholders(value: &mut Value) -> Result<(), ConfigError> {
+    match value {
+        Value::String(raw_value) if raw_value.contains('$') => {
+            let substituted = substitute_env_variables(raw_value)?;
+            *value = if is_single_placeholder(raw_value) {
+                typed_scalar(substituted)
+            } else {
+                Value::String(substituted)
+            };
+        }
+        Value::Mapping(mapping) => {
+            for child in mapping.values_mut() {
+                substitute_placeholders(child)?;
+            }
+        }
+        Value::Sequence(sequence) => {
+            for child in sequence {
+                substitute_placeholders(child)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn is_single_placeholder(raw_value: &str) -> bool {
+    raw_value
+        .strip_prefix("${")
+        .and_then(placeholder_end)
+        .is_some_and(|end| end + 3 == raw_value.len())
+}
+
+/// Reads a substituted value as a number or boolean where it is one, and as
+/// a string otherwise; hex literals stay strings since they are addresses.
+fn typed_scalar(substituted: String) -> Value {
+    match serde_yaml::from_str::<Value>(&substituted) {
+        Ok(value @ (Value::Number(_) | Value::Bool(_))) if !substituted.starts_with("0x") => value,
+        _ => Value::String(substituted),
+    }
+}
+
+/// Keys left empty, such as `path:`, hold a null; they are read as empty
+/// strings.
+fn null_to_empty_string(value: &mut Value) {
+    match value {
+        Value::Null => *value = Value::String(String::new()),
+        Value::Mapping(mapping) => mapping.values_mut().for_each(null_to_empty_string),
+        Value::Sequence(sequence) => sequence.iter_mut().for_each(null_to_empty_string),
+        _ => {}
+    }
+}
+
+pub(crate) fn key_to_string(key: &Value) -> String {
+    match key {
+        Value::String(key) => key.clone(),
+        key => serde_yaml::to_string(key)
+            .unwrap_or_default()
+            .trim_end()
+            .to_string(),
+    }
+}
+
+pub(crate) fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Replaces placeholders in a configuration value:
+///
+/// - `${VAR}` fails when `VAR` is not set
+/// - `${VAR:default}` uses `default` when `VAR` is not set
+/// - `${VAR:-default}` uses `default` when `VAR` is not set or empty
+/// - `${VAR:?message}` fails with `message` when `VAR` is not set or empty
+/// - `${file:/path}` reads the (trimmed) contents of a file, e.g. a mounted secret
+/// - `$${` is a literal `${`
+///
+/// Defaults and file paths may contain placeholders themselves.
+pub fn substitute_env_variables(contents: &str) -> Result<String, ConfigError> {
+    let mut output = String::with_capacity(contents.len());
+    let mut rest = contents;
+
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        if let Some(after) = rest.strip_prefix("$${") {
+            output.push_str("${");
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("${") {
+            let end = placeholder_end(after).ok_or_else(|| {
+                let line = rest.lines().next().unwrap_or_default();
+                ConfigError::UnterminatedPlaceholder(line.to_string())
+            })?;
+            output.push_str(&resolve_placeholder(&after[..end])?);
+            rest = &after[end + 1..];
+        } else {
+            output.push('$');
+            rest = &rest[1..];
+        }
+    }
+
+    output.push_str(rest);
+    Ok(output)
+}
+
+/// Position of the `}` closing a placeholder, skipping nested placeholders.
+fn placeholder_end(expression: &str) -> Option<usize> {
+    let mut depth = 0;
+    let bytes = expression.as_bytes();
+    for (index, byte) in bytes.iter().enumerate() {
+        match byte {
+            b'{' if index > 0 && bytes[index - 1] == b'$' => depth += 1,
+            b'}' if depth == 0 => return Some(index),
+            b'}' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn resolve_placeholder(expression: &str) -> Result<String, ConfigError> {
+    let (name, modifier) = match expression.split_once(':') {
+        Some((name, modifier)) => (name, Some(modifier)),
+        None => (expression, None),
     };
-    load_config_by_filename(&filename)
+
+    if name == "file" {
+        if let Some(path) = modifier {
+            let path = substitute_env_variables(path)?;
+            return fs::read_to_string(&path)
+                .map(|contents| contents.trim_end_matches(['\r', '\n']).to_string())
+                .map_err(|_| ConfigError::SecretFileError(path));
+        }
+    }
+
+    let value = env::var(name).ok();
+    match modifier {
+        None => value.ok_or_else(|| ConfigError::MissingVariable {
+            name: name.to_string(),
+            message: "variable is not set".to_string(),
+        }),
+        Some(modifier) => {
+            if let Some(default) = modifier.strip_prefix('-') {
+                match value.filter(|value| !value.is_empty()) {
+                    Some(value) => Ok(value),
+                    None => substitute_env_variables(default),
+                }
+            } else if let Some(message) = modifier.strip_prefix('?') {
+                let message = match message {
+                    "" => "variable is not set or empty",
+                    message => message,
+                };
+                value.filter(|value| !value.is_empty()).ok_or_else(|| {
+                    ConfigError::MissingVariable {
+                        name: name.to_string(),
+                        message: message.to_string(),
+                    }
+                })
+            } else {
+                match value {
+                    Some(value) => Ok(value),
+                    None => substitute_env_variables(modifier),
+                }
+            }
+        }
+    }
+}
+
+pub fn load_effective_config(
+    env: Option<String>,
+    overrides: &[String],
+) -> Result<EffectiveConfig, ConfigError> {
+    let mut config = EffectiveConfig::default();
+    config.merge_file(BASE_CONFIG_FILE)?;
+    if let Some(env) = env {
+        config.merge_file(&format!("resources/application-{}.yml", env))?;
+    }
+    config.merge_environment(env::vars())?;
+    config.merge_overrides(overrides)?;
+    Ok(config)
+}
+
+pub fn load_config_with_overrides(
+    env: Option<String>,
+    overrides: &[String],
+) -> Result<Configuration, ConfigError> {
+    load_effective_config(env, overrides)?.configuration()
+}
+
+pub fn load_config(env: Option<String>) -> Result<Configuration, ConfigError> {
+    load_config_with_overrides(env, &[])
 }
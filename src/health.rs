@@ -0,0 +1,188 @@
+use crate::blockchain::block::SubscriberState;
+use crate::server::{self, Response};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpListener;
+
+/// Where `/healthz` and `/readyz` listen, and the thresholds they apply.
+/// Disabled unless `enabled` is set.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct HealthConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_host")]
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+    /// Confirmed blocks a network may be behind and still be ready.
+    #[serde(default = "default_max_head_lag")]
+    pub max_head_lag: u64,
+    /// Seconds without a processed block before a network is not live.
+    #[serde(default = "default_max_block_age_secs")]
+    pub max_block_age_secs: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: default_host(),
+            port: default_port(),
+            max_head_lag: default_max_head_lag(),
+            max_block_age_secs: default_max_block_age_secs(),
+        }
+    }
+}
+
+fn default_host() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_port() -> u16 {
+    8080
+}
+
+fn default_max_head_lag() -> u64 {
+    5
+}
+
+fn default_max_block_age_secs() -> u64 {
+    300
+}
+
+/// Liveness and readiness of every network, from the state their
+/// subscribers report.
+#[derive(Debug, Clone)]
+pub struct HealthCheck {
+    config: HealthConfig,
+    states: BTreeMap<String, Arc<SubscriberState>>,
+}
+
+impl HealthCheck {
+    pub fn new(config: HealthConfig, states: BTreeMap<String, Arc<SubscriberState>>) -> Self {
+        Self { config, states }
+    }
+
+    /// Networks that have not processed a block within `max_block_age_secs`,
+    /// counted from startup until their first block.
+    pub fn liveness(&self) -> Result<(), Vec<String>> {
+        self.check(|name, state| {
+            let max_age = Duration::from_secs(self.config.max_block_age_secs);
+            let age = Instant::now().duration_since(state.status().last_progress);
+            (age > max_age).then(|| {
+                format!(
+                    "network {}: no block processed for {}s",
+                    name,
+                    age.as_secs()
+                )
+            })
+        })
+    }
+
+    /// Networks that are not connected, have not processed a block yet or
+    /// are more than `max_head_lag` confirmed blocks behind the head.
+    pub fn readiness(&self) -> Result<(), Vec<String>> {
+        self.check(|name, state| {
+            let status = state.status();
+            if !status.connected {
+                return Some(format!("network {}: not connected", name));
+            }
+            match status.lag() {
+                None => Some(format!("network {}: no block processed yet", name)),
+                Some(lag) if lag > self.config.max_head_lag => {
+                    Some(format!("network {}: {} blocks behind head", name, lag))
+                }
+                Some(_) => None,
+            }
+        })
+    }
+
+    fn check<F>(&self, failure: F) -> Result<(), Vec<String>>
+    where
+        F: Fn(&str, &SubscriberState) -> Option<String>,
+    {
+        let failures: Vec<String> = self
+            .states
+            .iter()
+            .filter_map(|(name, state)| failure(name, state))
+            .collect();
+        if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures)
+        }
+    }
+
+    pub fn respond(&self, path: &str) -> Response {
+        let result = match path {
+            "/healthz" => self.liveness(),
+            "/readyz" => self.readiness(),
+            _ => return Response::not_found(),
+        };
+        match result {
+            Ok(()) => Response::ok("text/plain", "ok\n".to_string()),
+            Err(failures) => Response::unavailable(failures.join("\n") + "\n"),
+        }
+    }
+}
+
+/// Serves `/healthz` and `/readyz` on `listener`.
+pub async fn serve_health(listener: TcpListener, check: HealthCheck) {
+    server::serve(listener, move |path| check.respond(path)).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(state: Arc<SubscriberState>, config: HealthConfig) -> HealthCheck {
+        HealthCheck::new(config, BTreeMap::from([("local".to_string(), state)]))
+    }
+
+    #[test]
+    fn test_ready_once_connected_and_caught_up() {
+        let state = Arc::new(SubscriberState::new());
+        let health = check(state.clone(), HealthConfig::default());
+        assert_eq!(
+            health.readiness(),
+            Err(vec!["network local: not connected".to_string()])
+        );
+
+        state.set_connected(true);
+        state.record_head(20);
+        assert_eq!(
+            health.readiness(),
+            Err(vec!["network local: no block processed yet".to_string()])
+        );
+
+        state.record_block(10);
+        assert_eq!(
+            health.readiness(),
+            Err(vec!["network local: 10 blocks behind head".to_string()])
+        );
+
+        state.record_block(15);
+        assert_eq!(health.readiness(), Ok(()));
+        assert_eq!(health.respond("/readyz").status, 200);
+    }
+
+    #[test]
+    fn test_not_live_without_recent_blocks() {
+        let state = Arc::new(SubscriberState::new());
+        let config = HealthConfig {
+            max_block_age_secs: 0,
+            ..HealthConfig::default()
+        };
+        let health = check(state.clone(), config);
+        std::thread::sleep(Duration::from_millis(5));
+        assert!(health.liveness().is_err());
+        assert_eq!(health.respond("/healthz").status, 503);
+
+        let health = check(state, HealthConfig::default());
+        assert_eq!(health.liveness(), Ok(()));
+        assert_eq!(health.respond("/healthz").status, 200);
+        assert_eq!(health.respond("/other").status, 404);
+    }
+}
@@ -1,3 +1,10 @@
 pub mod blockchain;
+pub mod cli;
 pub mod configuration;
+pub mod health;
+pub mod metrics;
+pub mod runtime;
+pub mod server;
+pub mod shutdown;
 pub mod utils;
+pub mod validation;
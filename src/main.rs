@@ -1,24 +1,68 @@
-use blockstream::blockchain::block::BlockSubscriber;
-use blockstream::blockchain::{
-    decoder::{Decoder, EthereumDecoder},
-    transaction::EthereumTransactionProcessor,
-};
 use blockstream::{
-    blockchain::{block::EthereumBlockSubscriber, connection},
-    configuration::load_config,
+    cli::CliArgs,
+    configuration::load_effective_config,
+    health::{serve_health, HealthCheck},
+    metrics::serve_metrics,
+    runtime::Runtime,
     utils::logger::initialize_logger,
+    validation::validate,
 };
-use log::{error, info};
 use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() {
-    if let Err(err) = initialize_logger() {
-        error!("Failed to initialize logger: {}", err);
+    let _logger = match initialize_logger() {
+        Ok(logger) => logger,
+        Err(err) => {
+            eprintln!("Failed to initialize logger: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let args = match CliArgs::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            error!("Invalid arguments: {}", err);
+            return;
+        }
+    };
+
+    let effective_config = match load_effective_config(args.profile.clone(), &args.overrides) {
+        Ok(effective_config) => effective_config,
+        Err(err) => {
+            error!("Failed to load configuration: {}", err);
+            return;
+        }
+    };
+
+    if args.print_effective_config {
+        println!("{}", effective_config.describe());
         return;
     }
 
-    let config = match load_config(None) {
+    let issues = validate(&effective_config);
+    if args.validate {
+        if issues.is_empty() {
+            println!("Configuration is valid");
+            return;
+        }
+        for issue in &issues {
+            println!("{}", issue);
+        }
+        std::process::exit(1);
+    }
+    if !issues.is_empty() {
+        for issue in &issues {
+            error!("Invalid configuration - {}", issue);
+        }
+        return;
+    }
+
+    let config = match effective_config.configuration() {
         Ok(config) => config,
         Err(err) => {
             error!("Failed to load configuration: {}", err);
@@ -30,45 +74,106 @@ async fn main() {
         info!("Configured subscription - {:?}", subscription);
     }
 
-    match connection::build_connection(&config).await {
-        Ok(connection) => {
-            let connection = Arc::new(connection);
-            let subscriber = EthereumBlockSubscriber::new(connection.clone());
-            let tx_processor = Arc::new(EthereumTransactionProcessor::new(
-                connection.clone(),
-                config.subscriptions,
-            ));
-
-            if let Err(err) = subscriber
-                .subscribe(move |block| {
-                    let tx_processor = tx_processor.clone();
-                    async move {
-                        if let Err(err) = tx_processor
-                            .process_transaction_logs(block, |event_filter, log| async move {
-                                match EthereumDecoder::new(event_filter.data_types)
-                                    .decode(&log.data().data)
-                                {
-                                    Ok(parameters) => {
-                                        info!("Event data output: {:?}", parameters);
-                                    }
-                                    Err(err) => {
-                                        error!("Error decoding event: {}", err);
-                                    }
-                                }
-                            })
-                            .await
-                        {
-                            error!("Error processing transaction logs: {}", err);
-                        }
-                    }
-                })
-                .await
-            {
-                error!("Error subscribing to blocks: {}", err);
+    if config.metrics.enabled {
+        match TcpListener::bind((config.metrics.host.as_str(), config.metrics.port)).await {
+            Ok(listener) => {
+                tokio::spawn(serve_metrics(listener));
+            }
+            Err(err) => {
+                error!("Failed to listen for metrics requests: {}", err);
+                return;
+            }
+        }
+    }
+
+    let health = config.health.clone();
+    let runtime = Arc::new(Runtime::new(config));
+
+    if health.enabled {
+        match TcpListener::bind((health.host.as_str(), health.port)).await {
+            Ok(listener) => {
+                tokio::spawn(serve_health(
+                    listener,
+                    HealthCheck::new(health, runtime.states()),
+                ));
+            }
+            Err(err) => {
+                error!("Failed to listen for health checks: {}", err);
+                return;
             }
         }
+    }
+
+    tokio::spawn(reload_on_hangup(runtime.clone(), args));
+    tokio::spawn(shutdown_on_signal(runtime.cancellation()));
+
+    if let Err(err) = runtime.run_with_timeout().await {
+        error!("{}, exiting", err);
+        std::process::exit(1);
+    }
+    info!("Shutdown complete");
+}
+
+/// Cancels `cancellation` on the first SIGINT or SIGTERM.
+async fn shutdown_on_signal(cancellation: CancellationToken) {
+    let mut terminations = match signal(SignalKind::terminate()) {
+        Ok(terminations) => terminations,
         Err(err) => {
-            error!("Error connecting to the blockchain: {}", err);
+            error!("Failed to listen for SIGTERM: {}", err);
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => info!("SIGINT received, shutting down"),
+        _ = terminations.recv() => info!("SIGTERM received, shutting down"),
+    }
+    cancellation.cancel();
+
+    if tokio::signal::ctrl_c().await.is_ok() {
+        warn!("Second SIGINT received, exiting immediately");
+        std::process::exit(130);
+    }
+}
+
+/// Reloads the subscriptions from the configuration files, environment and
+/// command line each time the process receives SIGHUP.
+async fn reload_on_hangup(runtime: Arc<Runtime>, args: CliArgs) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            error!("Failed to listen for SIGHUP, reload is disabled: {}", err);
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        info!("SIGHUP received, reloading configuration");
+        let effective_config = match load_effective_config(args.profile.clone(), &args.overrides) {
+            Ok(effective_config) => effective_config,
+            Err(err) => {
+                error!("Reload rejected, failed to load configuration: {}", err);
+                continue;
+            }
+        };
+        let issues = validate(&effective_config);
+        if !issues.is_empty() {
+            for issue in &issues {
+                error!("Reload rejected, invalid configuration - {}", issue);
+            }
+            continue;
+        }
+        match effective_config
+            .configuration()
+            .map_err(|err| err.to_string())
+            .and_then(|config| runtime.reload(config).map_err(|err| err.to_string()))
+        {
+            Ok(summary) => info!(
+                "Configuration reloaded: {} subscriptions added, {} removed",
+                summary.added.len(),
+                summary.removed.len()
+            ),
+            Err(err) => error!("Reload rejected: {}", err),
         }
     }
 }
@@ -0,0 +1,364 @@
+use crate::server::{self, Response};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+pub const BLOCKS_PROCESSED: &str = "blockstream_blocks_processed_total";
+pub const HEAD_LAG: &str = "blockstream_head_lag_blocks";
+pub const BLOCKS_SKIPPED: &str = "blockstream_blocks_skipped_total";
+pub const BLOCKS_PARKED: &str = "blockstream_blocks_parked";
+pub const REORGS: &str = "blockstream_reorgs_total";
+pub const RECONNECTS: &str = "blockstream_reconnects_total";
+pub const RPC_REQUESTS: &str = "blockstream_rpc_requests_total";
+pub const RPC_ERRORS: &str = "blockstream_rpc_errors_total";
+pub const RPC_LATENCY: &str = "blockstream_rpc_request_duration_seconds";
+pub const RPC_RATE_LIMITED: &str = "blockstream_rpc_rate_limited_total";
+pub const RETRIES: &str = "blockstream_retries_total";
+pub const RECEIPTS_FETCHED: &str = "blockstream_receipts_fetched_total";
+pub const EVENTS_MATCHED: &str = "blockstream_events_matched_total";
+pub const EVENTS_DECODED: &str = "blockstream_events_decoded_total";
+pub const EVENTS_FAILED: &str = "blockstream_events_failed_total";
+pub const SINK_WRITE_LATENCY: &str = "blockstream_sink_write_duration_seconds";
+pub const SINK_ERRORS: &str = "blockstream_sink_errors_total";
+
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+const DESCRIPTIONS: [(&str, Kind, &str); 17] = [
+    (BLOCKS_PROCESSED, Kind::Counter, "Blocks processed."),
+    (
+        HEAD_LAG,
+        Kind::Gauge,
+        "Latest confirmed block minus the last processed block.",
+    ),
+    (
+        BLOCKS_SKIPPED,
+        Kind::Counter,
+        "Blocks skipped after their retries were exhausted.",
+    ),
+    (BLOCKS_PARKED, Kind::Gauge, "Blocks waiting to be retried."),
+    (REORGS, Kind::Counter, "Chain reorganizations detected."),
+    (RECONNECTS, Kind::Counter, "Reconnections to the node."),
+    (
+        RPC_REQUESTS,
+        Kind::Counter,
+        "JSON-RPC requests sent, by network and method.",
+    ),
+    (
+        RPC_ERRORS,
+        Kind::Counter,
+        "JSON-RPC requests that failed, by network and method.",
+    ),
+    (
+        RPC_LATENCY,
+        Kind::Histogram,
+        "JSON-RPC request latency, by network and method.",
+    ),
+    (
+        RPC_RATE_LIMITED,
+        Kind::Counter,
+        "JSON-RPC requests rejected with HTTP 429, by network and method.",
+    ),
+    (
+        RETRIES,
+        Kind::Counter,
+        "Calls retried after a transient error, by operation.",
+    ),
+    (
+        RECEIPTS_FETCHED,
+        Kind::Counter,
+        "Transaction receipts fetched.",
+    ),
+    (
+        EVENTS_MATCHED,
+        Kind::Counter,
+        "Logs matched by a subscribed event.",
+    ),
+    (
+        EVENTS_DECODED,
+        Kind::Counter,
+        "Events decoded successfully.",
+    ),
+    (
+        EVENTS_FAILED,
+        Kind::Counter,
+        "Events and function calls that failed to decode.",
+    ),
+    (
+        SINK_WRITE_LATENCY,
+        Kind::Histogram,
+        "Time spent writing decoded output to a sink.",
+    ),
+    (
+        SINK_ERRORS,
+        Kind::Counter,
+        "Writes and flushes that failed, by sink.",
+    ),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl Kind {
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+            Kind::Histogram => "histogram",
+        }
+    }
+}
+
+/// Where the `/metrics` endpoint listens. Disabled unless `enabled` is set.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct MetricsConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "default_host")]
+    pub host: String,
+    #[serde(default = "default_port")]
+    pub port: u16,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            host: default_host(),
+            port: default_port(),
+        }
+    }
+}
+
+fn default_host() -> String {
+    "0.0.0.0".to_string()
+}
+
+fn default_port() -> u16 {
+    9090
+}
+
+/// Serves the registry at `/metrics` on `listener`.
+pub async fn serve_metrics(listener: TcpListener) {
+    server::serve(listener, |path| match path {
+        "/metrics" => Response::ok("text/plain; version=0.0.4", metrics().render()),
+        _ => Response::not_found(),
+    })
+    .await
+}
+
+type Labels = Vec<(String, String)>;
+
+#[derive(Debug, Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+/// In-memory metric values, rendered in the Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<BTreeMap<(&'static str, Labels), u64>>,
+    gauges: Mutex<BTreeMap<(&'static str, Labels), i64>>,
+    histograms: Mutex<BTreeMap<(&'static str, Labels), Histogram>>,
+}
+
+/// The process-wide registry every component records into.
+pub fn metrics() -> &'static Metrics {
+    static METRICS: OnceLock<Metrics> = OnceLock::new();
+    METRICS.get_or_init(Metrics::default)
+}
+
+impl Metrics {
+    pub fn increment(&self, name: &'static str, labels: &[(&str, &str)]) {
+        self.add(name, labels, 1);
+    }
+
+    pub fn add(&self, name: &'static str, labels: &[(&str, &str)], value: u64) {
+        let mut counters = self.counters.lock().unwrap_or_else(|err| err.into_inner());
+        *counters.entry((name, owned(labels))).or_default() += value;
+    }
+
+    pub fn set(&self, name: &'static str, labels: &[(&str, &str)], value: i64) {
+        let mut gauges = self.gauges.lock().unwrap_or_else(|err| err.into_inner());
+        gauges.insert((name, owned(labels)), value);
+    }
+
+    pub fn observe(&self, name: &'static str, labels: &[(&str, &str)], duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let mut histograms = self
+            .histograms
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+        let histogram = histograms.entry((name, owned(labels))).or_default();
+        for (bucket, bound) in histogram.buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        histogram.count += 1;
+        histogram.sum += seconds;
+    }
+
+    /// Current value of a counter, or 0 if it was never incremented.
+    pub fn counter(&self, name: &'static str, labels: &[(&str, &str)]) -> u64 {
+        let counters = self.counters.lock().unwrap_or_else(|err| err.into_inner());
+        counters
+            .get(&(name, owned(labels)))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    pub fn gauge(&self, name: &'static str, labels: &[(&str, &str)]) -> Option<i64> {
+        let gauges = self.gauges.lock().unwrap_or_else(|err| err.into_inner());
+        gauges.get(&(name, owned(labels))).copied()
+    }
+
+    pub fn render(&self) -> String {
+        let counters = self.counters.lock().unwrap_or_else(|err| err.into_inner());
+        let gauges = self.gauges.lock().unwrap_or_else(|err| err.into_inner());
+        let histograms = self
+            .histograms
+            .lock()
+            .unwrap_or_else(|err| err.into_inner());
+
+        let mut output = String::new();
+        for (name, kind, help) in DESCRIPTIONS {
+            let _ = writeln!(output, "# HELP {} {}", name, help);
+            let _ = writeln!(output, "# TYPE {} {}", name, kind.name());
+            match kind {
+                Kind::Counter => {
+                    for ((_, labels), value) in
+                        counters.iter().filter(|((metric, _), _)| *metric == name)
+                    {
+                        let _ = writeln!(output, "{}{} {}", name, format_labels(labels), value);
+                    }
+                }
+                Kind::Gauge => {
+                    for ((_, labels), value) in
+                        gauges.iter().filter(|((metric, _), _)| *metric == name)
+                    {
+                        let _ = writeln!(output, "{}{} {}", name, format_labels(labels), value);
+                    }
+                }
+                Kind::Histogram => {
+                    for ((_, labels), histogram) in
+                        histograms.iter().filter(|((metric, _), _)| *metric == name)
+                    {
+                        for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+                            let mut bucket_labels = labels.clone();
+                            bucket_labels.push(("le".to_string(), bound.to_string()));
+                            let _ = writeln!(
+                                output,
+                                "{}_bucket{} {}",
+                                name,
+                                format_labels(&bucket_labels),
+                                count
+                            );
+                        }
+                        let mut bucket_labels = labels.clone();
+                        bucket_labels.push(("le".to_string(), "+Inf".to_string()));
+                        let _ = writeln!(
+                            output,
+                            "{}_bucket{} {}",
+                            name,
+                            format_labels(&bucket_labels),
+                            histogram.count
+                        );
+                        let labels = format_labels(labels);
+                        let _ = writeln!(output, "{}_sum{} {}", name, labels, histogram.sum);
+                        let _ = writeln!(output, "{}_count{} {}", name, labels, histogram.count);
+                    }
+                }
+            }
+        }
+        output
+    }
+}
+
+fn owned(labels: &[(&str, &str)]) -> Labels {
+    labels
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+fn format_labels(labels: &Labels) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect();
+    format!("{{{}}}", labels.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_counters_and_gauges() {
+        let metrics = Metrics::default();
+        metrics.increment(BLOCKS_PROCESSED, &[("network", "local")]);
+        metrics.add(BLOCKS_PROCESSED, &[("network", "local")], 2);
+        metrics.set(HEAD_LAG, &[("network", "local")], 3);
+
+        let output = metrics.render();
+        assert!(output.contains("# TYPE blockstream_blocks_processed_total counter\n"));
+        assert!(output.contains("blockstream_blocks_processed_total{network=\"local\"} 3\n"));
+        assert!(output.contains("blockstream_head_lag_blocks{network=\"local\"} 3\n"));
+        assert_eq!(
+            metrics.counter(BLOCKS_PROCESSED, &[("network", "local")]),
+            3
+        );
+    }
+
+    #[test]
+    fn test_render_histogram() {
+        let metrics = Metrics::default();
+        metrics.observe(
+            RPC_LATENCY,
+            &[("method", "eth_call")],
+            Duration::from_millis(30),
+        );
+
+        let output = metrics.render();
+        assert!(output.contains(
+            "blockstream_rpc_request_duration_seconds_bucket{method=\"eth_call\",le=\"0.025\"} 0\n"
+        ));
+        assert!(output.contains(
+            "blockstream_rpc_request_duration_seconds_bucket{method=\"eth_call\",le=\"0.05\"} 1\n"
+        ));
+        assert!(output.contains(
+            "blockstream_rpc_request_duration_seconds_bucket{method=\"eth_call\",le=\"+Inf\"} 1\n"
+        ));
+        assert!(output
+            .contains("blockstream_rpc_request_duration_seconds_count{method=\"eth_call\"} 1\n"));
+    }
+
+    #[test]
+    fn test_label_values_are_escaped() {
+        let metrics = Metrics::default();
+        metrics.increment(EVENTS_MATCHED, &[("event", "a\"b")]);
+        assert!(metrics
+            .render()
+            .contains("blockstream_events_matched_total{event=\"a\\\"b\"} 1\n"));
+    }
+}
@@ -0,0 +1,764 @@
+use crate::blockchain::{
+    block::{
+        BlockEvent, BlockSubscriber, EthereumBlockSubscriber, SubscriberState, SubscriptionError,
+    },
+    configuration::{Configuration, EventSubscription, Network},
+    connection::{build_connection, rate_limit_layer, ConnectionError},
+    rate_limit::RateLimitLayer,
+    retry::{OnExhaustion, Transient},
+    transaction::{EthereumTransactionProcessor, SharedSubscriptions, TransactionError},
+};
+use crate::metrics::{
+    metrics, BLOCKS_PARKED, BLOCKS_SKIPPED, RECONNECTS, SINK_ERRORS, SINK_WRITE_LATENCY,
+};
+use crate::shutdown::{load_checkpoint, save_checkpoint, ShutdownConfig, ShutdownError};
+use alloy::{
+    eips::BlockNumberOrTag,
+    primitives::B256,
+    providers::{Provider, RootProvider},
+    rpc::types::{Block, BlockTransactionsKind},
+    transports::{BoxTransport, TransportError},
+};
+use futures_util::StreamExt;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, Write};
+use std::pin::pin;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+use tracing::{error, info, info_span, warn, Instrument};
+
+const INITIAL_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum RuntimeError {
+    #[error("Connection error: {0}")]
+    ConnectionError(#[from] ConnectionError),
+    #[error("Transport error: {0}")]
+    TransportError(#[from] TransportError),
+    #[error("Subscription error: {0}")]
+    SubscriptionError(#[from] SubscriptionError),
+    #[error("Block {number} could not be processed: {source}")]
+    BlockFailed {
+        number: u64,
+        source: TransactionError,
+    },
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReloadError {
+    #[error("Network '{0}' was added, networks can only be changed with a restart")]
+    NetworkAdded(String),
+    #[error("Network '{0}' was removed, networks can only be changed with a restart")]
+    NetworkRemoved(String),
+    #[error("Network '{0}' was modified, networks can only be changed with a restart")]
+    NetworkChanged(String),
+}
+
+/// Subscriptions added and removed by a reload.
+#[derive(Debug, Default)]
+pub struct ReloadSummary {
+    pub added: Vec<EventSubscription>,
+    pub removed: Vec<EventSubscription>,
+}
+
+/// The configured networks, the subscriptions currently followed on each of
+/// them and the progress of their subscribers.
+pub struct Runtime {
+    networks: BTreeMap<String, Network>,
+    rate_limits: BTreeMap<String, RateLimitLayer>,
+    subscriptions: BTreeMap<String, SharedSubscriptions>,
+    states: BTreeMap<String, Arc<SubscriberState>>,
+    shutdown: ShutdownConfig,
+    cancellation: CancellationToken,
+    tasks: TaskTracker,
+}
+
+impl Runtime {
+    pub fn new(config: Configuration) -> Self {
+        let subscriptions = config
+            .networks
+            .keys()
+            .map(|name| {
+                let subscriptions = SharedSubscriptions::new(config.subscriptions_for(name));
+                (name.clone(), subscriptions)
+            })
+            .collect();
+        let rate_limits = config
+            .networks
+            .iter()
+            .filter_map(|(name, network)| Some((name.clone(), rate_limit_layer(name, network)?)))
+            .collect();
+        let states: BTreeMap<String, Arc<SubscriberState>> = config
+            .networks
+            .keys()
+            .map(|name| (name.clone(), Arc::new(SubscriberState::new())))
+            .collect();
+        if let Some(path) = config
+            .shutdown
+            .checkpoint_path
+            .as_ref()
+            .filter(|path| path.exists())
+        {
+            match load_checkpoint(path) {
+                Ok(blocks) => {
+                    for (name, number) in blocks {
+                        if let Some(state) = states.get(&name) {
+                            state.record_checkpoint(number, None);
+                        }
+                    }
+                }
+                Err(err) => error!("Failed to read checkpoint {}: {}", path.display(), err),
+            }
+        }
+        Self {
+            networks: config.networks,
+            rate_limits,
+            subscriptions,
+            states,
+            shutdown: config.shutdown,
+            cancellation: CancellationToken::new(),
+            tasks: TaskTracker::new(),
+        }
+    }
+
+    /// Shuts down when `cancellation` is cancelled instead of a private token.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Cancelling this token shuts the runtime down: no new blocks are taken,
+    /// blocks being processed are finished and [`Runtime::run`] returns once
+    /// the sinks are flushed and the checkpoint is written.
+    pub fn cancellation(&self) -> CancellationToken {
+        self.cancellation.clone()
+    }
+
+    /// Progress of the subscriber of each network, kept across restarts.
+    pub fn states(&self) -> BTreeMap<String, Arc<SubscriberState>> {
+        self.states.clone()
+    }
+
+    /// Subscriptions currently followed on the network called `name`.
+    pub fn subscriptions(&self, name: &str) -> Vec<EventSubscription> {
+        self.subscriptions
+            .get(name)
+            .map(|subscriptions| subscriptions.load().to_vec())
+            .unwrap_or_default()
+    }
+
+    /// Runs one supervised subscriber per configured network concurrently.
+    /// Only returns once the runtime is cancelled and every subscriber and
+    /// backfill has stopped.
+    pub async fn run(&self) {
+        let mut handles = Vec::new();
+        for (name, network) in &self.networks {
+            let subscriptions = self.subscriptions[name].clone();
+            handles.push(
+                self.tasks.spawn(
+                    supervise(
+                        name.clone(),
+                        network.clone(),
+                        self.rate_limits.get(name).cloned(),
+                        subscriptions,
+                        self.states[name].clone(),
+                        self.cancellation.clone(),
+                    )
+                    .instrument(info_span!("network", network = %name)),
+                ),
+            );
+        }
+
+        for handle in handles {
+            if let Err(err) = handle.await {
+                error!("Network task failed: {}", err);
+            }
+        }
+        self.tasks.close();
+        self.tasks.wait().await;
+
+        info!("All networks stopped, flushing sinks");
+        if let Err(err) = flush_log() {
+            error!("Failed to flush the log: {}", err);
+        }
+        self.write_checkpoint();
+    }
+
+    /// Runs like [`Runtime::run`], but gives the drain after cancellation at
+    /// most the configured shutdown timeout. When it takes longer, the sinks
+    /// are flushed and the checkpoint of the blocks processed so far is
+    /// written before returning, leaving the remaining blocks unfinished.
+    pub async fn run_with_timeout(self: Arc<Self>) -> Result<(), ShutdownError> {
+        let cancellation = self.cancellation();
+        let mut running = tokio::spawn({
+            let runtime = self.clone();
+            async move { runtime.run().await }
+        });
+        tokio::select! {
+            _ = &mut running => return Ok(()),
+            _ = cancellation.cancelled() => {}
+        }
+        let timeout = self.shutdown.timeout();
+        if tokio::time::timeout(timeout, running).await.is_err() {
+            warn!(
+                "Shutdown did not complete within {:?}, flushing sinks",
+                timeout
+            );
+            if let Err(err) = flush_log() {
+                error!("Failed to flush the log: {}", err);
+            }
+            self.write_checkpoint();
+            return Err(ShutdownError::TimedOut(timeout));
+        }
+        Ok(())
+    }
+
+    /// Writes the checkpoint of each network to the configured checkpoint
+    /// file, if any.
+    fn write_checkpoint(&self) {
+        let Some(path) = &self.shutdown.checkpoint_path else {
+            return;
+        };
+        let blocks = self
+            .states
+            .iter()
+            .filter_map(|(name, state)| Some((name.clone(), state.status().checkpoint?)))
+            .collect();
+        match save_checkpoint(path, &blocks) {
+            Ok(()) => info!("Checkpoint written to {}", path.display()),
+            Err(err) => error!("Failed to write checkpoint to {}: {}", path.display(), err),
+        }
+    }
+
+    /// Replaces the running subscriptions with those of `config`. Blocks
+    /// already being processed finish with the previous set. Added
+    /// subscriptions with a `start_block` are backfilled in the background up
+    /// to the current head, so blocks around the reload may be delivered to
+    /// them twice. Nothing is applied when the networks differ.
+    pub fn reload(&self, config: Configuration) -> Result<ReloadSummary, ReloadError> {
+        for (name, network) in &self.networks {
+            match config.networks.get(name) {
+                Some(new_network) if new_network == network => {}
+                Some(_) => return Err(ReloadError::NetworkChanged(name.clone())),
+                None => return Err(ReloadError::NetworkRemoved(name.clone())),
+            }
+        }
+        if let Some(name) = config
+            .networks
+            .keys()
+            .find(|name| !self.networks.contains_key(*name))
+        {
+            return Err(ReloadError::NetworkAdded(name.clone()));
+        }
+
+        let mut summary = ReloadSummary::default();
+        for (name, network) in &self.networks {
+            let current = self.subscriptions[name].load();
+            let subscriptions = config.subscriptions_for(name);
+            let added: Vec<EventSubscription> = subscriptions
+                .iter()
+                .filter(|subscription| !current.contains(subscription))
+                .cloned()
+                .collect();
+            summary.removed.extend(
+                current
+                    .iter()
+                    .filter(|subscription| !subscriptions.contains(subscription))
+                    .cloned(),
+            );
+
+            self.subscriptions[name].store(subscriptions);
+
+            let backfills: Vec<EventSubscription> = added
+                .iter()
+                .filter(|subscription| subscription.start_block.is_some())
+                .cloned()
+                .collect();
+            if !backfills.is_empty() {
+                let name = name.clone();
+                let network = network.clone();
+                let rate_limit = self.rate_limits.get(&name).cloned();
+                let cancellation = self.cancellation.clone();
+                let span = info_span!("backfill", network = %name);
+                self.tasks.spawn(
+                    async move {
+                        if let Err(err) =
+                            backfill(&name, &network, rate_limit, backfills, &cancellation).await
+                        {
+                            error!("Backfill on network {} failed: {}", name, err);
+                        }
+                    }
+                    .instrument(span),
+                );
+            }
+            summary.added.extend(added);
+        }
+
+        for subscription in &summary.added {
+            info!("Subscription added - {:?}", subscription);
+        }
+        for subscription in &summary.removed {
+            info!("Subscription removed - {:?}", subscription);
+        }
+        Ok(summary)
+    }
+}
+
+/// Restarts the subscriber for `name` whenever it fails or its stream ends,
+/// backing off exponentially while it keeps failing quickly, until
+/// `cancellation` is cancelled.
+async fn supervise(
+    name: String,
+    network: Network,
+    rate_limit: Option<RateLimitLayer>,
+    subscriptions: SharedSubscriptions,
+    state: Arc<SubscriberState>,
+    cancellation: CancellationToken,
+) {
+    let mut delay = INITIAL_RESTART_DELAY;
+    loop {
+        let started = Instant::now();
+        let result = run_network(
+            &name,
+            &network,
+            rate_limit.clone(),
+            subscriptions.clone(),
+            state.clone(),
+            cancellation.clone(),
+        )
+        .await;
+        if cancellation.is_cancelled() {
+            info!("Network {} stopped", name);
+            return;
+        }
+        match result {
+            // A block, or the subscription's fetch of one, still failing once
+            // out of retries.
+            Err(err @ (RuntimeError::BlockFailed { .. } | RuntimeError::SubscriptionError(_)))
+                if network.retry.on_exhaustion == OnExhaustion::Halt =>
+            {
+                error!("Halting network {}: {}", name, err);
+                state.set_connected(false);
+                return;
+            }
+            Ok(()) => warn!("Block subscription for network {} ended", name),
+            Err(err) => error!("Error processing network {}: {}", name, err),
+        }
+
+        if started.elapsed() >= MAX_RESTART_DELAY {
+            delay = INITIAL_RESTART_DELAY;
+        }
+        info!("Restarting network {} in {:?}", name, delay);
+        tokio::select! {
+            _ = cancellation.cancelled() => {
+                info!("Network {} stopped", name);
+                return;
+            }
+            _ = tokio::time::sleep(delay) => {}
+        }
+        metrics().increment(RECONNECTS, &[("network", name.as_str())]);
+        delay = (delay * 2).min(MAX_RESTART_DELAY);
+    }
+}
+
+/// Connects to a single network and processes its blocks until the
+/// subscription ends, fails or is cancelled, reporting its progress into
+/// `state`. Requests are throttled through `rate_limit`, if any.
+pub async fn run_network(
+    name: &str,
+    network: &Network,
+    rate_limit: Option<RateLimitLayer>,
+    subscriptions: SharedSubscriptions,
+    state: Arc<SubscriberState>,
+    cancellation: CancellationToken,
+) -> Result<(), RuntimeError> {
+    let provider = Arc::new(build_connection(name, network, rate_limit).await?);
+    let chain_id = provider.get_chain_id().await?;
+    info!("Connected to network {} with chain ID {}", name, chain_id);
+
+    let mut subscriber = EthereumBlockSubscriber::new(provider.clone())
+        .with_confirmations(network.confirmations)
+        .with_network(name.to_string())
+        .with_state(state.clone())
+        .with_cancellation(cancellation)
+        .with_retry_policy(network.retry.clone())
+        .with_poll_mode(network.poll_mode);
+    if let Some(poll_interval) = network.poll_interval {
+        subscriber = subscriber.with_poll_interval(poll_interval);
+    }
+    let status = state.status();
+    if let Some(number) = status.checkpoint {
+        let hash = match status.checkpoint_hash {
+            Some(hash) => hash,
+            None => fetch_block(&provider, network, number).await?.header.hash,
+        };
+        info!("Resuming network {} after block {}", name, number);
+        subscriber = subscriber.with_last_block(number, hash);
+    }
+    let tx_processor =
+        EthereumTransactionProcessor::with_shared_subscriptions(provider, subscriptions)
+            .with_chain_id(chain_id)
+            .with_network(name.to_string())
+            .with_receipt_concurrency(network.receipt_concurrency)
+            .with_retry_policy(network.retry.clone());
+    let mut blocks = BlockRunner::new(tx_processor, network.retry.on_exhaustion).with_state(state);
+
+    let result = async {
+        let mut events = pin!(subscriber.blocks());
+        while let Some(event) = events.next().await {
+            match event? {
+                BlockEvent::NewBlock(block) => blocks.process(*block).await?,
+                BlockEvent::Rollback { from, to } => blocks.rollback(from, to),
+                BlockEvent::Reconnected => {}
+            }
+        }
+        Ok(())
+    }
+    .await;
+    blocks.report_parked();
+    result
+}
+
+/// Processes the confirmed blocks from the lowest `start_block` up to the
+/// current head for `subscriptions` only. Each subscription sees the blocks
+/// from its own `start_block` on. Stops between blocks once `cancellation`
+/// is cancelled. Requests are throttled through `rate_limit`, if any.
+pub async fn backfill(
+    name: &str,
+    network: &Network,
+    rate_limit: Option<RateLimitLayer>,
+    subscriptions: Vec<EventSubscription>,
+    cancellation: &CancellationToken,
+) -> Result<(), RuntimeError> {
+    let Some(start_block) = subscriptions
+        .iter()
+        .filter_map(|subscription| subscription.start_block)
+        .min()
+    else {
+        return Ok(());
+    };
+
+    let provider = Arc::new(build_connection(name, network, rate_limit).await?);
+    let chain_id = provider.get_chain_id().await?;
+    let head = provider
+        .get_block_number()
+        .await?
+        .saturating_sub(network.confirmations);
+    info!(
+        "Backfilling network {} from block {} to {}",
+        name, start_block, head
+    );
+
+    let active = SharedSubscriptions::default();
+    let tx_processor =
+        EthereumTransactionProcessor::with_shared_subscriptions(provider.clone(), active.clone())
+            .with_chain_id(chain_id)
+            .with_network(name.to_string())
+            .with_receipt_concurrency(network.receipt_concurrency)
+            .with_retry_policy(network.retry.clone());
+    let mut blocks = BlockRunner::new(tx_processor, network.retry.on_exhaustion);
+    for number in start_block..=head {
+        if cancellation.is_cancelled() {
+            info!("Backfill of network {} stopped at block {}", name, number);
+            blocks.report_parked();
+            return Ok(());
+        }
+        active.store(
+            subscriptions
+                .iter()
+                .filter(|subscription| subscription.start_block <= Some(number))
+                .cloned()
+                .collect(),
+        );
+        let block = fetch_block(&provider, network, number).await?;
+        blocks.process(block).await?;
+    }
+    blocks.retry_parked().await;
+    blocks.report_parked();
+
+    info!("Backfill of network {} complete", name);
+    Ok(())
+}
+
+async fn fetch_block(
+    provider: &RootProvider<BoxTransport>,
+    network: &Network,
+    number: u64,
+) -> Result<Block, SubscriptionError> {
+    network
+        .retry
+        .retry("fetch_block", || async {
+            provider
+                .get_block_by_number(
+                    BlockNumberOrTag::Number(number),
+                    BlockTransactionsKind::Full,
+                )
+                .await?
+                .ok_or(SubscriptionError::BlockNotFoundForNumber(number))
+        })
+        .await
+}
+
+/// Processes the blocks of one network, applying the `on_exhaustion`
+/// behaviour of its retry policy to the blocks that still fail.
+struct BlockRunner {
+    tx_processor: EthereumTransactionProcessor,
+    on_exhaustion: OnExhaustion,
+    /// Blocks that failed with a transient error, oldest first.
+    parked: VecDeque<Block>,
+    /// Where the checkpoint is recorded, if anywhere.
+    state: Option<Arc<SubscriberState>>,
+    /// Number and, unless moved back by a rollback, hash of the last block
+    /// processed successfully.
+    last_processed: Option<(u64, Option<B256>)>,
+}
+
+impl BlockRunner {
+    fn new(tx_processor: EthereumTransactionProcessor, on_exhaustion: OnExhaustion) -> Self {
+        Self {
+            tx_processor,
+            on_exhaustion,
+            parked: VecDeque::new(),
+            state: None,
+            last_processed: None,
+        }
+    }
+
+    /// Records the checkpoint of the blocks processed into `state`.
+    fn with_state(mut self, state: Arc<SubscriberState>) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    /// Processes `block` after retrying the parked blocks. Fails only when
+    /// the block fails and the network is configured to halt.
+    async fn process(&mut self, block: Block) -> Result<(), RuntimeError> {
+        self.retry_parked().await;
+        let number = block.header.number;
+        let Err(err) = process_block(&self.tx_processor, &block).await else {
+            self.record_processed(&block);
+            return Ok(());
+        };
+        match self.on_exhaustion {
+            OnExhaustion::Halt => {
+                return Err(RuntimeError::BlockFailed {
+                    number,
+                    source: err,
+                });
+            }
+            // Retrying cannot help with permanent errors, so these are skipped.
+            OnExhaustion::Park if err.is_transient() => {
+                warn!(
+                    "Parking block {} on network {} to retry it later: {}",
+                    number,
+                    self.tx_processor.network(),
+                    err
+                );
+                self.parked.push_back(block);
+                self.record_parked();
+            }
+            _ => self.skip(number, &err),
+        }
+        Ok(())
+    }
+
+    /// Tries each parked block once more, keeping those that still fail
+    /// with a transient error.
+    async fn retry_parked(&mut self) {
+        if self.parked.is_empty() {
+            return;
+        }
+        for block in std::mem::take(&mut self.parked) {
+            let number = block.header.number;
+            match process_block(&self.tx_processor, &block).await {
+                Ok(()) => {
+                    info!(
+                        "Parked block {} on network {} processed",
+                        number,
+                        self.tx_processor.network()
+                    );
+                    self.record_processed(&block);
+                }
+                Err(err) if err.is_transient() => {
+                    warn!(
+                        "Parked block {} on network {} failed again: {}",
+                        number,
+                        self.tx_processor.network(),
+                        err
+                    );
+                    self.parked.push_back(block);
+                }
+                Err(err) => self.skip(number, &err),
+            }
+        }
+        self.record_parked();
+    }
+
+    /// Drops the parked blocks in `from..=to`, which are no longer part of
+    /// the canonical chain, and moves the checkpoint back before them.
+    fn rollback(&mut self, from: u64, to: u64) {
+        self.parked
+            .retain(|block| !(from..=to).contains(&block.header.number));
+        self.record_parked();
+        if let Some((number, hash)) = &mut self.last_processed {
+            if *number >= from {
+                *number = from.saturating_sub(1);
+                *hash = None;
+            }
+        }
+        self.record_checkpoint();
+    }
+
+    fn record_processed(&mut self, block: &Block) {
+        let number = block.header.number;
+        if self.last_processed.is_none_or(|(last, _)| number > last) {
+            self.last_processed = Some((number, Some(block.header.hash)));
+        }
+        self.record_checkpoint();
+    }
+
+    /// Moves the checkpoint to the last block processed, but never past a
+    /// parked block. Skipped blocks do not move it on their own.
+    fn record_checkpoint(&self) {
+        let (Some(state), Some((last, hash))) = (&self.state, self.last_processed) else {
+            return;
+        };
+        match self.parked.front() {
+            Some(block) if block.header.number <= last => state.record_checkpoint(
+                block.header.number.saturating_sub(1),
+                Some(block.header.parent_hash),
+            ),
+            _ => state.record_checkpoint(last, hash),
+        }
+    }
+
+    fn skip(&self, number: u64, err: &TransactionError) {
+        error!(
+            "Skipping block {} on network {}: {}",
+            number,
+            self.tx_processor.network(),
+            err
+        );
+        metrics().increment(BLOCKS_SKIPPED, &[("network", self.tx_processor.network())]);
+    }
+
+    fn record_parked(&self) {
+        metrics().set(
+            BLOCKS_PARKED,
+            &[("network", self.tx_processor.network())],
+            self.parked.len() as i64,
+        );
+    }
+
+    /// Logs the blocks left parked when processing stops.
+    fn report_parked(&self) {
+        if self.parked.is_empty() {
+            return;
+        }
+        let numbers: Vec<u64> = self
+            .parked
+            .iter()
+            .map(|block| block.header.number)
+            .collect();
+        error!(
+            "Blocks {:?} on network {} were parked and never processed",
+            numbers,
+            self.tx_processor.network()
+        );
+    }
+}
+
+/// Decodes the subscribed events, calls and reverts of `block` within a span
+/// carrying its network, number and hash. The span is the root of its own
+/// trace so that each block is exported on its own.
+async fn process_block(
+    tx_processor: &EthereumTransactionProcessor,
+    block: &Block,
+) -> Result<(), TransactionError> {
+    let network = tx_processor.network();
+    let span = info_span!(
+        parent: None,
+        "block",
+        network = network,
+        number = block.header.number,
+        hash = %block.header.hash
+    );
+    tx_processor
+        .process_transactions(
+            block,
+            |event| async move {
+                write_to_log(|| {
+                    info!(
+                        "Event {} output: {:?} (chain ID: {}, block: {}, transaction: {}, log index: {:?}, address: {})",
+                        event.event_name,
+                        event.parameters,
+                        event.chain_id,
+                        event.block_number,
+                        event.transaction_hash,
+                        event.log_index,
+                        event.address
+                    )
+                });
+            },
+            |function_filter, function_call| async move {
+                write_to_log(|| {
+                    info!(
+                        "Function {} output: {:?} (chain ID: {}, block: {}, transaction: {}, from: {}, value: {}, success: {})",
+                        function_filter.function_name,
+                        function_call.parameters,
+                        function_call.chain_id,
+                        function_call.block_number,
+                        function_call.transaction_hash,
+                        function_call.from,
+                        function_call.value,
+                        function_call.success
+                    )
+                });
+            },
+            |failed_transaction| async move {
+                write_to_log(|| {
+                    warn!(
+                        "Transaction {} reverted on chain {}: {:?}",
+                        failed_transaction.transaction_hash,
+                        failed_transaction.chain_id,
+                        failed_transaction.revert_reason
+                    )
+                });
+            },
+        )
+        .instrument(span)
+        .await
+}
+
+/// Writes decoded output to the log, which is currently the only sink.
+fn write_to_log(write: impl FnOnce()) {
+    let _ = write_sink("log", || {
+        write();
+        Ok(())
+    });
+}
+
+/// Flushes the output written by [`write_to_log`], which goes to standard
+/// output.
+fn flush_log() -> io::Result<()> {
+    flush_sink("log", &mut io::stdout())
+}
+
+/// Writes decoded output to the sink called `sink`, recording how long it
+/// took and counting a failure.
+pub fn write_sink(sink: &str, write: impl FnOnce() -> io::Result<()>) -> io::Result<()> {
+    let started = Instant::now();
+    let result = info_span!("sink_write", sink).in_scope(write);
+    metrics().observe(SINK_WRITE_LATENCY, &[("sink", sink)], started.elapsed());
+    result.inspect_err(|_| metrics().increment(SINK_ERRORS, &[("sink", sink)]))
+}
+
+/// Flushes `writer`, the output of the sink called `sink`, counting a
+/// failure.
+pub fn flush_sink(sink: &str, writer: &mut impl Write) -> io::Result<()> {
+    info_span!("sink_flush", sink)
+        .in_scope(|| writer.flush())
+        .inspect_err(|_| metrics().increment(SINK_ERRORS, &[("sink", sink)]))
+}
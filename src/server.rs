@@ -0,0 +1,113 @@
+use std::sync::Arc;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{error, info};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub body: String,
+}
+
+impl Response {
+    pub fn ok(content_type: &'static str, body: String) -> Self {
+        Self {
+            status: 200,
+            content_type,
+            body,
+        }
+    }
+
+    pub fn not_found() -> Self {
+        Self {
+            status: 404,
+            content_type: "text/plain",
+            body: "Not Found\n".to_string(),
+        }
+    }
+
+    pub fn unavailable(body: String) -> Self {
+        Self {
+            status: 503,
+            content_type: "text/plain",
+            body,
+        }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            503 => "Service Unavailable",
+            _ => "",
+        }
+    }
+}
+
+/// Serves plain HTTP/1.1 GET requests on `listener`, answering each with
+/// `handler` called with the request path. Connections are closed after
+/// every response; this is meant for scrapes and probes, not general use.
+pub async fn serve<H>(listener: TcpListener, handler: H)
+where
+    H: Fn(&str) -> Response + Send + Sync + 'static,
+{
+    if let Ok(address) = listener.local_addr() {
+        info!("Listening for HTTP requests on {}", address);
+    }
+    let handler = Arc::new(handler);
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let handler = handler.clone();
+                tokio::spawn(async move {
+                    if let Err(err) = respond(stream, &*handler).await {
+                        error!("Error answering HTTP request: {}", err);
+                    }
+                });
+            }
+            Err(err) => error!("Error accepting HTTP connection: {}", err),
+        }
+    }
+}
+
+async fn respond<H>(stream: TcpStream, handler: &H) -> std::io::Result<()>
+where
+    H: Fn(&str) -> Response,
+{
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(target)) => {
+            handler(target.split_once('?').map_or(target, |(path, _)| path))
+        }
+        _ => Response {
+            status: 405,
+            content_type: "text/plain",
+            body: "Method Not Allowed\n".to_string(),
+        },
+    };
+
+    let reply = format!(
+        "HTTP/1.1 {} {}\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len(),
+        response.body
+    );
+    writer.write_all(reply.as_bytes()).await?;
+    writer.shutdown().await
+}
@@ -0,0 +1,78 @@
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{fs, io};
+
+#[derive(Debug, thiserror::Error)]
+pub enum CheckpointError {
+    #[error("Error accessing checkpoint file: {0}")]
+    Io(#[from] io::Error),
+    #[error("Invalid checkpoint file: {0}")]
+    Invalid(#[from] serde_yaml::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ShutdownError {
+    #[error("Shutdown did not complete within {0:?}")]
+    TimedOut(Duration),
+}
+
+/// How long a shutdown may take, and where the last processed block of each
+/// network is written once it has drained or the timeout has expired.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ShutdownConfig {
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    #[serde(default)]
+    pub checkpoint_path: Option<PathBuf>,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            timeout_secs: default_timeout_secs(),
+            checkpoint_path: None,
+        }
+    }
+}
+
+impl ShutdownConfig {
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout_secs)
+    }
+}
+
+fn default_timeout_secs() -> u64 {
+    30
+}
+
+/// Writes the last processed block of each network to `path`, replacing the
+/// previous checkpoint only once the new one is complete.
+pub fn save_checkpoint(path: &Path, blocks: &BTreeMap<String, u64>) -> Result<(), CheckpointError> {
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, serde_yaml::to_string(blocks)?)?;
+    fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// Reads a checkpoint written by [`save_checkpoint`].
+pub fn load_checkpoint(path: &Path) -> Result<BTreeMap<String, u64>, CheckpointError> {
+    Ok(serde_yaml::from_str(&fs::read_to_string(path)?)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let path = std::env::temp_dir().join(format!("checkpoint-{}.yml", std::process::id()));
+        let blocks = BTreeMap::from([("arbitrum".to_string(), 7), ("mainnet".to_string(), 42)]);
+
+        save_checkpoint(&path, &blocks).unwrap();
+        assert_eq!(load_checkpoint(&path).unwrap(), blocks);
+        assert!(!path.with_extension("tmp").exists());
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,39 +1,131 @@
-use log::LevelFilter;
+use super::telemetry::{otlp_endpoint_from_env, otlp_tracer_provider, trace_layer, TelemetryError};
+use opentelemetry_sdk::trace::TracerProvider;
 use std::env;
-use std::io::Write;
+use std::io::IsTerminal;
+use std::str::FromStr;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+const DEFAULT_DIRECTIVES: &str = "info";
 
 #[derive(Debug, thiserror::Error)]
 pub enum LoggerError {
     #[error("Invalid log level: {0}")]
     InvalidLogLevel(String),
+    #[error("Invalid log format '{0}', expected text or json")]
+    InvalidLogFormat(String),
+    #[error("Telemetry error: {0}")]
+    TelemetryError(#[from] TelemetryError),
+    #[error("Logger initialization failed: {0}")]
+    InitializationFailed(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = LoggerError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(LoggerError::InvalidLogFormat(s.to_string())),
+        }
+    }
+}
+
+/// Exports the spans still buffered when dropped. Keep it alive until the
+/// process exits.
+#[must_use]
+pub struct LoggerGuard {
+    tracer_provider: Option<TracerProvider>,
+}
+
+impl Drop for LoggerGuard {
+    fn drop(&mut self) {
+        if let Some(tracer_provider) = self.tracer_provider.take() {
+            if let Err(err) = tracer_provider.shutdown() {
+                eprintln!("Failed to export remaining traces: {}", err);
+            }
+        }
+    }
 }
 
-pub fn initialize_logger() -> Result<(), LoggerError> {
-    let log_level = env::var("LOG_LEVEL").unwrap_or_else(|_| "info".to_string());
+/// Parses `RUST_LOG`-style directives such as
+/// `info,blockstream::blockchain=debug,alloy=warn`.
+pub fn log_filter(directives: &str) -> Result<EnvFilter, LoggerError> {
+    EnvFilter::try_new(directives)
+        .map_err(|err| LoggerError::InvalidLogLevel(format!("{}: {}", directives, err)))
+}
 
-    let log_level = match log_level.to_lowercase().as_str() {
-        "trace" => LevelFilter::Trace,
-        "debug" => LevelFilter::Debug,
-        "info" => LevelFilter::Info,
-        "warn" => LevelFilter::Warn,
-        "error" => LevelFilter::Error,
-        _ => return Err(LoggerError::InvalidLogLevel(log_level)),
+/// Installs the global subscriber. Levels come from `RUST_LOG`, falling back
+/// to `LOG_LEVEL` and then `info`; `LOG_FORMAT=json` writes one JSON object
+/// per line carrying the fields of the enclosing spans. Records emitted
+/// through the `log` crate by dependencies are captured too. Spans are also
+/// exported over OTLP when a collector endpoint is configured, see
+/// [`otlp_endpoint_from_env`].
+pub fn initialize_logger() -> Result<LoggerGuard, LoggerError> {
+    let directives = env::var("RUST_LOG")
+        .or_else(|_| env::var("LOG_LEVEL"))
+        .unwrap_or_else(|_| DEFAULT_DIRECTIVES.to_string());
+    let filter = log_filter(&directives)?;
+    let format = match env::var("LOG_FORMAT") {
+        Ok(format) => format.parse()?,
+        Err(_) => LogFormat::Text,
     };
 
-    let log_format = |buf: &mut env_logger::fmt::Formatter, record: &log::Record| {
-        writeln!(
-            buf,
-            "{} [{}] - {}",
-            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-            record.level(),
-            record.args(),
-        )
+    let mut layers: Vec<Box<dyn Layer<Registry> + Send + Sync>> = vec![match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_ansi(std::io::stdout().is_terminal())
+            .boxed(),
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(true)
+            .boxed(),
+    }];
+    let tracer_provider = match otlp_endpoint_from_env() {
+        Some(endpoint) => Some(otlp_tracer_provider(&endpoint)?),
+        None => None,
     };
+    if let Some(tracer_provider) = &tracer_provider {
+        layers.push(trace_layer(tracer_provider).boxed());
+    }
+
+    tracing_subscriber::registry()
+        .with(layers)
+        .with(filter)
+        .try_init()
+        .map_err(|err| LoggerError::InitializationFailed(err.to_string()))?;
+    Ok(LoggerGuard { tracer_provider })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    env_logger::Builder::new()
-        .filter_level(log_level)
-        .format(log_format)
-        .init();
+    #[test]
+    fn test_log_format() {
+        assert_eq!("JSON".parse::<LogFormat>().unwrap(), LogFormat::Json);
+        assert_eq!("text".parse::<LogFormat>().unwrap(), LogFormat::Text);
+        assert!(matches!(
+            "xml".parse::<LogFormat>(),
+            Err(LoggerError::InvalidLogFormat(_))
+        ));
+    }
 
-    Ok(())
+    #[test]
+    fn test_log_filter_directives() {
+        assert!(log_filter("info,blockstream::blockchain=debug,alloy=warn").is_ok());
+        assert!(matches!(
+            log_filter("blockstream=loud"),
+            Err(LoggerError::InvalidLogLevel(_))
+        ));
+    }
 }
@@ -0,0 +1,55 @@
+use opentelemetry::trace::{TraceError, TracerProvider as _};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::trace::{Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use std::env;
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+const SERVICE_NAME: &str = "blockstream";
+const TRACES_ENDPOINT: &str = "OTEL_EXPORTER_OTLP_TRACES_ENDPOINT";
+const ENDPOINT: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+const TRACES_PATH: &str = "/v1/traces";
+
+#[derive(Debug, thiserror::Error)]
+pub enum TelemetryError {
+    #[error("Trace exporter error: {0}")]
+    ExporterError(#[from] TraceError),
+}
+
+/// The collector URL traces are sent to: `OTEL_EXPORTER_OTLP_TRACES_ENDPOINT`
+/// as is, or `OTEL_EXPORTER_OTLP_ENDPOINT` followed by `/v1/traces`. Trace
+/// export is disabled when neither is set.
+pub fn otlp_endpoint_from_env() -> Option<String> {
+    if let Ok(endpoint) = env::var(TRACES_ENDPOINT) {
+        return Some(endpoint);
+    }
+    env::var(ENDPOINT)
+        .ok()
+        .map(|endpoint| format!("{}{}", endpoint.trim_end_matches('/'), TRACES_PATH))
+}
+
+/// Batches finished spans and sends them as OTLP/HTTP protobuf to `endpoint`.
+/// The service is named by `OTEL_SERVICE_NAME`, `blockstream` by default.
+/// Must be called within a Tokio runtime.
+pub fn otlp_tracer_provider(endpoint: &str) -> Result<TracerProvider, TelemetryError> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(endpoint)
+        .build()?;
+    let service_name = env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| SERVICE_NAME.to_string());
+    Ok(TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", service_name)]))
+        .build())
+}
+
+/// A layer recording `tracing` spans into `provider`.
+pub fn trace_layer<S>(provider: &TracerProvider) -> OpenTelemetryLayer<S, Tracer>
+where
+    S: Subscriber + for<'span> LookupSpan<'span>,
+{
+    tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME))
+}
@@ -0,0 +1,741 @@
+use crate::blockchain::configuration::{
+    EventFilter, EventFilterError, FunctionFilter, FunctionFilterError, ParamType,
+};
+use crate::configuration::{join_path, key_to_string, EffectiveConfig};
+use crate::health::HealthConfig;
+use crate::metrics::MetricsConfig;
+use alloy::primitives::Address;
+use serde_yaml::{Mapping, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+const PROTOCOLS: [&str; 5] = ["http", "https", "ws", "wss", "ipc"];
+const ENDPOINT_FIELDS: [&str; 4] = ["protocol", "host", "port", "path"];
+const ON_EXHAUSTION: [&str; 3] = ["halt", "skip", "park"];
+const POLL_MODES: [&str; 2] = ["filter", "head"];
+
+/// A problem found in the effective configuration, with the YAML path of the
+/// offending value and where it was defined.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ValidationIssue {
+    pub path: String,
+    pub location: Option<String>,
+    pub message: String,
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{}: {}: {}", location, self.path, self.message),
+            None => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+/// Checks the effective configuration and reports every problem found rather
+/// than stopping at the first one.
+pub fn validate(config: &EffectiveConfig) -> Vec<ValidationIssue> {
+    let mut validator = Validator {
+        config,
+        issues: Vec::new(),
+    };
+    validator.validate_networks();
+    validator.validate_subscriptions();
+    validator.validate_ports();
+
+    if validator.issues.is_empty() {
+        if let Err(err) = config.configuration() {
+            validator.report("", err.to_string());
+        }
+    }
+    validator.issues
+}
+
+struct Validator<'a> {
+    config: &'a EffectiveConfig,
+    issues: Vec<ValidationIssue>,
+}
+
+impl Validator<'_> {
+    fn report(&mut self, path: &str, message: String) {
+        self.issues.push(ValidationIssue {
+            path: path.to_string(),
+            location: self.config.location(path),
+            message,
+        });
+    }
+
+    fn validate_networks(&mut self) {
+        let Some(networks) = self
+            .config
+            .value
+            .get("networks")
+            .and_then(Value::as_mapping)
+            .filter(|networks| !networks.is_empty())
+        else {
+            self.report("networks", "at least one network is required".to_string());
+            return;
+        };
+
+        for (name, network) in networks {
+            let path = format!("networks.{}", name.as_str().unwrap_or_default());
+            match network.as_mapping() {
+                Some(network) => self.validate_network(&path, network),
+                None => self.report(&path, "network must be a mapping".to_string()),
+            }
+        }
+    }
+
+    fn validate_network(&mut self, path: &str, network: &Mapping) {
+        let protocol = match string_field(network, "url") {
+            Some(url) => {
+                self.validate_url(path, network, url);
+                url.split_once("://")
+                    .map_or(Some("ipc"), |(scheme, _)| Some(scheme))
+            }
+            None => {
+                self.validate_endpoint(path, network);
+                string_field(network, "protocol")
+            }
+        };
+
+        for key in ["confirmations", "poll_interval_ms", "receipt_concurrency"] {
+            match network.get(key) {
+                Some(Value::Number(number)) if number.as_u64().is_some() => {}
+                Some(value) if !is_empty(value) => self.report(
+                    &format!("{}.{}", path, key),
+                    format!(
+                        "{} must be a non-negative integer, got {}",
+                        key,
+                        render(value)
+                    ),
+                ),
+                _ => {}
+            }
+        }
+        if let Some(Value::Number(number)) = network.get("receipt_concurrency") {
+            if number.as_u64() == Some(0) {
+                self.report(
+                    &format!("{}.receipt_concurrency", path),
+                    "receipt_concurrency must be at least 1".to_string(),
+                );
+            }
+        }
+        if network.get("batch").is_some_and(|batch| !is_empty(batch))
+            && !matches!(protocol, Some("http" | "https"))
+        {
+            self.report(
+                &format!("{}.batch", path),
+                "batching is only used with the http and https protocols".to_string(),
+            );
+        }
+        if let Some(rate_limit) = network.get("rate_limit").filter(|value| !is_empty(value)) {
+            match rate_limit.get("requests_per_second") {
+                Some(Value::Number(number)) if number.as_f64().is_some_and(|rate| rate > 0.0) => {}
+                Some(value) => self.report(
+                    &format!("{}.rate_limit.requests_per_second", path),
+                    format!(
+                        "requests_per_second must be a positive number, got {}",
+                        render(value)
+                    ),
+                ),
+                None => self.report(
+                    &format!("{}.rate_limit.requests_per_second", path),
+                    "requests_per_second is required".to_string(),
+                ),
+            }
+        }
+        if let Some(retry) = network.get("retry").filter(|value| !is_empty(value)) {
+            self.validate_retry(&format!("{}.retry", path), retry);
+        }
+        match string_field(network, "poll_mode") {
+            Some(mode) if !mode.is_empty() && !POLL_MODES.contains(&mode) => self.report(
+                &format!("{}.poll_mode", path),
+                format!(
+                    "unsupported poll_mode '{}', expected one of {}",
+                    mode,
+                    POLL_MODES.join(", ")
+                ),
+            ),
+            _ => {}
+        }
+
+        let Some(auth) = network.get("auth").filter(|auth| !is_empty(auth)) else {
+            return;
+        };
+        let Some(auth) = auth.as_mapping() else {
+            self.report(
+                &format!("{}.auth", path),
+                "auth must be a mapping".to_string(),
+            );
+            return;
+        };
+        if protocol == Some("ipc") {
+            self.report(
+                &format!("{}.auth", path),
+                "authentication is not used with the ipc protocol".to_string(),
+            );
+        } else if matches!(protocol, Some("ws" | "wss"))
+            && auth
+                .get("headers")
+                .is_some_and(|headers| !is_empty(headers))
+        {
+            self.report(
+                &format!("{}.auth.headers", path),
+                "custom headers are not supported with websockets".to_string(),
+            );
+        }
+        if auth.contains_key("password") && !auth.contains_key("username") {
+            self.report(
+                &format!("{}.auth.password", path),
+                "password requires a username".to_string(),
+            );
+        }
+    }
+
+    fn validate_url(&mut self, path: &str, network: &Mapping, url: &str) {
+        for field in ENDPOINT_FIELDS {
+            if network.get(field).is_some_and(|value| !is_empty(value)) {
+                self.report(
+                    &format!("{}.{}", path, field),
+                    format!("{} cannot be combined with url", field),
+                );
+            }
+        }
+        match url.split_once("://") {
+            Some((scheme, _)) if PROTOCOLS.contains(&scheme) => {}
+            Some((scheme, _)) => self.report(
+                &format!("{}.url", path),
+                format!(
+                    "unsupported protocol '{}', expected one of {}",
+                    scheme,
+                    PROTOCOLS.join(", ")
+                ),
+            ),
+            None if url.is_empty() => {
+                self.report(&format!("{}.url", path), "url is empty".to_string())
+            }
+            None => {}
+        }
+    }
+
+    fn validate_retry(&mut self, path: &str, retry: &Value) {
+        for key in ["max_attempts", "initial_backoff_ms", "max_backoff_ms"] {
+            match retry.get(key) {
+                Some(Value::Number(number)) if number.as_u64().is_some() => {}
+                Some(value) if !is_empty(value) => self.report(
+                    &format!("{}.{}", path, key),
+                    format!(
+                        "{} must be a non-negative integer, got {}",
+                        key,
+                        render(value)
+                    ),
+                ),
+                _ => {}
+            }
+        }
+        if let Some(Value::Number(number)) = retry.get("max_attempts") {
+            if number.as_u64() == Some(0) {
+                self.report(
+                    &format!("{}.max_attempts", path),
+                    "max_attempts must be at least 1".to_string(),
+                );
+            }
+        }
+        match retry.get("on_exhaustion").and_then(Value::as_str) {
+            Some(policy) if !ON_EXHAUSTION.contains(&policy) => self.report(
+                &format!("{}.on_exhaustion", path),
+                format!(
+                    "unsupported on_exhaustion '{}', expected one of {}",
+                    policy,
+                    ON_EXHAUSTION.join(", ")
+                ),
+            ),
+            _ => {}
+        }
+    }
+
+    fn validate_endpoint(&mut self, path: &str, network: &Mapping) {
+        let protocol = string_field(network, "protocol");
+        match protocol {
+            Some(protocol) if PROTOCOLS.contains(&protocol) => {}
+            Some(protocol) => self.report(
+                &format!("{}.protocol", path),
+                format!(
+                    "unsupported protocol '{}', expected one of {}",
+                    protocol,
+                    PROTOCOLS.join(", ")
+                ),
+            ),
+            None => self.report(
+                &format!("{}.protocol", path),
+                "either url or protocol is required".to_string(),
+            ),
+        }
+
+        let host = string_field(network, "host").unwrap_or_default();
+        let port = network.get("port").filter(|port| !is_empty(port));
+        if protocol == Some("ipc") {
+            if !host.is_empty() {
+                self.report(
+                    &format!("{}.host", path),
+                    "host is not used with the ipc protocol".to_string(),
+                );
+            }
+            if port.is_some() {
+                self.report(
+                    &format!("{}.port", path),
+                    "port is not used with the ipc protocol".to_string(),
+                );
+            }
+            if string_field(network, "path").unwrap_or_default().is_empty() {
+                self.report(
+                    &format!("{}.path", path),
+                    "path to the IPC socket is required".to_string(),
+                );
+            }
+        } else {
+            if host.is_empty() {
+                self.report(&format!("{}.host", path), "host is required".to_string());
+            }
+            if let Some(port) = port.filter(|port| as_port(port).is_none()) {
+                self.report(
+                    &format!("{}.port", path),
+                    format!("invalid port {}", render(port)),
+                );
+            }
+        }
+    }
+
+    fn validate_subscriptions(&mut self) {
+        let Some(subscriptions) = self
+            .config
+            .value
+            .get("subscriptions")
+            .and_then(Value::as_sequence)
+        else {
+            self.report(
+                "subscriptions",
+                "a list of subscriptions is required".to_string(),
+            );
+            return;
+        };
+
+        let networks: Vec<&str> = self
+            .config
+            .value
+            .get("networks")
+            .and_then(Value::as_mapping)
+            .map(|networks| networks.keys().filter_map(Value::as_str).collect())
+            .unwrap_or_default();
+
+        let mut seen: HashMap<(String, Address, String), String> = HashMap::new();
+        for (index, subscription) in subscriptions.iter().enumerate() {
+            let path = format!("subscriptions.{}", index);
+            let Some(subscription) = subscription.as_mapping() else {
+                self.report(&path, "subscription must be a mapping".to_string());
+                continue;
+            };
+
+            let network_path = format!("{}.network", path);
+            let network = match string_field(subscription, "network") {
+                Some(network) if networks.contains(&network) => network.to_string(),
+                Some(network) => {
+                    self.report(
+                        &network_path,
+                        format!(
+                            "unknown network '{}', expected one of {}",
+                            network,
+                            networks.join(", ")
+                        ),
+                    );
+                    network.to_string()
+                }
+                None if networks.len() > 1 => {
+                    self.report(
+                        &network_path,
+                        "network is required when several networks are configured".to_string(),
+                    );
+                    String::new()
+                }
+                None => networks.first().copied().unwrap_or_default().to_string(),
+            };
+
+            let address_path = format!("{}.contract_address", path);
+            let address = match string_field(subscription, "contract_address") {
+                Some(value) => match Address::from_str(value) {
+                    Ok(address) => Some(address),
+                    Err(_) => {
+                        self.report(&address_path, format!("invalid address '{}'", value));
+                        None
+                    }
+                },
+                None => {
+                    self.report(&address_path, "contract_address is required".to_string());
+                    None
+                }
+            };
+
+            match subscription.get("start_block") {
+                Some(Value::Number(number)) if number.as_u64().is_some() => {}
+                Some(value) if !is_empty(value) => self.report(
+                    &format!("{}.start_block", path),
+                    format!(
+                        "start_block must be a non-negative integer, got {}",
+                        render(value)
+                    ),
+                ),
+                _ => {}
+            }
+
+            let events = self.signatures(subscription, &path, "events");
+            let functions = self.entries(subscription, &path, "functions");
+            let errors = self.entries(subscription, &path, "errors");
+
+            if events.is_empty() && functions.is_empty() {
+                self.report(
+                    &format!("{}.events", path),
+                    "subscription has no events or functions".to_string(),
+                );
+            }
+
+            for (event_path, signature) in events {
+                match EventFilter::new(signature.clone()) {
+                    Ok(event_filter) => {
+                        if let Some(address) = address {
+                            let canonical = format!(
+                                "{}({})",
+                                event_filter.event_name,
+                                event_filter
+                                    .data_types
+                                    .iter()
+                                    .map(ParamType::name)
+                                    .collect::<Vec<_>>()
+                                    .join(",")
+                            );
+                            let key = (network.clone(), address, canonical);
+                            match seen.get(&key) {
+                                Some(first_path) => {
+                                    let first_location = self
+                                        .config
+                                        .location(first_path)
+                                        .unwrap_or_else(|| first_path.clone());
+                                    self.report(
+                                        &event_path,
+                                        format!(
+                                            "duplicate subscription to {} on {}, already defined at {}",
+                                            signature, address, first_location
+                                        ),
+                                    )
+                                }
+                                None => {
+                                    seen.insert(key, event_path);
+                                }
+                            }
+                        }
+                    }
+                    Err(EventFilterError::UnsupportedDataType(data_type)) => self.report(
+                        &event_path,
+                        format!("unsupported type '{}' in '{}'", data_type, signature),
+                    ),
+                    Err(EventFilterError::InvalidSignatureFormat) => self.report(
+                        &event_path,
+                        format!("invalid event signature '{}'", signature),
+                    ),
+                }
+            }
+
+            for (function_path, entry) in functions.into_iter().chain(errors) {
+                let signature = match entry {
+                    Value::String(signature) => signature,
+                    // A JSON ABI entry, checked the way it is parsed.
+                    Value::Mapping(_) => {
+                        if let Err(err) = serde_yaml::from_value::<FunctionFilter>(entry) {
+                            self.report(&function_path, format!("invalid ABI entry: {}", err));
+                        }
+                        continue;
+                    }
+                    _ => {
+                        self.report(
+                            &function_path,
+                            "must be a signature or a JSON ABI entry".to_string(),
+                        );
+                        continue;
+                    }
+                };
+                match FunctionFilter::new(signature.clone()) {
+                    Ok(_) => {}
+                    Err(FunctionFilterError::UnsupportedDataType(data_type)) => self.report(
+                        &function_path,
+                        format!("unsupported type '{}' in '{}'", data_type, signature),
+                    ),
+                    Err(FunctionFilterError::InvalidSignatureFormat) => {
+                        self.report(&function_path, format!("invalid signature '{}'", signature))
+                    }
+                }
+            }
+        }
+    }
+
+    /// Ports this process listens on must be distinct: each enabled listener
+    /// is compared on its effective port, the default when it sets none. The
+    /// node ports under `networks` are remote and therefore not considered.
+    fn validate_ports(&mut self) {
+        let listeners = [
+            ("metrics", MetricsConfig::default().port),
+            ("health", HealthConfig::default().port),
+        ];
+        let mut used: BTreeMap<u16, String> = BTreeMap::new();
+        for (section, default_port) in listeners {
+            let Some(listener) = self.config.value.get(section).and_then(Value::as_mapping) else {
+                continue;
+            };
+            let port_path = format!("{}.port", section);
+            let (path, port, owner) = match listener.get("port") {
+                Some(value) => match as_port(value) {
+                    Some(port) => (port_path.clone(), port, port_path),
+                    None => {
+                        self.report(&port_path, format!("invalid port {}", render(value)));
+                        continue;
+                    }
+                },
+                None => (
+                    format!("{}.enabled", section),
+                    default_port,
+                    format!("{} (default)", port_path),
+                ),
+            };
+            if !listener.get("enabled").is_some_and(is_enabled) {
+                continue;
+            }
+            match used.get(&port) {
+                Some(other) => {
+                    let message = format!("port {} is already used by {}", port, other);
+                    self.report(&path, message);
+                }
+                None => {
+                    used.insert(port, owner);
+                }
+            }
+        }
+    }
+
+    /// Returns the signatures listed under `key`, reporting entries that are
+    /// not strings.
+    fn signatures(
+        &mut self,
+        subscription: &Mapping,
+        path: &str,
+        key: &str,
+    ) -> Vec<(String, String)> {
+        let mut signatures = Vec::new();
+        for (entry_path, entry) in self.entries(subscription, path, key) {
+            match entry {
+                Value::String(signature) => signatures.push((entry_path, signature)),
+                _ => self.report(&entry_path, "signature must be a string".to_string()),
+            }
+        }
+        signatures
+    }
+
+    /// Returns the entries listed under `key` with their paths, reporting a
+    /// value that is not a list.
+    fn entries(&mut self, subscription: &Mapping, path: &str, key: &str) -> Vec<(String, Value)> {
+        let list_path = format!("{}.{}", path, key);
+        match subscription.get(key) {
+            None => Vec::new(),
+            Some(Value::Sequence(entries)) => entries
+                .iter()
+                .enumerate()
+                .map(|(index, entry)| (format!("{}.{}", list_path, index), entry.clone()))
+                .collect(),
+            Some(value) if is_empty(value) => Vec::new(),
+            Some(_) => {
+                self.report(&list_path, format!("{} must be a list", key));
+                Vec::new()
+            }
+        }
+    }
+}
+
+fn string_field<'a>(mapping: &'a Mapping, key: &str) -> Option<&'a str> {
+    mapping.get(key).and_then(Value::as_str)
+}
+
+fn as_port(value: &Value) -> Option<u16> {
+    let port = match value {
+        Value::Number(number) => number.as_u64()?,
+        Value::String(string) => string.parse().ok()?,
+        _ => return None,
+    };
+    u16::try_from(port).ok().filter(|port| *port != 0)
+}
+
+/// Whether an `enabled` flag is set, as a boolean or a string from an
+/// environment variable or the command line.
+fn is_enabled(value: &Value) -> bool {
+    match value {
+        Value::Bool(enabled) => *enabled,
+        Value::String(string) => string == "true",
+        _ => false,
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(string) => string.is_empty(),
+        _ => false,
+    }
+}
+
+fn render(value: &Value) -> String {
+    serde_yaml::to_string(value)
+        .unwrap_or_default()
+        .trim_end()
+        .to_string()
+}
+
+enum Frame {
+    Key,
+    Item,
+}
+
+/// Maps the dotted path of every key and sequence item in a YAML document to
+/// its (1-based) line number. `value` is the parsed document; it supplies the
+/// paths inside flow collections and aliases, which take the line of the key
+/// or item that holds them.
+pub(crate) fn line_numbers(contents: &str, value: &Value) -> BTreeMap<String, usize> {
+    let mut lines = BTreeMap::new();
+    let mut stack: Vec<(usize, String, Frame)> = Vec::new();
+    let mut item_counts: HashMap<String, usize> = HashMap::new();
+    // Lines indented deeper than a value that started inline continue it.
+    let mut continued: Option<usize> = None;
+
+    for (number, line) in contents.lines().enumerate() {
+        let content = line.trim_start();
+        let mut indent = line.len() - content.len();
+        if let Some(value_indent) = continued {
+            if content.is_empty() || indent > value_indent {
+                continue;
+            }
+            continued = None;
+        }
+        if content.is_empty() || content.starts_with('#') || content == "---" {
+            continue;
+        }
+        let mut content = content;
+
+        let is_item = content == "-" || content.starts_with("- ");
+        while let Some((frame_indent, _, frame)) = stack.last() {
+            let keep_parent_key = is_item && *frame_indent == indent && matches!(frame, Frame::Key);
+            if *frame_indent > indent || (*frame_indent == indent && !keep_parent_key) {
+                stack.pop();
+            } else {
+                break;
+            }
+        }
+        let parent = stack
+            .last()
+            .map(|(_, path, _)| path.clone())
+            .unwrap_or_default();
+
+        let mut path = if is_item {
+            let count = item_counts.entry(parent.clone()).or_default();
+            let path = join_path(&parent, &count.to_string());
+            *count += 1;
+            lines.entry(path.clone()).or_insert(number + 1);
+            stack.push((indent, path.clone(), Frame::Item));
+
+            let rest = content[1..].trim_start();
+            indent += content.len() - rest.len();
+            content = rest;
+            path
+        } else {
+            parent
+        };
+
+        let mut is_key = false;
+        if let Some((key, rest)) = split_key(content) {
+            path = join_path(&path, key);
+            lines.entry(path.clone()).or_insert(number + 1);
+            content = rest;
+            is_key = true;
+        }
+
+        if inline_value(content).is_empty() {
+            if is_key {
+                stack.push((indent, path, Frame::Key));
+            }
+        } else {
+            if let Some(value) = value_at(value, &path) {
+                record_descendants(value, &path, number + 1, &mut lines);
+            }
+            continued = Some(indent);
+        }
+    }
+
+    lines
+}
+
+/// Splits `key: value` into the unquoted key and the rest of the line.
+fn split_key(content: &str) -> Option<(&str, &str)> {
+    if content.starts_with(['[', '{']) {
+        return None;
+    }
+    let key_end = match content.chars().next() {
+        Some(quote @ ('"' | '\'')) => content[1..].find(quote)? + 2,
+        _ => 0,
+    };
+    let rest = &content[key_end..];
+    let colon = key_end
+        + rest
+            .find(": ")
+            .or_else(|| rest.strip_suffix(':').map(str::len))?;
+    let key = content[..colon].trim().trim_matches(['"', '\'']);
+    (!key.is_empty()).then(|| (key, &content[colon + 1..]))
+}
+
+/// The value written on the line of its key or item, without anchors, tags
+/// and comments; empty when the value is nested below.
+fn inline_value(content: &str) -> &str {
+    let mut value = content.trim();
+    while value.starts_with(['&', '!']) {
+        value = value
+            .split_once(char::is_whitespace)
+            .map_or("", |(_, rest)| rest.trim_start());
+    }
+    if value.starts_with('#') {
+        ""
+    } else {
+        value
+    }
+}
+
+fn value_at<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, key| match value {
+        Value::Mapping(mapping) => mapping.get(key),
+        Value::Sequence(sequence) => sequence.get(key.parse::<usize>().ok()?),
+        _ => None,
+    })
+}
+
+fn record_descendants(value: &Value, path: &str, line: usize, lines: &mut BTreeMap<String, usize>) {
+    let children: Vec<(String, &Value)> = match value {
+        Value::Mapping(mapping) => mapping
+            .iter()
+            .map(|(key, child)| (join_path(path, &key_to_string(key)), child))
+            .collect(),
+        Value::Sequence(sequence) => sequence
+            .iter()
+            .enumerate()
+            .map(|(index, child)| (join_path(path, &index.to_string()), child))
+            .collect(),
+        _ => Vec::new(),
+    };
+    for (child_path, child) in children {
+        lines.entry(child_path.clone()).or_insert(line);
+        record_descendants(child, &child_path, line, lines);
+    }
+}
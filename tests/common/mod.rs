@@ -0,0 +1,289 @@
+#![allow(dead_code)]
+
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+pub const CONTRACT_ADDRESS: &str = "0xe7f1725e7734ce288f8367e1bb143e90bb3f0512";
+pub const SENDER_ADDRESS: &str = "0xf39fd6e51aad88f6f4ce6ab8827279cfffb92266";
+
+type Handler = dyn Fn(&str, &Value) -> Result<Value, Value> + Send + Sync;
+
+/// Minimal JSON-RPC over HTTP stand-in for a node. Each request is answered
+/// by `handler`, which returns either a `result` or an `error` object.
+pub struct MockRpcServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<(String, Value)>>>,
+    headers: Arc<Mutex<Vec<(String, String)>>>,
+    batch_sizes: Arc<Mutex<Vec<usize>>>,
+    throttle: Arc<Mutex<Throttle>>,
+}
+
+/// HTTP requests still to be rejected with 429, and the `Retry-After` they
+/// carry.
+#[derive(Default)]
+struct Throttle {
+    remaining: usize,
+    retry_after: Option<u64>,
+}
+
+impl MockRpcServer {
+    pub async fn start<H>(handler: H) -> Self
+    where
+        H: Fn(&str, &Value) -> Result<Value, Value> + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let handler: Arc<Handler> = Arc::new(handler);
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let headers = Arc::new(Mutex::new(Vec::new()));
+        let batch_sizes = Arc::new(Mutex::new(Vec::new()));
+        let throttle = Arc::new(Mutex::new(Throttle::default()));
+
+        let recorded = requests.clone();
+        let recorded_headers = headers.clone();
+        let recorded_batch_sizes = batch_sizes.clone();
+        let throttled = throttle.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let handler = handler.clone();
+                let recorded = recorded.clone();
+                let recorded_headers = recorded_headers.clone();
+                let recorded_batch_sizes = recorded_batch_sizes.clone();
+                let throttled = throttled.clone();
+                tokio::spawn(async move {
+                    let (reader, mut writer) = stream.into_split();
+                    let mut reader = BufReader::new(reader);
+                    while let Some((headers, body)) = read_request(&mut reader).await {
+                        recorded_headers.lock().unwrap().extend(headers);
+                        if let Some(reply) = throttled_reply(&throttled) {
+                            if writer.write_all(reply.as_bytes()).await.is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                        let request: Value = serde_json::from_slice(&body).unwrap();
+                        recorded_batch_sizes
+                            .lock()
+                            .unwrap()
+                            .push(request.as_array().map_or(1, Vec::len));
+                        let response = match request {
+                            Value::Array(batch) => Value::Array(
+                                batch
+                                    .iter()
+                                    .map(|call| respond(&*handler, &recorded, call))
+                                    .collect(),
+                            ),
+                            call => respond(&*handler, &recorded, &call),
+                        };
+                        let body = response.to_string();
+                        let reply = format!(
+                            "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                            body.len(),
+                            body
+                        );
+                        if writer.write_all(reply.as_bytes()).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+
+        Self {
+            url,
+            requests,
+            headers,
+            batch_sizes,
+            throttle,
+        }
+    }
+
+    /// Rejects the next `requests` HTTP requests with 429 Too Many Requests,
+    /// asking to retry after `retry_after` seconds if given.
+    pub fn throttle(&self, requests: usize, retry_after: Option<u64>) {
+        *self.throttle.lock().unwrap() = Throttle {
+            remaining: requests,
+            retry_after,
+        };
+    }
+
+    /// Values of the HTTP header `name` across all requests received so far.
+    pub fn header_values(&self, name: &str) -> Vec<String> {
+        self.headers
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.clone())
+            .collect()
+    }
+
+    /// Number of calls carried by each HTTP request received so far, 1 for
+    /// requests that are not batched.
+    pub fn batch_sizes(&self) -> Vec<usize> {
+        self.batch_sizes.lock().unwrap().clone()
+    }
+
+    /// Methods received so far, in order.
+    pub fn methods(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(method, _)| method.clone())
+            .collect()
+    }
+
+    pub fn requests(&self, method: &str) -> Vec<Value> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(m, _)| m == method)
+            .map(|(_, params)| params.clone())
+            .collect()
+    }
+}
+
+fn throttled_reply(throttle: &Mutex<Throttle>) -> Option<String> {
+    let mut throttle = throttle.lock().unwrap();
+    if throttle.remaining == 0 {
+        return None;
+    }
+    throttle.remaining -= 1;
+    let retry_after = throttle
+        .retry_after
+        .map(|seconds| format!("retry-after: {}\r\n", seconds))
+        .unwrap_or_default();
+    Some(format!(
+        "HTTP/1.1 429 Too Many Requests\r\n{}content-length: 12\r\n\r\nrate limited",
+        retry_after
+    ))
+}
+
+fn respond(handler: &Handler, recorded: &Mutex<Vec<(String, Value)>>, call: &Value) -> Value {
+    let method = call["method"].as_str().unwrap_or_default().to_string();
+    let params = call["params"].clone();
+    recorded
+        .lock()
+        .unwrap()
+        .push((method.clone(), params.clone()));
+    match handler(&method, &params) {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": call["id"], "result": result }),
+        Err(error) => json!({ "jsonrpc": "2.0", "id": call["id"], "error": error }),
+    }
+}
+
+async fn read_request<R: AsyncBufReadExt + Unpin>(
+    reader: &mut R,
+) -> Option<(Vec<(String, String)>, Vec<u8>)> {
+    let mut content_length = 0;
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await.ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().ok()?;
+            }
+            headers.push((name.to_string(), value.trim().to_string()));
+        }
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).await.ok()?;
+    Some((headers, body))
+}
+
+pub fn hash(n: u64) -> String {
+    format!("0x{:064x}", n)
+}
+
+pub fn quantity(n: u64) -> String {
+    format!("0x{:x}", n)
+}
+
+pub fn transaction_json(tx_hash: &str, block_number: u64, to: &str, input: &str) -> Value {
+    json!({
+        "hash": tx_hash,
+        "type": "0x0",
+        "nonce": "0x0",
+        "blockHash": hash(block_number),
+        "blockNumber": quantity(block_number),
+        "transactionIndex": "0x0",
+        "from": SENDER_ADDRESS,
+        "to": to,
+        "value": "0x0",
+        "gasPrice": "0x3b9aca00",
+        "gas": "0x5208",
+        "input": input,
+        "chainId": "0x7a69",
+        "v": "0xf4f6",
+        "r": "0x1",
+        "s": "0x1"
+    })
+}
+
+pub fn receipt_json(tx_hash: &str, block_number: u64, success: bool, logs: Vec<Value>) -> Value {
+    json!({
+        "type": "0x0",
+        "status": if success { "0x1" } else { "0x0" },
+        "cumulativeGasUsed": "0x5208",
+        "logs": logs,
+        "logsBloom": format!("0x{}", "0".repeat(512)),
+        "transactionHash": tx_hash,
+        "transactionIndex": "0x0",
+        "blockHash": hash(block_number),
+        "blockNumber": quantity(block_number),
+        "gasUsed": "0x5208",
+        "effectiveGasPrice": "0x3b9aca00",
+        "from": SENDER_ADDRESS,
+        "to": CONTRACT_ADDRESS,
+        "contractAddress": null
+    })
+}
+
+pub fn log_json(tx_hash: &str, block_number: u64, topics: Vec<String>, data: &str) -> Value {
+    json!({
+        "address": CONTRACT_ADDRESS,
+        "topics": topics,
+        "data": data,
+        "blockHash": hash(block_number),
+        "blockNumber": quantity(block_number),
+        "transactionHash": tx_hash,
+        "transactionIndex": "0x0",
+        "logIndex": "0x0",
+        "removed": false
+    })
+}
+
+pub fn block_json(number: u64, transactions: Vec<Value>) -> Value {
+    json!({
+        "hash": hash(number),
+        "parentHash": hash(number.saturating_sub(1)),
+        "sha3Uncles": hash(0),
+        "miner": SENDER_ADDRESS,
+        "stateRoot": hash(0),
+        "transactionsRoot": hash(0),
+        "receiptsRoot": hash(0),
+        "logsBloom": format!("0x{}", "0".repeat(512)),
+        "difficulty": "0x0",
+        "number": quantity(number),
+        "gasLimit": "0x1c9c380",
+        "gasUsed": "0x0",
+        "timestamp": quantity(1_700_000_000 + number),
+        "extraData": "0x",
+        "mixHash": hash(0),
+        "nonce": "0x0000000000000000",
+        "baseFeePerGas": "0x1",
+        "uncles": [],
+        "transactions": transactions
+    })
+}
@@ -0,0 +1,315 @@
+mod common;
+
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use alloy::providers::ProviderBuilder;
+use blockstream::blockchain::block::{
+    BlockEvent, BlockSubscriber, EthereumBlockSubscriber, PollMode, SubscriptionError,
+};
+use blockstream::blockchain::retry::{OnExhaustion, RetryPolicy};
+use blockstream::metrics::{metrics, BLOCKS_SKIPPED};
+use common::{block_json, hash, quantity, MockRpcServer};
+use futures_util::{Stream, StreamExt};
+use serde_json::json;
+use tokio::sync::mpsc;
+
+#[tokio::test]
+async fn test_poll_subscription_waits_for_confirmations() {
+    let polled = AtomicBool::new(false);
+    let server = MockRpcServer::start(move |method, params| match method {
+        "eth_newBlockFilter" => Ok(json!("0x1")),
+        "eth_getFilterChanges" if !polled.swap(true, Ordering::SeqCst) => Ok(json!([hash(10)])),
+        "eth_getFilterChanges" => Ok(json!([])),
+        "eth_getBlockByHash" => Ok(block_json(10, vec![])),
+        "eth_getBlockByNumber" => {
+            let number =
+                u64::from_str_radix(params[0].as_str().unwrap().trim_start_matches("0x"), 16);
+            Ok(block_json(number.unwrap(), vec![]))
+        }
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let subscriber = EthereumBlockSubscriber::new(provider)
+        .with_confirmations(2)
+        .with_poll_interval(Duration::from_millis(20));
+    let state = subscriber.state();
+
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    let subscription = tokio::spawn(async move {
+        subscriber
+            .subscribe(move |block| {
+                let sender = sender.clone();
+                async move {
+                    let _ = sender.send(block.header.number);
+                }
+            })
+            .await
+    });
+
+    let number = tokio::time::timeout(Duration::from_secs(5), receiver.recv())
+        .await
+        .expect("No block received");
+    assert_eq!(number, Some(8));
+    assert_eq!(server.requests("eth_getBlockByNumber")[0][0], json!("0x8"));
+    let status = state.status();
+    assert!(status.connected);
+    assert_eq!(status.head, Some(8));
+    subscription.abort();
+}
+
+async fn next_event<S>(events: &mut S) -> BlockEvent
+where
+    S: Stream<Item = Result<BlockEvent, SubscriptionError>> + Unpin,
+{
+    tokio::time::timeout(Duration::from_secs(5), events.next())
+        .await
+        .expect("No event received")
+        .expect("Stream ended")
+        .expect("Subscription failed")
+}
+
+/// Block `number` of a fork off block 8, as `block_json` builds the chain
+/// the fork replaces.
+fn fork_block_json(number: u64) -> serde_json::Value {
+    let mut block = block_json(number, vec![]);
+    block["hash"] = json!(hash(100 + number));
+    if number > 9 {
+        block["parentHash"] = json!(hash(100 + number - 1));
+    }
+    block
+}
+
+#[tokio::test]
+async fn test_block_stream_reports_rollbacks() {
+    let polls = AtomicUsize::new(0);
+    let server = MockRpcServer::start(move |method, params| match method {
+        "eth_newBlockFilter" => Ok(json!("0x1")),
+        "eth_getFilterChanges" => match polls.fetch_add(1, Ordering::SeqCst) {
+            0 => Ok(json!([hash(8), hash(9), hash(10)])),
+            1 => Ok(json!([hash(111)])),
+            _ => Ok(json!([])),
+        },
+        "eth_getBlockByHash" => match (8..=10).find(|number| params[0] == json!(hash(*number))) {
+            Some(number) => Ok(block_json(number, vec![])),
+            None => Ok(fork_block_json(11)),
+        },
+        "eth_getBlockByNumber" => Ok(fork_block_json(block_number_param(params))),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let subscriber =
+        EthereumBlockSubscriber::new(provider).with_poll_interval(Duration::from_millis(20));
+    let state = subscriber.state();
+
+    let mut events = pin!(subscriber.blocks());
+
+    assert_eq!(next_block_numbers(&mut events, 3).await, vec![8, 9, 10]);
+    match next_event(&mut events).await {
+        BlockEvent::Rollback { from, to } => assert_eq!((from, to), (9, 10)),
+        other => panic!("Unexpected event: {:?}", other),
+    }
+    assert_eq!(state.status().last_block, Some(10));
+    let mut hashes = Vec::new();
+    for _ in 0..3 {
+        match next_event(&mut events).await {
+            BlockEvent::NewBlock(block) => hashes.push(block.header.hash.to_string()),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+
+    // The canonical blocks 9 and 10 are delivered again before block 11.
+    assert_eq!(hashes, vec![hash(109), hash(110), hash(111)]);
+    let fetched: Vec<_> = server
+        .requests("eth_getBlockByNumber")
+        .iter()
+        .map(|params| params[0].clone())
+        .collect();
+    assert_eq!(
+        fetched,
+        vec![json!("0xa"), json!("0x9"), json!("0x9"), json!("0xa")]
+    );
+}
+
+#[tokio::test]
+async fn test_block_stream_fills_gaps() {
+    let polls = AtomicUsize::new(0);
+    let server = MockRpcServer::start(move |method, params| match method {
+        "eth_newBlockFilter" => Ok(json!("0x1")),
+        "eth_getFilterChanges" => match polls.fetch_add(1, Ordering::SeqCst) {
+            0 => Ok(json!([hash(10)])),
+            1 => Ok(json!([hash(13)])),
+            _ => Ok(json!([])),
+        },
+        "eth_getBlockByHash" if params[0] == json!(hash(10)) => Ok(block_json(10, vec![])),
+        "eth_getBlockByHash" => Ok(block_json(13, vec![])),
+        "eth_getBlockByNumber" => {
+            let number =
+                u64::from_str_radix(params[0].as_str().unwrap().trim_start_matches("0x"), 16);
+            Ok(block_json(number.unwrap(), vec![]))
+        }
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let subscriber =
+        EthereumBlockSubscriber::new(provider).with_poll_interval(Duration::from_millis(20));
+    let state = subscriber.state();
+
+    let mut events = pin!(subscriber.blocks());
+    let mut numbers = Vec::new();
+    for _ in 0..4 {
+        match next_event(&mut events).await {
+            BlockEvent::NewBlock(block) => numbers.push(block.header.number),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+
+    assert_eq!(numbers, vec![10, 11, 12, 13]);
+    let fetched: Vec<_> = server
+        .requests("eth_getBlockByNumber")
+        .iter()
+        .map(|params| params[0].clone())
+        .collect();
+    assert_eq!(fetched, vec![json!("0xb"), json!("0xc")]);
+    assert_eq!(state.status().last_block, Some(12));
+}
+
+fn block_number_param(params: &serde_json::Value) -> u64 {
+    u64::from_str_radix(params[0].as_str().unwrap().trim_start_matches("0x"), 16).unwrap()
+}
+
+async fn next_block_numbers<S>(events: &mut S, count: usize) -> Vec<u64>
+where
+    S: Stream<Item = Result<BlockEvent, SubscriptionError>> + Unpin,
+{
+    let mut numbers = Vec::new();
+    for _ in 0..count {
+        match next_event(events).await {
+            BlockEvent::NewBlock(block) => numbers.push(block.header.number),
+            other => panic!("Unexpected event: {:?}", other),
+        }
+    }
+    numbers
+}
+
+#[tokio::test]
+async fn test_head_polling_fetches_blocks_by_number() {
+    let polls = AtomicUsize::new(0);
+    let server = MockRpcServer::start(move |method, params| match method {
+        "eth_blockNumber" => match polls.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => Ok(json!(quantity(10))),
+            _ => Ok(json!(quantity(12))),
+        },
+        "eth_getBlockByNumber" => Ok(block_json(block_number_param(params), vec![])),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let subscriber = EthereumBlockSubscriber::new(provider)
+        .with_poll_mode(PollMode::Head)
+        .with_poll_interval(Duration::from_millis(20));
+
+    let mut events = pin!(subscriber.blocks());
+
+    assert_eq!(next_block_numbers(&mut events, 3).await, vec![10, 11, 12]);
+    assert!(server.requests("eth_newBlockFilter").is_empty());
+    assert!(server.requests("eth_getFilterChanges").is_empty());
+}
+
+#[tokio::test]
+async fn test_gap_block_that_cannot_be_fetched_is_skipped_when_configured() {
+    let polls = AtomicUsize::new(0);
+    let server = MockRpcServer::start(move |method, params| match method {
+        "eth_blockNumber" => match polls.fetch_add(1, Ordering::SeqCst) {
+            0 | 1 => Ok(json!(quantity(10))),
+            _ => Ok(json!(quantity(13))),
+        },
+        "eth_getBlockByNumber" => match block_number_param(params) {
+            11 => Ok(json!(null)),
+            number => Ok(block_json(number, vec![])),
+        },
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let subscriber = EthereumBlockSubscriber::new(provider)
+        .with_network("skipping".to_string())
+        .with_poll_mode(PollMode::Head)
+        .with_poll_interval(Duration::from_millis(20))
+        .with_retry_policy(
+            RetryPolicy::default()
+                .with_max_attempts(2)
+                .with_backoff(Duration::from_millis(1), Duration::from_millis(1))
+                .with_on_exhaustion(OnExhaustion::Skip),
+        );
+    let skipped = metrics().counter(BLOCKS_SKIPPED, &[("network", "skipping")]);
+
+    let mut events = pin!(subscriber.blocks());
+
+    assert_eq!(next_block_numbers(&mut events, 3).await, vec![10, 12, 13]);
+    assert_eq!(
+        metrics().counter(BLOCKS_SKIPPED, &[("network", "skipping")]),
+        skipped + 1
+    );
+}
+
+#[tokio::test]
+async fn test_lost_block_filter_falls_back_to_head_polling() {
+    let polls = AtomicUsize::new(0);
+    let server = MockRpcServer::start(move |method, params| match method {
+        "eth_newBlockFilter" => Ok(json!("0x1")),
+        "eth_getFilterChanges" => match polls.fetch_add(1, Ordering::SeqCst) {
+            0 => Ok(json!([hash(10)])),
+            _ => Err(json!({ "code": -32000, "message": "filter not found" })),
+        },
+        "eth_getBlockByHash" => Ok(block_json(10, vec![])),
+        "eth_blockNumber" => Ok(json!(quantity(12))),
+        "eth_getBlockByNumber" => Ok(block_json(block_number_param(params), vec![])),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let subscriber =
+        EthereumBlockSubscriber::new(provider).with_poll_interval(Duration::from_millis(20));
+
+    let mut events = pin!(subscriber.blocks());
+
+    assert_eq!(next_block_numbers(&mut events, 3).await, vec![10, 11, 12]);
+    assert_eq!(server.requests("eth_getFilterChanges").len(), 2);
+    assert_eq!(server.requests("eth_newBlockFilter").len(), 1);
+}
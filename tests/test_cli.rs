@@ -0,0 +1,43 @@
+use blockstream::cli::{CliArgs, CliError};
+
+fn args(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+#[test]
+fn test_parse_cli_args() {
+    let cli_args = CliArgs::parse(args(&[
+        "validate",
+        "--profile",
+        "test",
+        "--print-effective-config",
+        "--networks.local.host=10.0.0.1",
+    ]))
+    .expect("Parsing failed");
+
+    assert_eq!(
+        cli_args,
+        CliArgs {
+            validate: true,
+            profile: Some("test".to_string()),
+            print_effective_config: true,
+            overrides: vec!["networks.local.host=10.0.0.1".to_string()],
+        }
+    );
+    assert_eq!(
+        CliArgs::parse(args(&["--profile=test"])).unwrap().profile,
+        Some("test".to_string())
+    );
+}
+
+#[test]
+fn test_parse_cli_args_errors() {
+    assert!(matches!(
+        CliArgs::parse(args(&["--profile"])),
+        Err(CliError::MissingValue(_))
+    ));
+    assert!(matches!(
+        CliArgs::parse(args(&["--verbose"])),
+        Err(CliError::UnknownArgument(_))
+    ));
+}
@@ -1,14 +1,25 @@
 use std::str::FromStr;
+use std::time::Duration;
 
 use alloy::primitives::Address;
-use blockstream::configuration::load_config;
-use log::error;
+use alloy::transports::Authorization;
+use blockstream::blockchain::batch::BatchConfig;
+use blockstream::blockchain::block::PollMode;
+use blockstream::blockchain::configuration::{
+    Configuration, FunctionFilter, Network, NetworkProtocol, ParamType, DEFAULT_RECEIPT_CONCURRENCY,
+};
+use blockstream::blockchain::retry::{OnExhaustion, RetryPolicy};
+use blockstream::configuration::{
+    load_config, load_config_with_overrides, load_effective_config, substitute_env_variables,
+    ConfigError, EffectiveConfig, ValueSource,
+};
+use tracing::error;
 
 #[test]
 fn test_load_config_env() {
     match load_config(Some("test".to_string())) {
         Ok(config) => {
-            assert_eq!(config.network.url(), "http://localhost:8545/eth");
+            assert_eq!(config.networks["local"].url(), "http://localhost:8545/eth");
             assert_eq!(config.subscriptions.len(), 2);
             assert_eq!(
                 config.subscriptions[0].contract_address,
@@ -18,6 +29,10 @@ fn test_load_config_env() {
                 config.subscriptions[0].events[0].signature,
                 "DummyStructCreated(uint256,uint256,int256,bool,address,string,bytes32)"
             );
+            assert_eq!(
+                config.subscriptions[0].functions[0].signature,
+                "createDummyStruct(uint256,int256)"
+            );
             assert_eq!(
                 config.subscriptions[1].contract_address,
                 Address::from_str("0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512").unwrap()
@@ -26,6 +41,7 @@ fn test_load_config_env() {
                 config.subscriptions[1].events[0].signature,
                 "DummyStructUpdated(uint256,uint256,int256,bool,address,string,bytes32)"
             );
+            assert!(config.subscriptions[1].functions.is_empty());
         }
         Err(err) => {
             error!("Failed to load configuration: {}", err);
@@ -38,7 +54,7 @@ fn test_load_config_env() {
 fn test_load_config_default() {
     match load_config(None) {
         Ok(config) => {
-            assert_eq!(config.network.url(), "ws://localhost:8545");
+            assert_eq!(config.networks["local"].url(), "ws://localhost:8545");
             assert_eq!(config.subscriptions.len(), 2);
             assert_eq!(
                 config.subscriptions[0].contract_address,
@@ -71,3 +87,350 @@ fn test_load_config_invalid_env() {
         panic!("Configuration loading failed: {:?}", err);
     }
 }
+
+#[test]
+fn test_function_filter_selector() {
+    let function_filter = FunctionFilter::new("transfer(address,uint256)".to_string())
+        .expect("Invalid function signature");
+    assert_eq!(function_filter.function_name, "transfer");
+    assert_eq!(
+        function_filter.selector.as_slice(),
+        &[0xa9, 0x05, 0x9c, 0xbb]
+    );
+    assert_eq!(function_filter.data_types.len(), 2);
+
+    let input = [0xa9, 0x05, 0x9c, 0xbb, 0x01, 0x02];
+    assert_eq!(function_filter.match_input(&input), Some(&input[4..]));
+    assert_eq!(function_filter.match_input(&input[1..]), None);
+
+    let no_args = FunctionFilter::new("pause()".to_string()).expect("Invalid function signature");
+    assert!(no_args.data_types.is_empty());
+}
+
+#[test]
+fn test_function_filter_from_abi() {
+    let functions: Vec<FunctionFilter> = serde_yaml::from_str(
+        r#"
+        - transfer(address,uint256)
+        - {"type": "function", "name": "transfer", "stateMutability": "nonpayable",
+           "inputs": [{"name": "to", "type": "address"}, {"name": "amount", "type": "uint256"}],
+           "outputs": [{"name": "", "type": "bool"}]}
+        - name: submit
+          inputs:
+            - name: order
+              type: tuple
+              components:
+                - {name: id, type: uint256}
+                - {name: note, type: string}
+          outputs: []
+        "#,
+    )
+    .expect("Invalid function definitions");
+
+    assert_eq!(functions[1].signature, functions[0].signature);
+    assert_eq!(functions[1].selector, functions[0].selector);
+    assert_eq!(functions[1].data_types, functions[0].data_types);
+    assert_eq!(functions[2].signature, "submit((uint256,string))");
+    assert_eq!(functions[2].function_name, "submit");
+    assert_eq!(
+        functions[2].data_types,
+        vec![ParamType::Struct(vec![
+            ParamType::UInt(256),
+            ParamType::String
+        ])]
+    );
+}
+
+#[test]
+fn test_load_config_layered_profile() {
+    let config = load_config(Some("layered".to_string())).expect("Configuration loading failed");
+    assert_eq!(
+        config.networks["local"].url(),
+        "ws://node.internal:8545/rpc"
+    );
+    assert_eq!(config.subscriptions.len(), 2);
+
+    let effective_config =
+        load_effective_config(Some("layered".to_string()), &[]).expect("Loading failed");
+    assert_eq!(
+        effective_config.sources["networks.local.protocol"],
+        ValueSource::File("resources/application.yml".to_string())
+    );
+    assert_eq!(
+        effective_config.sources["networks.local.host"],
+        ValueSource::File("resources/application-layered.yml".to_string())
+    );
+    assert!(effective_config
+        .describe()
+        .contains("networks.local.path = rpc (resources/application-layered.yml)"));
+}
+
+#[test]
+fn test_load_config_command_line_overrides() {
+    let config = load_config_with_overrides(
+        Some("layered".to_string()),
+        &[
+            "networks.local.port=9545".to_string(),
+            "subscriptions.1.contract_address=0x0000000000000000000000000000000000000001"
+                .to_string(),
+        ],
+    )
+    .expect("Configuration loading failed");
+    assert_eq!(
+        config.networks["local"].url(),
+        "ws://node.internal:9545/rpc"
+    );
+    assert_eq!(
+        config.subscriptions[1].contract_address,
+        Address::from_str("0x0000000000000000000000000000000000000001").unwrap()
+    );
+    assert_eq!(config.subscriptions[1].events.len(), 1);
+}
+
+#[test]
+fn test_merge_environment_variables() {
+    let mut effective_config = EffectiveConfig::default();
+    effective_config
+        .merge_file("resources/application.yml")
+        .expect("Loading failed");
+    effective_config
+        .merge_environment(vec![
+            (
+                "BLOCKSTREAM_NETWORKS__LOCAL__HOST".to_string(),
+                "10.0.0.1".to_string(),
+            ),
+            (
+                "BLOCKSTREAM_NETWORKS__LOCAL__PROTOCOL".to_string(),
+                "http".to_string(),
+            ),
+            ("UNRELATED".to_string(), "ignored".to_string()),
+        ])
+        .expect("Merging failed");
+    effective_config
+        .merge_overrides(&["networks.local.protocol=https".to_string()])
+        .expect("Merging failed");
+
+    let config = effective_config
+        .configuration()
+        .expect("Configuration loading failed");
+    assert_eq!(config.networks["local"].url(), "https://10.0.0.1:8545");
+    assert_eq!(
+        effective_config.sources["networks.local.host"],
+        ValueSource::Environment("BLOCKSTREAM_NETWORKS__LOCAL__HOST".to_string())
+    );
+    assert_eq!(
+        effective_config.sources["networks.local.protocol"],
+        ValueSource::CommandLine
+    );
+}
+
+#[test]
+fn test_substitute_env_variables() {
+    std::env::set_var("PLACEHOLDER_TEST_SET", "value");
+    std::env::set_var("PLACEHOLDER_TEST_EMPTY", "");
+    std::env::remove_var("PLACEHOLDER_TEST_UNSET");
+
+    let substitute = |contents: &str| substitute_env_variables(contents).unwrap();
+    assert_eq!(substitute("a: ${PLACEHOLDER_TEST_SET}"), "a: value");
+    assert_eq!(
+        substitute("a: ${PLACEHOLDER_TEST_UNSET:default}"),
+        "a: default"
+    );
+    assert_eq!(substitute("a: ${PLACEHOLDER_TEST_EMPTY:default}"), "a: ");
+    assert_eq!(
+        substitute("a: ${PLACEHOLDER_TEST_EMPTY:-default}"),
+        "a: default"
+    );
+    assert_eq!(
+        substitute("a: ${PLACEHOLDER_TEST_SET:-default}"),
+        "a: value"
+    );
+    assert_eq!(
+        substitute("a: ${PLACEHOLDER_TEST_UNSET:${PLACEHOLDER_TEST_SET:other}}"),
+        "a: value"
+    );
+    assert_eq!(
+        substitute("a: ${PLACEHOLDER_TEST_UNSET:-${PLACEHOLDER_TEST_EMPTY:-nested}}"),
+        "a: nested"
+    );
+    assert_eq!(
+        substitute("a: $${PLACEHOLDER_TEST_SET} $5"),
+        "a: ${PLACEHOLDER_TEST_SET} $5"
+    );
+    assert_eq!(
+        substitute("a: ${PLACEHOLDER_TEST_SET:-${PLACEHOLDER_TEST_UNSET:?unused}}"),
+        "a: value"
+    );
+}
+
+#[test]
+fn test_substitute_env_variables_errors() {
+    std::env::remove_var("PLACEHOLDER_TEST_MISSING");
+
+    match substitute_env_variables("key: ${PLACEHOLDER_TEST_MISSING:?API key is required}") {
+        Err(ConfigError::MissingVariable { name, message }) => {
+            assert_eq!(name, "PLACEHOLDER_TEST_MISSING");
+            assert_eq!(message, "API key is required");
+        }
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    assert!(matches!(
+        substitute_env_variables("key: ${PLACEHOLDER_TEST_MISSING}"),
+        Err(ConfigError::MissingVariable { .. })
+    ));
+    assert!(matches!(
+        substitute_env_variables("key: ${PLACEHOLDER_TEST_MISSING:default"),
+        Err(ConfigError::UnterminatedPlaceholder(_))
+    ));
+    assert!(matches!(
+        substitute_env_variables("key: ${file:/nonexistent/secret}"),
+        Err(ConfigError::SecretFileError(_))
+    ));
+}
+
+#[test]
+fn test_substitute_secret_file() {
+    let path = std::env::temp_dir().join("blockstream-test-secret");
+    std::fs::write(&path, "s3cr3t\n").unwrap();
+
+    let substituted =
+        substitute_env_variables(&format!("key: ${{file:{}}}", path.display())).unwrap();
+    assert_eq!(substituted, "key: s3cr3t");
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_placeholders_are_substituted_into_values() {
+    std::env::set_var("PLACEHOLDER_TEST_SECRET", "p@ss: {word} # not a comment");
+    std::env::set_var("PLACEHOLDER_TEST_PORT", "9545");
+    std::env::remove_var("PLACEHOLDER_TEST_COMMENTED");
+    let path = std::env::temp_dir().join("blockstream-test-placeholders.yml");
+    std::fs::write(
+        &path,
+        "# Set ${PLACEHOLDER_TEST_COMMENTED} to override the key\n\
+         key: ${PLACEHOLDER_TEST_SECRET}\n\
+         port: ${PLACEHOLDER_TEST_PORT}\n\
+         url: http://host:${PLACEHOLDER_TEST_PORT}\n\
+         path: ${PLACEHOLDER_TEST_COMMENTED:}\n",
+    )
+    .unwrap();
+
+    let mut effective_config = EffectiveConfig::default();
+    effective_config
+        .merge_file(path.to_str().unwrap())
+        .expect("Loading failed");
+    std::fs::remove_file(path).unwrap();
+
+    let value = &effective_config.value;
+    assert_eq!(value["key"], "p@ss: {word} # not a comment");
+    assert_eq!(value["port"], 9545);
+    assert_eq!(value["url"], "http://host:9545");
+    assert_eq!(value["path"], "");
+}
+
+#[test]
+fn test_network_url_configuration() {
+    let network: Network = serde_yaml::from_str(
+        "url: https://mainnet.example.com/v3?apiKey=abc\n\
+         auth:\n  bearer_token: secret\n  headers:\n    X-Api-Key: abc\n",
+    )
+    .expect("Parsing failed");
+    assert_eq!(network.protocol, NetworkProtocol::Https);
+    assert_eq!(network.url(), "https://mainnet.example.com/v3?apiKey=abc");
+    assert_eq!(
+        network.auth.authorization(),
+        Some(Authorization::bearer("secret"))
+    );
+    assert_eq!(network.auth.headers["X-Api-Key"], "abc");
+
+    let network: Network = serde_yaml::from_str("url: /tmp/geth.ipc").expect("Parsing failed");
+    assert_eq!(network.protocol, NetworkProtocol::Ipc);
+    assert_eq!(network.url(), "/tmp/geth.ipc");
+
+    let network: Network =
+        serde_yaml::from_str("url: ipc:///tmp/geth.ipc").expect("Parsing failed");
+    assert_eq!(network.protocol, NetworkProtocol::Ipc);
+    assert_eq!(network.url(), "/tmp/geth.ipc");
+}
+
+#[test]
+fn test_network_structured_configuration() {
+    let network: Network =
+        serde_yaml::from_str("protocol: ipc\npath: /tmp/geth.ipc").expect("Parsing failed");
+    assert_eq!(network.url(), "/tmp/geth.ipc");
+
+    let network: Network = serde_yaml::from_str("protocol: https\nhost: rpc.example.com\npath: v1")
+        .expect("Parsing failed");
+    assert_eq!(network.url(), "https://rpc.example.com/v1");
+
+    for invalid in [
+        "url: http://localhost:8545\nhost: localhost",
+        "protocol: ipc",
+        "host: localhost",
+        "url: udp://localhost:8545",
+    ] {
+        assert!(
+            serde_yaml::from_str::<Network>(invalid).is_err(),
+            "{} should be rejected",
+            invalid
+        );
+    }
+}
+
+#[test]
+fn test_load_config_multiple_networks() {
+    let config = load_config(Some("multichain".to_string())).expect("Configuration loading failed");
+    let mainnet = &config.networks["mainnet"];
+    assert_eq!(mainnet.protocol, NetworkProtocol::SecureWebSocket);
+    assert_eq!(mainnet.confirmations, 12);
+    assert_eq!(mainnet.receipt_concurrency, DEFAULT_RECEIPT_CONCURRENCY);
+    let arbitrum = &config.networks["arbitrum"];
+    assert_eq!(arbitrum.protocol, NetworkProtocol::Https);
+    assert_eq!(arbitrum.poll_interval, Some(Duration::from_millis(500)));
+    assert_eq!(arbitrum.poll_mode, PollMode::Head);
+    assert_eq!(mainnet.poll_mode, PollMode::Filter);
+    assert_eq!(arbitrum.receipt_concurrency, 32);
+    assert_eq!(
+        arbitrum.batch,
+        Some(BatchConfig {
+            max_size: 20,
+            window_ms: 5
+        })
+    );
+    assert_eq!(mainnet.batch, None);
+    assert_eq!(
+        mainnet.retry,
+        RetryPolicy::default()
+            .with_max_attempts(8)
+            .with_on_exhaustion(OnExhaustion::Halt)
+    );
+    assert_eq!(arbitrum.retry, RetryPolicy::default());
+
+    assert_eq!(config.subscriptions_for("mainnet").len(), 1);
+    assert_eq!(config.subscriptions_for("arbitrum").len(), 1);
+    assert!(config.subscriptions_for("local").is_empty());
+}
+
+#[test]
+fn test_subscription_network_references() {
+    let config: Configuration = serde_yaml::from_str(
+        "networks:\n  local:\n    url: http://localhost:8545\n\
+         subscriptions:\n  - contract_address: 0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512\n    events: []\n",
+    )
+    .expect("Parsing failed");
+    assert_eq!(config.subscriptions[0].network, "local");
+
+    let networks = "networks:\n  a:\n    url: http://a:8545\n  b:\n    url: http://b:8545\n";
+    for (subscription, expected) in [
+        ("network: c\n    ", "references unknown network 'c'"),
+        ("", "must name its network"),
+    ] {
+        let contents = format!(
+            "{}subscriptions:\n  - {}contract_address: 0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512\n    events: []\n",
+            networks, subscription
+        );
+        let err = serde_yaml::from_str::<Configuration>(&contents).unwrap_err();
+        assert!(err.to_string().contains(expected), "{}", err);
+    }
+}
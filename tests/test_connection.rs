@@ -0,0 +1,218 @@
+mod common;
+
+use std::future::IntoFuture;
+use std::time::{Duration, Instant};
+
+use alloy::primitives::{Address, U256};
+use alloy::providers::Provider;
+use blockstream::blockchain::batch::BatchConfig;
+use blockstream::blockchain::configuration::{Network, NetworkAuth};
+use blockstream::blockchain::connection::{build_connection, rate_limit_layer};
+use blockstream::blockchain::http::RateLimited;
+use blockstream::blockchain::rate_limit::RateLimitConfig;
+use blockstream::blockchain::retry::Transient;
+use blockstream::metrics::{metrics, RPC_RATE_LIMITED};
+use common::{quantity, MockRpcServer};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_http_connection_sends_auth_headers() {
+    let server = MockRpcServer::start(|method, _| match method {
+        "eth_blockNumber" => Ok(json!(quantity(42))),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let auth = NetworkAuth {
+        bearer_token: Some("secret-token".to_string()),
+        headers: [("X-Api-Key".to_string(), "abc123".to_string())].into(),
+        ..NetworkAuth::default()
+    };
+    let network = Network::from_url(format!("{}/v3?network=mainnet", server.url))
+        .expect("Invalid URL")
+        .with_auth(auth);
+    let provider = build_connection("local", &network, rate_limit_layer("local", &network))
+        .await
+        .expect("Connection failed");
+    assert_eq!(provider.get_block_number().await.unwrap(), 42);
+    assert_eq!(
+        server.header_values("authorization"),
+        vec!["Bearer secret-token"]
+    );
+    assert_eq!(server.header_values("x-api-key"), vec!["abc123"]);
+}
+
+#[tokio::test]
+async fn test_http_connection_uses_url_credentials() {
+    let server = MockRpcServer::start(|method, _| match method {
+        "eth_blockNumber" => Ok(json!(quantity(7))),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let url = server.url.replace("http://", "http://user:pass@");
+    let network = Network::from_url(url).unwrap();
+
+    let provider = build_connection("local", &network, rate_limit_layer("local", &network))
+        .await
+        .expect("Connection failed");
+    assert_eq!(provider.get_block_number().await.unwrap(), 7);
+    assert_eq!(
+        server.header_values("authorization"),
+        vec!["Basic dXNlcjpwYXNz"]
+    );
+}
+
+#[tokio::test]
+async fn test_http_connection_batches_concurrent_requests() {
+    let server = MockRpcServer::start(|method, params| match method {
+        "eth_getBalance" => Ok(json!(params[0].clone())),
+        "eth_chainId" => Err(json!({ "code": -32000, "message": "unavailable" })),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let network = Network::from_url(server.url.clone())
+        .unwrap()
+        .with_batch(BatchConfig {
+            max_size: 3,
+            window_ms: 50,
+        });
+    let provider = build_connection("local", &network, rate_limit_layer("local", &network))
+        .await
+        .expect("Connection failed");
+
+    let addresses: Vec<Address> = (1..=5u8).map(Address::repeat_byte).collect();
+    let (balances, chain_id) = tokio::join!(
+        futures_util::future::join_all(
+            addresses
+                .iter()
+                .map(|address| provider.get_balance(*address).into_future())
+        ),
+        provider.get_chain_id().into_future()
+    );
+
+    for (address, balance) in addresses.iter().zip(balances) {
+        assert_eq!(balance.unwrap(), U256::from_be_slice(address.as_slice()));
+    }
+    assert!(chain_id.is_err());
+    let mut batch_sizes = server.batch_sizes();
+    batch_sizes.sort();
+    assert_eq!(batch_sizes, vec![3, 3]);
+}
+
+#[tokio::test]
+async fn test_failed_batch_keeps_the_error_kind_for_each_request() {
+    let server = MockRpcServer::start(|method, params| match method {
+        "eth_getBalance" => Ok(json!(params[0].clone())),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let network = Network::from_url(server.url.clone())
+        .unwrap()
+        .with_batch(BatchConfig {
+            max_size: 3,
+            window_ms: 50,
+        });
+    let provider = build_connection("local", &network, rate_limit_layer("local", &network))
+        .await
+        .expect("Connection failed");
+
+    server.throttle(1, None);
+    let balances = futures_util::future::join_all((1..=3u8).map(|byte| {
+        provider
+            .get_balance(Address::repeat_byte(byte))
+            .into_future()
+    }))
+    .await;
+
+    for balance in balances {
+        let err = balance.unwrap_err();
+        assert!(RateLimited::from_error(&err).is_some(), "{}", err);
+        assert!(err.is_transient(), "{}", err);
+    }
+}
+
+#[tokio::test]
+async fn test_rate_limited_requests_are_retried_after_the_requested_delay() {
+    let server = MockRpcServer::start(|method, _| match method {
+        "eth_blockNumber" => Ok(json!(quantity(9))),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let network = Network::from_url(server.url.clone())
+        .unwrap()
+        .with_rate_limit(RateLimitConfig::new(100.0).unwrap().with_max_retries(2));
+    let provider = build_connection("local", &network, rate_limit_layer("local", &network))
+        .await
+        .expect("Connection failed");
+
+    server.throttle(2, Some(1));
+    let started = Instant::now();
+    assert_eq!(provider.get_block_number().await.unwrap(), 9);
+    assert!(started.elapsed() >= Duration::from_secs(2));
+    assert_eq!(
+        metrics().counter(
+            RPC_RATE_LIMITED,
+            &[("network", "local"), ("method", "eth_blockNumber")]
+        ),
+        2
+    );
+
+    server.throttle(3, Some(0));
+    let err = provider.get_block_number().await.unwrap_err();
+    assert!(err.to_string().contains("429"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_rate_limit_spends_method_costs() {
+    let server = MockRpcServer::start(|method, _| match method {
+        "eth_chainId" => Ok(json!(quantity(1))),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let network = Network::from_url(server.url.clone())
+        .unwrap()
+        .with_rate_limit(
+            RateLimitConfig::new(10.0)
+                .unwrap()
+                .with_burst(10)
+                .with_method_cost("eth_chainId", 5),
+        );
+    let provider = build_connection("local", &network, rate_limit_layer("local", &network))
+        .await
+        .expect("Connection failed");
+
+    let started = Instant::now();
+    for _ in 0..3 {
+        provider.get_chain_id().await.unwrap();
+    }
+    // The burst covers two calls, the third waits for 5 units at 10 per second.
+    assert!(started.elapsed() >= Duration::from_millis(450));
+}
+
+#[tokio::test]
+async fn test_connections_to_a_network_share_its_rate_limit() {
+    let server = MockRpcServer::start(|method, _| match method {
+        "eth_chainId" => Ok(json!(quantity(1))),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let network = Network::from_url(server.url.clone())
+        .unwrap()
+        .with_rate_limit(RateLimitConfig::new(10.0).unwrap().with_burst(2));
+    let rate_limit = rate_limit_layer("local", &network);
+    let live = build_connection("local", &network, rate_limit.clone())
+        .await
+        .expect("Connection failed");
+    let backfill = build_connection("local", &network, rate_limit)
+        .await
+        .expect("Connection failed");
+
+    let started = Instant::now();
+    for _ in 0..2 {
+        live.get_chain_id().await.unwrap();
+        backfill.get_chain_id().await.unwrap();
+    }
+    // The burst covers two calls, the other two wait for a token each at 10
+    // per second, which separate buckets would not.
+    assert!(started.elapsed() >= Duration::from_millis(180));
+    assert_eq!(server.requests("eth_chainId").len(), 4);
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use alloy::providers::Provider;
+use blockstream::blockchain::configuration::Network;
+use blockstream::blockchain::connection::build_connection;
+use blockstream::metrics::{
+    metrics, serve_metrics, RPC_ERRORS, RPC_REQUESTS, SINK_ERRORS, SINK_WRITE_LATENCY,
+};
+use blockstream::runtime::{flush_sink, write_sink};
+use common::{quantity, MockRpcServer};
+use serde_json::json;
+use std::io::{self, Write};
+use tokio::net::TcpListener;
+
+#[tokio::test]
+async fn test_rpc_requests_are_exposed() {
+    let server = MockRpcServer::start(|method, _| match method {
+        "eth_blockNumber" => Ok(json!(quantity(42))),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = build_connection(
+        "metrics-test",
+        &Network::from_url(server.url.clone()).unwrap(),
+        None,
+    )
+    .await
+    .expect("Connection failed");
+    provider.get_block_number().await.unwrap();
+    assert!(provider.get_chain_id().await.is_err());
+
+    let labels = |method| [("network", "metrics-test"), ("method", method)];
+    assert_eq!(
+        metrics().counter(RPC_REQUESTS, &labels("eth_blockNumber")),
+        1
+    );
+    assert_eq!(metrics().counter(RPC_ERRORS, &labels("eth_chainId")), 1);
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(serve_metrics(listener));
+
+    let response = reqwest::get(format!("http://{}/metrics", address))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("# TYPE blockstream_rpc_requests_total counter"));
+    assert!(body.contains(
+        "blockstream_rpc_requests_total{network=\"metrics-test\",method=\"eth_blockNumber\"} 1"
+    ));
+    assert!(body.contains(
+        "blockstream_rpc_request_duration_seconds_count{network=\"metrics-test\",method=\"eth_blockNumber\"} 1"
+    ));
+
+    let response = reqwest::get(format!("http://{}/other", address))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+/// A sink whose writes and flushes always fail.
+struct BrokenSink;
+
+impl Write for BrokenSink {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::ErrorKind::BrokenPipe.into())
+    }
+}
+
+#[test]
+fn test_sink_errors_are_counted() {
+    let labels = [("sink", "broken-test")];
+    let mut sink = BrokenSink;
+
+    write_sink("broken-test", || Ok(())).unwrap();
+    assert_eq!(metrics().counter(SINK_ERRORS, &labels), 0);
+
+    assert!(write_sink("broken-test", || sink.write_all(b"output")).is_err());
+    assert!(flush_sink("broken-test", &mut sink).is_err());
+    assert_eq!(metrics().counter(SINK_ERRORS, &labels), 2);
+
+    let body = metrics().render();
+    assert!(body.contains("# TYPE blockstream_sink_errors_total counter"));
+    assert!(body.contains("blockstream_sink_errors_total{sink=\"broken-test\"} 2"));
+    assert!(body.contains(&format!(
+        "{}_count{{sink=\"broken-test\"}} 2",
+        SINK_WRITE_LATENCY
+    )));
+}
@@ -0,0 +1,99 @@
+use std::str::FromStr;
+
+use alloy::primitives::{Address, U256};
+use blockstream::blockchain::decoder::Parameter;
+use blockstream::blockchain::parameters::{FromParameters, ParameterError};
+
+const ALICE: &str = "0x0000000000000000000000000000000000000001";
+const BOB: &str = "0x0000000000000000000000000000000000000002";
+
+#[derive(Debug, PartialEq, FromParameters)]
+struct Leg {
+    to: Address,
+    amount: U256,
+}
+
+#[derive(Debug, PartialEq, FromParameters)]
+struct Batch {
+    #[parameter(name = "from")]
+    sender: Address,
+    memo: String,
+    legs: Vec<Leg>,
+}
+
+fn address(value: &str) -> Address {
+    Address::from_str(value).unwrap()
+}
+
+fn leg(to: &str, amount: usize) -> Parameter {
+    Parameter::Struct(vec![
+        Parameter::Address(address(to)),
+        Parameter::Uint(amount),
+    ])
+}
+
+#[test]
+fn test_from_parameters_by_position() {
+    let batch = Batch::from_parameters(&[
+        Parameter::Address(address(ALICE)),
+        Parameter::String("rent".to_string()),
+        Parameter::Array(vec![leg(BOB, 5), leg(ALICE, 7)]),
+    ])
+    .unwrap();
+
+    assert_eq!(
+        batch,
+        Batch {
+            sender: address(ALICE),
+            memo: "rent".to_string(),
+            legs: vec![
+                Leg {
+                    to: address(BOB),
+                    amount: U256::from(5)
+                },
+                Leg {
+                    to: address(ALICE),
+                    amount: U256::from(7)
+                },
+            ],
+        }
+    );
+}
+
+#[test]
+fn test_from_parameters_by_name() {
+    let batch = Batch::from_named_parameters(
+        &["legs", "memo", "from"],
+        &[
+            Parameter::Array(vec![]),
+            Parameter::String("rent".to_string()),
+            Parameter::Address(address(BOB)),
+        ],
+    )
+    .unwrap();
+
+    assert_eq!(batch.sender, address(BOB));
+    assert_eq!(batch.memo, "rent");
+    assert!(batch.legs.is_empty());
+}
+
+#[test]
+fn test_conversion_errors_name_the_field() {
+    let err = Batch::from_parameters(&[
+        Parameter::Address(address(ALICE)),
+        Parameter::String("rent".to_string()),
+        Parameter::Array(vec![
+            leg(BOB, 5),
+            Parameter::Struct(vec![Parameter::Bool(true)]),
+        ]),
+    ])
+    .unwrap_err();
+    assert_eq!(err.to_string(), "legs[1].to: Expected address, found bool");
+
+    let err = Batch::from_parameters(&[Parameter::Address(address(ALICE))]).unwrap_err();
+    assert_eq!(err.to_string(), "memo: Missing parameter at position 1");
+
+    let err =
+        Batch::from_named_parameters(&["from"], &[Parameter::Address(address(ALICE))]).unwrap_err();
+    assert_eq!(err, ParameterError::MissingName("memo".to_string()));
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use alloy::primitives::{Address, U256};
+use alloy::providers::{Provider, ProviderBuilder};
+use alloy::rpc::types::BlockTransactionsKind;
+use alloy::sol;
+use alloy::sol_types::SolEvent;
+use blockstream::blockchain::event::DecodedEvent;
+use blockstream::blockchain::pipeline::Pipeline;
+use common::{
+    block_json, hash, log_json, receipt_json, transaction_json, MockRpcServer, CONTRACT_ADDRESS,
+    SENDER_ADDRESS,
+};
+use serde_json::json;
+
+sol! {
+    interface IERC20 {
+        event Transfer(address indexed from, address indexed to, uint256 value);
+        event Approval(address indexed owner, address indexed spender, uint256 value);
+    }
+}
+
+fn topic(address: &str) -> String {
+    format!("0x{:0>64}", address.trim_start_matches("0x"))
+}
+
+#[tokio::test]
+async fn test_typed_handlers_receive_decoded_events() {
+    let transfer = log_json(
+        &hash(1),
+        7,
+        vec![
+            IERC20::Transfer::SIGNATURE_HASH.to_string(),
+            topic(SENDER_ADDRESS),
+            topic(CONTRACT_ADDRESS),
+        ],
+        &format!("0x{:064x}", 42),
+    );
+    let mut approval = transfer.clone();
+    approval["topics"][0] = json!(IERC20::Approval::SIGNATURE_HASH.to_string());
+    approval["logIndex"] = json!("0x1");
+    let server = MockRpcServer::start(move |method, _| match method {
+        "eth_getBlockByNumber" => Ok(block_json(
+            7,
+            vec![transaction_json(&hash(1), 7, CONTRACT_ADDRESS, "0x")],
+        )),
+        "eth_getTransactionReceipt" => Ok(receipt_json(
+            &hash(1),
+            7,
+            true,
+            vec![transfer.clone(), approval.clone()],
+        )),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let address = Address::from_str(CONTRACT_ADDRESS).unwrap();
+
+    let transfers = Arc::new(Mutex::new(Vec::new()));
+    let approvals = Arc::new(Mutex::new(Vec::new()));
+    let mut pipeline = Pipeline::new(provider.clone()).with_chain_id(31337);
+    pipeline
+        .on::<IERC20::Transfer>(address, {
+            let transfers = transfers.clone();
+            move |event, meta| transfers.lock().unwrap().push((event, meta))
+        })
+        .register(address, {
+            let approvals = approvals.clone();
+            move |event: IERC20::Approval, _: DecodedEvent| {
+                let approvals = approvals.clone();
+                async move { approvals.lock().unwrap().push(event.value) }
+            }
+        });
+
+    let block = provider
+        .get_block_by_number(7.into(), BlockTransactionsKind::Full)
+        .await
+        .unwrap()
+        .unwrap();
+    pipeline.process_block(block).await.unwrap();
+
+    let transfers = transfers.lock().unwrap();
+    assert_eq!(transfers.len(), 1);
+    let (event, meta) = &transfers[0];
+    assert_eq!(event.from, Address::from_str(SENDER_ADDRESS).unwrap());
+    assert_eq!(event.to, address);
+    assert_eq!(event.value, U256::from(42));
+    assert_eq!(meta.chain_id, 31337);
+    assert_eq!(meta.address, address);
+    assert_eq!(meta.block_number, 7);
+    assert_eq!(meta.transaction_hash.to_string(), hash(1));
+    assert_eq!(meta.from, Address::from_str(SENDER_ADDRESS).unwrap());
+    assert_eq!(meta.event_name, "Transfer");
+    assert_eq!(meta.log_index, Some(0));
+    assert_eq!(*approvals.lock().unwrap(), vec![U256::from(42)]);
+    assert_eq!(server.requests("eth_getTransactionReceipt").len(), 1);
+}
@@ -0,0 +1,512 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use alloy::primitives::Address;
+use blockstream::blockchain::configuration::{Configuration, Network};
+use blockstream::blockchain::retry::{OnExhaustion, RetryPolicy};
+use blockstream::metrics::{metrics, BLOCKS_PARKED, BLOCKS_SKIPPED};
+use blockstream::runtime::{backfill, ReloadError, Runtime, RuntimeError};
+use blockstream::shutdown::{load_checkpoint, ShutdownError};
+use common::{block_json, hash, quantity, transaction_json, MockRpcServer, CONTRACT_ADDRESS};
+use serde_json::json;
+use tokio_util::sync::CancellationToken;
+
+const OTHER_ADDRESS: &str = "0x0000000000000000000000000000000000000001";
+
+fn configuration(url: &str, subscriptions: &[(&str, Option<u64>)]) -> Configuration {
+    let mut contents = format!("networks:\n  local:\n    url: {}\nsubscriptions:\n", url);
+    for (address, start_block) in subscriptions {
+        contents.push_str(&format!(
+            "  - contract_address: {}\n    events:\n      - Transfer(address,address,uint256)\n",
+            address
+        ));
+        if let Some(start_block) = start_block {
+            contents.push_str(&format!("    start_block: {}\n", start_block));
+        }
+    }
+    serde_yaml::from_str(&contents).expect("Parsing failed")
+}
+
+#[tokio::test]
+async fn test_reload_replaces_subscriptions() {
+    let runtime = Runtime::new(configuration(
+        "http://localhost:8545",
+        &[(CONTRACT_ADDRESS, None)],
+    ));
+
+    let summary = runtime
+        .reload(configuration(
+            "http://localhost:8545",
+            &[(OTHER_ADDRESS, None)],
+        ))
+        .expect("Reload failed");
+    assert_eq!(summary.added.len(), 1);
+    assert_eq!(summary.removed.len(), 1);
+    assert_eq!(
+        summary.added[0].contract_address,
+        Address::from_str(OTHER_ADDRESS).unwrap()
+    );
+
+    let subscriptions = runtime.subscriptions("local");
+    assert_eq!(subscriptions.len(), 1);
+    assert_eq!(subscriptions[0], summary.added[0]);
+
+    let summary = runtime
+        .reload(configuration(
+            "http://localhost:8545",
+            &[(OTHER_ADDRESS, None)],
+        ))
+        .expect("Reload failed");
+    assert!(summary.added.is_empty() && summary.removed.is_empty());
+}
+
+#[tokio::test]
+async fn test_reload_rejects_network_changes() {
+    let runtime = Runtime::new(configuration(
+        "http://localhost:8545",
+        &[(CONTRACT_ADDRESS, None)],
+    ));
+
+    match runtime.reload(configuration(
+        "http://localhost:9545",
+        &[(OTHER_ADDRESS, None)],
+    )) {
+        Err(ReloadError::NetworkChanged(name)) => assert_eq!(name, "local"),
+        other => panic!("Unexpected reload result: {:?}", other.map(|_| ())),
+    }
+    assert_eq!(
+        runtime.subscriptions("local")[0].contract_address,
+        Address::from_str(CONTRACT_ADDRESS).unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_backfill_from_start_block() {
+    let server = MockRpcServer::start(|method, params| match method {
+        "eth_chainId" => Ok(json!(quantity(31337))),
+        "eth_blockNumber" => Ok(json!(quantity(12))),
+        "eth_getBlockByNumber" => {
+            let number =
+                u64::from_str_radix(params[0].as_str().unwrap().trim_start_matches("0x"), 16)
+                    .unwrap();
+            let transactions = if number == 11 {
+                vec![transaction_json(&hash(11), 11, CONTRACT_ADDRESS, "0x")]
+            } else {
+                vec![]
+            };
+            Ok(block_json(number, transactions))
+        }
+        "eth_getTransactionReceipt" => Ok(common::receipt_json(&hash(11), 11, true, vec![])),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let config = configuration(&server.url, &[(CONTRACT_ADDRESS, Some(10))]);
+
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        backfill(
+            "local",
+            &config.networks["local"],
+            None,
+            config.subscriptions.clone(),
+            &CancellationToken::new(),
+        ),
+    )
+    .await
+    .expect("Backfill timed out")
+    .expect("Backfill failed");
+
+    let blocks: Vec<_> = server
+        .requests("eth_getBlockByNumber")
+        .iter()
+        .map(|params| params[0].clone())
+        .collect();
+    assert_eq!(blocks, vec![json!("0xa"), json!("0xb"), json!("0xc")]);
+    assert_eq!(
+        server.requests("eth_getTransactionReceipt"),
+        vec![json!([hash(11)])]
+    );
+}
+
+/// Serves blocks 10 to 12, where block 11 holds a subscribed transaction
+/// whose receipt is only returned from the `available_from`th request on.
+async fn flaky_receipt_server(available_from: usize) -> MockRpcServer {
+    let receipt_requests = AtomicUsize::new(0);
+    MockRpcServer::start(move |method, params| match method {
+        "eth_chainId" => Ok(json!(quantity(31337))),
+        "eth_blockNumber" => Ok(json!(quantity(12))),
+        "eth_getBlockByNumber" => {
+            let number =
+                u64::from_str_radix(params[0].as_str().unwrap().trim_start_matches("0x"), 16)
+                    .unwrap();
+            let transactions = if number == 11 {
+                vec![transaction_json(&hash(11), 11, CONTRACT_ADDRESS, "0x")]
+            } else {
+                vec![]
+            };
+            Ok(block_json(number, transactions))
+        }
+        "eth_getTransactionReceipt" => {
+            if receipt_requests.fetch_add(1, Ordering::Relaxed) + 1 < available_from {
+                Ok(json!(null))
+            } else {
+                Ok(common::receipt_json(&hash(11), 11, true, vec![]))
+            }
+        }
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await
+}
+
+async fn backfill_with_retry(
+    server: &MockRpcServer,
+    retry: RetryPolicy,
+) -> Result<(), RuntimeError> {
+    let config = configuration(&server.url, &[(CONTRACT_ADDRESS, Some(10))]);
+    let network: Network = config.networks["local"]
+        .clone()
+        .with_retry(retry.with_backoff(Duration::from_millis(1), Duration::from_millis(1)));
+    tokio::time::timeout(
+        Duration::from_secs(5),
+        backfill(
+            "local",
+            &network,
+            None,
+            config.subscriptions.clone(),
+            &CancellationToken::new(),
+        ),
+    )
+    .await
+    .expect("Backfill timed out")
+}
+
+fn requested_blocks(server: &MockRpcServer) -> Vec<serde_json::Value> {
+    server
+        .requests("eth_getBlockByNumber")
+        .iter()
+        .map(|params| params[0].clone())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_missing_receipts_are_retried() {
+    let server = flaky_receipt_server(3).await;
+
+    backfill_with_retry(&server, RetryPolicy::default().with_max_attempts(3))
+        .await
+        .expect("Backfill failed");
+
+    assert_eq!(server.requests("eth_getTransactionReceipt").len(), 3);
+    assert_eq!(
+        requested_blocks(&server),
+        vec![json!("0xa"), json!("0xb"), json!("0xc")]
+    );
+}
+
+#[tokio::test]
+async fn test_exhausted_block_halts_when_configured() {
+    let server = flaky_receipt_server(usize::MAX).await;
+
+    let result = backfill_with_retry(
+        &server,
+        RetryPolicy::default()
+            .with_max_attempts(2)
+            .with_on_exhaustion(OnExhaustion::Halt),
+    )
+    .await;
+
+    match result {
+        Err(RuntimeError::BlockFailed { number, .. }) => assert_eq!(number, 11),
+        other => panic!("Unexpected backfill result: {:?}", other),
+    }
+    assert_eq!(server.requests("eth_getTransactionReceipt").len(), 2);
+    assert_eq!(requested_blocks(&server), vec![json!("0xa"), json!("0xb")]);
+}
+
+#[tokio::test]
+async fn test_exhausted_block_is_skipped_by_default() {
+    let server = flaky_receipt_server(usize::MAX).await;
+    let labels = [("network", "local")];
+    let skipped = metrics().counter(BLOCKS_SKIPPED, &labels);
+
+    backfill_with_retry(&server, RetryPolicy::default().with_max_attempts(2))
+        .await
+        .expect("Backfill failed");
+
+    assert_eq!(metrics().counter(BLOCKS_SKIPPED, &labels), skipped + 1);
+    assert_eq!(
+        requested_blocks(&server),
+        vec![json!("0xa"), json!("0xb"), json!("0xc")]
+    );
+}
+
+#[tokio::test]
+async fn test_parked_block_is_retried_with_later_blocks() {
+    let server = flaky_receipt_server(3).await;
+
+    backfill_with_retry(
+        &server,
+        RetryPolicy::default()
+            .with_max_attempts(1)
+            .with_on_exhaustion(OnExhaustion::Park),
+    )
+    .await
+    .expect("Backfill failed");
+
+    // Parked at block 11, retried before block 12 and once more at the end.
+    assert_eq!(server.requests("eth_getTransactionReceipt").len(), 3);
+    assert_eq!(
+        metrics().gauge(BLOCKS_PARKED, &[("network", "local")]),
+        Some(0)
+    );
+}
+
+#[tokio::test]
+async fn test_shutdown_drains_and_writes_checkpoint() {
+    let polled = AtomicBool::new(false);
+    let server = MockRpcServer::start(move |method, _| match method {
+        "eth_chainId" => Ok(json!(quantity(31337))),
+        "eth_newBlockFilter" => Ok(json!("0x1")),
+        "eth_getFilterChanges" if !polled.swap(true, Ordering::SeqCst) => Ok(json!([hash(10)])),
+        "eth_getFilterChanges" => Ok(json!([])),
+        "eth_getBlockByHash" => Ok(block_json(10, vec![])),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let checkpoint = std::env::temp_dir().join(format!("shutdown-{}.yml", std::process::id()));
+    let contents = format!(
+        "networks:\n  local:\n    url: {}\n    poll_interval_ms: 20\nsubscriptions: []\nshutdown:\n  checkpoint_path: {}\n",
+        server.url,
+        checkpoint.display()
+    );
+    let runtime = Arc::new(Runtime::new(
+        serde_yaml::from_str(&contents).expect("Parsing failed"),
+    ));
+    let state = runtime.states()["local"].clone();
+
+    let running = tokio::spawn({
+        let runtime = runtime.clone();
+        async move { runtime.run().await }
+    });
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while state.status().last_block != Some(10) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("No block processed");
+
+    runtime.cancellation().cancel();
+    tokio::time::timeout(Duration::from_secs(5), running)
+        .await
+        .expect("Shutdown timed out")
+        .unwrap();
+
+    assert!(!state.status().connected);
+    assert_eq!(
+        load_checkpoint(&checkpoint).unwrap(),
+        BTreeMap::from([("local".to_string(), 10)])
+    );
+    std::fs::remove_file(checkpoint).unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_shutdown_timeout_still_writes_checkpoint() {
+    let polls = AtomicUsize::new(0);
+    let server = MockRpcServer::start(move |method, params| match method {
+        "eth_chainId" => Ok(json!(quantity(31337))),
+        "eth_newBlockFilter" => Ok(json!("0x1")),
+        "eth_getFilterChanges" => match polls.fetch_add(1, Ordering::SeqCst) {
+            0 => Ok(json!([hash(10)])),
+            1 => Ok(json!([hash(11)])),
+            _ => Ok(json!([])),
+        },
+        "eth_getBlockByHash" if params[0] == json!(hash(10)) => Ok(block_json(10, vec![])),
+        "eth_getBlockByHash" => Ok(block_json(
+            11,
+            vec![transaction_json(&hash(11), 11, CONTRACT_ADDRESS, "0x")],
+        )),
+        "eth_getTransactionReceipt" => {
+            // Keeps block 11 in flight past the shutdown timeout.
+            tokio::task::block_in_place(|| std::thread::sleep(Duration::from_secs(3)));
+            Ok(common::receipt_json(&hash(11), 11, true, vec![]))
+        }
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let checkpoint = std::env::temp_dir().join(format!("timeout-{}.yml", std::process::id()));
+    let contents = format!(
+        "networks:\n  local:\n    url: {}\n    poll_interval_ms: 20\nsubscriptions:\n  - contract_address: {}\n    events:\n      - Transfer(address,address,uint256)\nshutdown:\n  timeout_secs: 1\n  checkpoint_path: {}\n",
+        server.url,
+        CONTRACT_ADDRESS,
+        checkpoint.display()
+    );
+    let runtime = Arc::new(Runtime::new(
+        serde_yaml::from_str(&contents).expect("Parsing failed"),
+    ));
+    let state = runtime.states()["local"].clone();
+    let cancellation = runtime.cancellation();
+
+    let running = tokio::spawn(runtime.run_with_timeout());
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while server.requests("eth_getTransactionReceipt").is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Block 11 not fetched");
+    assert_eq!(state.status().checkpoint, Some(10));
+
+    cancellation.cancel();
+    let result = tokio::time::timeout(Duration::from_secs(2), running)
+        .await
+        .expect("Shutdown timeout not applied")
+        .unwrap();
+
+    assert!(
+        matches!(result, Err(ShutdownError::TimedOut(timeout)) if timeout == Duration::from_secs(1))
+    );
+    assert_eq!(
+        load_checkpoint(&checkpoint).unwrap(),
+        BTreeMap::from([("local".to_string(), 10)])
+    );
+    std::fs::remove_file(checkpoint).unwrap();
+}
+
+#[tokio::test]
+async fn test_run_resumes_after_checkpoint() {
+    let polled = AtomicBool::new(false);
+    let server = MockRpcServer::start(move |method, params| match method {
+        "eth_chainId" => Ok(json!(quantity(31337))),
+        "eth_newBlockFilter" => Ok(json!("0x1")),
+        "eth_getFilterChanges" if !polled.swap(true, Ordering::SeqCst) => Ok(json!([hash(10)])),
+        "eth_getFilterChanges" => Ok(json!([])),
+        "eth_getBlockByHash" => Ok(block_json(10, vec![])),
+        "eth_getBlockByNumber" => {
+            let number =
+                u64::from_str_radix(params[0].as_str().unwrap().trim_start_matches("0x"), 16);
+            Ok(block_json(number.unwrap(), vec![]))
+        }
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let checkpoint = std::env::temp_dir().join(format!("resume-{}.yml", std::process::id()));
+    std::fs::write(&checkpoint, "local: 8\n").unwrap();
+    let contents = format!(
+        "networks:\n  local:\n    url: {}\n    poll_interval_ms: 20\nsubscriptions: []\nshutdown:\n  checkpoint_path: {}\n",
+        server.url,
+        checkpoint.display()
+    );
+    let runtime = Arc::new(Runtime::new(
+        serde_yaml::from_str(&contents).expect("Parsing failed"),
+    ));
+    let state = runtime.states()["local"].clone();
+    assert_eq!(state.status().checkpoint, Some(8));
+
+    let running = tokio::spawn({
+        let runtime = runtime.clone();
+        async move { runtime.run().await }
+    });
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while state.status().checkpoint != Some(10) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Blocks after the checkpoint not processed");
+    runtime.cancellation().cancel();
+    running.await.unwrap();
+
+    // Block 8 is only fetched for its hash; 9 fills the gap up to the head.
+    assert_eq!(requested_blocks(&server), vec![json!("0x8"), json!("0x9")]);
+    assert_eq!(
+        load_checkpoint(&checkpoint).unwrap(),
+        BTreeMap::from([("local".to_string(), 10)])
+    );
+    std::fs::remove_file(checkpoint).unwrap();
+}
+
+#[tokio::test]
+async fn test_block_fetch_exhaustion_halts_when_configured() {
+    let server = MockRpcServer::start(move |method, _| match method {
+        "eth_chainId" => Ok(json!(quantity(31337))),
+        "eth_newBlockFilter" => Ok(json!("0x1")),
+        "eth_getFilterChanges" => Ok(json!([hash(10)])),
+        "eth_getBlockByHash" => Ok(json!(null)),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let contents = format!(
+        "networks:\n  local:\n    url: {}\n    poll_interval_ms: 20\n    retry:\n      max_attempts: 2\n      initial_backoff_ms: 1\n      on_exhaustion: halt\nsubscriptions: []\n",
+        server.url
+    );
+    let runtime = Runtime::new(serde_yaml::from_str(&contents).expect("Parsing failed"));
+
+    tokio::time::timeout(Duration::from_secs(5), runtime.run())
+        .await
+        .expect("Network was not halted");
+
+    assert_eq!(server.requests("eth_getBlockByHash").len(), 2);
+    assert_eq!(server.requests("eth_newBlockFilter").len(), 1);
+    assert!(!runtime.states()["local"].status().connected);
+}
+
+#[tokio::test]
+async fn test_restarted_subscriber_resumes_after_the_last_block() {
+    let polls = AtomicUsize::new(0);
+    let server = MockRpcServer::start(move |method, params| match method {
+        "eth_chainId" => Ok(json!(quantity(31337))),
+        "eth_newBlockFilter" => Ok(json!("0x1")),
+        "eth_getFilterChanges" => match polls.fetch_add(1, Ordering::SeqCst) {
+            0 => Ok(json!([hash(10)])),
+            1 => Err(json!({ "code": -32601, "message": "method not found" })),
+            2 => Ok(json!([hash(13)])),
+            _ => Ok(json!([])),
+        },
+        "eth_getBlockByHash" if params[0] == json!(hash(10)) => Ok(block_json(10, vec![])),
+        "eth_getBlockByHash" => Ok(block_json(13, vec![])),
+        "eth_getBlockByNumber" => {
+            let number =
+                u64::from_str_radix(params[0].as_str().unwrap().trim_start_matches("0x"), 16);
+            Ok(block_json(number.unwrap(), vec![]))
+        }
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let contents = format!(
+        "networks:\n  local:\n    url: {}\n    poll_interval_ms: 20\nsubscriptions: []\n",
+        server.url
+    );
+    let runtime = Arc::new(Runtime::new(
+        serde_yaml::from_str(&contents).expect("Parsing failed"),
+    ));
+    let state = runtime.states()["local"].clone();
+
+    let running = tokio::spawn({
+        let runtime = runtime.clone();
+        async move { runtime.run().await }
+    });
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while state.status().checkpoint != Some(13) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("Blocks after the restart not processed");
+    runtime.cancellation().cancel();
+    running.await.unwrap();
+
+    // Blocks 11 and 12 are filled in after the restart, without fetching
+    // block 10 again to resume after it.
+    assert_eq!(server.requests("eth_newBlockFilter").len(), 2);
+    assert_eq!(requested_blocks(&server), vec![json!("0xb"), json!("0xc")]);
+    assert_eq!(
+        state.status().checkpoint_hash.unwrap().to_string(),
+        hash(13)
+    );
+}
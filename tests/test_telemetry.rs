@@ -0,0 +1,137 @@
+mod common;
+
+use std::time::Duration;
+
+use alloy::primitives::keccak256;
+use blockstream::blockchain::configuration::Configuration;
+use blockstream::runtime::backfill;
+use blockstream::utils::telemetry::{otlp_tracer_provider, trace_layer};
+use common::{
+    block_json, hash, log_json, quantity, receipt_json, transaction_json, MockRpcServer,
+    CONTRACT_ADDRESS,
+};
+use serde_json::json;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Stands in for an OTLP/HTTP collector, forwarding the path and body of
+/// every request it receives.
+async fn start_collector() -> (String, mpsc::UnboundedReceiver<(String, Vec<u8>)>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (sender, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let sender = sender.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut reader = BufReader::new(reader);
+                loop {
+                    let mut request_line = String::new();
+                    if reader.read_line(&mut request_line).await.unwrap_or(0) == 0 {
+                        return;
+                    }
+                    let path = request_line
+                        .split_whitespace()
+                        .nth(1)
+                        .unwrap_or_default()
+                        .to_string();
+                    let mut content_length = 0;
+                    loop {
+                        let mut header = String::new();
+                        reader.read_line(&mut header).await.unwrap();
+                        let header = header.trim_end();
+                        if header.is_empty() {
+                            break;
+                        }
+                        if let Some((name, value)) = header.split_once(':') {
+                            if name.eq_ignore_ascii_case("content-length") {
+                                content_length = value.trim().parse().unwrap();
+                            }
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    reader.read_exact(&mut body).await.unwrap();
+                    let _ = sender.send((path, body));
+                    writer
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+                        .await
+                        .unwrap();
+                }
+            });
+        }
+    });
+    (url, receiver)
+}
+
+fn contains(haystack: &[u8], needle: &str) -> bool {
+    haystack
+        .windows(needle.len())
+        .any(|window| window == needle.as_bytes())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_block_spans_are_exported_over_otlp() {
+    let topic = keccak256("Stored(uint256)".as_bytes()).to_string();
+    let server = MockRpcServer::start(move |method, params| match method {
+        "eth_chainId" => Ok(json!(quantity(31337))),
+        "eth_blockNumber" => Ok(json!(quantity(11))),
+        "eth_getBlockByNumber" => {
+            assert_eq!(params[0], json!("0xb"));
+            Ok(block_json(
+                11,
+                vec![transaction_json(&hash(1), 11, CONTRACT_ADDRESS, "0x")],
+            ))
+        }
+        "eth_getTransactionReceipt" => Ok(receipt_json(
+            &hash(1),
+            11,
+            true,
+            vec![log_json(&hash(1), 11, vec![topic.clone()], &hash(7))],
+        )),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let config: Configuration = serde_yaml::from_str(&format!(
+        "networks:\n  local:\n    url: {}\nsubscriptions:\n  - contract_address: {}\n    start_block: 11\n    events:\n      - Stored(uint256)\n",
+        server.url, CONTRACT_ADDRESS
+    ))
+    .expect("Parsing failed");
+
+    let (collector, mut requests) = start_collector().await;
+    let provider = otlp_tracer_provider(&format!("{}/v1/traces", collector)).unwrap();
+    let subscriber = tracing_subscriber::registry().with(trace_layer(&provider));
+    let guard = tracing::subscriber::set_default(subscriber);
+    backfill(
+        "local",
+        &config.networks["local"],
+        None,
+        config.subscriptions.clone(),
+        &CancellationToken::new(),
+    )
+    .await
+    .expect("Backfill failed");
+    drop(guard);
+    tokio::task::spawn_blocking(move || provider.force_flush())
+        .await
+        .unwrap();
+
+    let (path, body) = tokio::time::timeout(Duration::from_secs(5), requests.recv())
+        .await
+        .expect("No traces exported")
+        .unwrap();
+    assert_eq!(path, "/v1/traces");
+    for name in [
+        "blockstream",
+        "block",
+        "transaction",
+        "fetch_receipt",
+        "decode",
+        "sink_write",
+    ] {
+        assert!(contains(&body, name), "span {} was not exported", name);
+    }
+}
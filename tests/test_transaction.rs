@@ -0,0 +1,508 @@
+mod common;
+
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use alloy::primitives::Address;
+use alloy::providers::ProviderBuilder;
+use alloy::rpc::types::Block;
+use blockstream::blockchain::configuration::{EventFilter, EventSubscription, FunctionFilter};
+use blockstream::blockchain::decoder::Parameter;
+use blockstream::blockchain::retry::RetryPolicy;
+use blockstream::blockchain::revert::RevertReason;
+use blockstream::blockchain::transaction::EthereumTransactionProcessor;
+use blockstream::metrics::{metrics, EVENTS_FAILED, RECEIPTS_FETCHED};
+use common::{
+    block_json, hash, log_json, receipt_json, transaction_json, MockRpcServer, CONTRACT_ADDRESS,
+    SENDER_ADDRESS,
+};
+use serde_json::{json, Value};
+
+const REVERT_DATA: &str = "0x08c379a0\
+    0000000000000000000000000000000000000000000000000000000000000020\
+    000000000000000000000000000000000000000000000000000000000000000d\
+    48656c6c6f2c20576f726c642100000000000000000000000000000000000000";
+
+/// `Error(string)` whose string offset points past the end of the data.
+const MALFORMED_REVERT_DATA: &str = "0x08c379a0\
+    00000000000000000000000000000000000000000000000000000000000000ff";
+
+const CREATE_ARGS: &str = "000000000000000000000000000000000000000000000000000000000000002a\
+    fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffb";
+
+fn create_function() -> FunctionFilter {
+    FunctionFilter::new("createDummyStruct(uint256,int256)".to_string())
+        .expect("Invalid function signature")
+}
+
+fn create_input() -> String {
+    format!("{}{}", create_function().selector, CREATE_ARGS)
+}
+
+fn subscription() -> EventSubscription {
+    EventSubscription::new(Address::from_str(CONTRACT_ADDRESS).unwrap(), Vec::new())
+        .with_functions(vec![create_function()])
+}
+
+#[tokio::test]
+async fn test_failed_transaction_revert_reason() {
+    let server = MockRpcServer::start(|method, _| match method {
+        "eth_getTransactionReceipt" => Ok(receipt_json(&hash(1), 10, false, vec![])),
+        "eth_call" => Err(json!({
+            "code": 3,
+            "message": "execution reverted: Hello, World!",
+            "data": REVERT_DATA
+        })),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let processor = EthereumTransactionProcessor::new(provider, vec![subscription()])
+        .with_chain_id(31337)
+        .with_network("revert-test".to_string());
+    let block: Block = serde_json::from_value(block_json(
+        10,
+        vec![transaction_json(
+            &hash(1),
+            10,
+            CONTRACT_ADDRESS,
+            &create_input(),
+        )],
+    ))
+    .unwrap();
+
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    processor
+        .process_transactions(
+            &block,
+            |_| async {},
+            |_, function_call| {
+                let calls = calls.clone();
+                async move { calls.lock().unwrap().push(function_call) }
+            },
+            |failed_transaction| {
+                let failures = failures.clone();
+                async move { failures.lock().unwrap().push(failed_transaction) }
+            },
+        )
+        .await
+        .expect("Processing failed");
+
+    let failures = failures.lock().unwrap();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].block_number, 10);
+    assert_eq!(failures[0].chain_id, 31337);
+    assert_eq!(
+        failures[0].revert_reason,
+        RevertReason::Error("Hello, World!".to_string())
+    );
+    assert_eq!(server.requests("eth_call")[0][1], json!("0x9"));
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.len(), 1);
+    assert!(!calls[0].success);
+    assert_eq!(calls[0].chain_id, 31337);
+    assert_eq!(
+        metrics().counter(RECEIPTS_FETCHED, &[("network", "revert-test")]),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_malformed_revert_data_does_not_fail_the_block() {
+    let (event_filter, subscription) = stored_subscription();
+    let topic = event_filter.hash.to_string();
+    let server = MockRpcServer::start(move |method, params| match method {
+        "eth_getTransactionReceipt" if params[0] == json!(hash(1)) => {
+            Ok(receipt_json(&hash(1), 10, false, vec![]))
+        }
+        "eth_getTransactionReceipt" => Ok(receipt_json(
+            &hash(2),
+            10,
+            true,
+            vec![log_json(
+                &hash(2),
+                10,
+                vec![topic.clone()],
+                &format!("0x{:064x}", 42),
+            )],
+        )),
+        "eth_call" => Err(json!({
+            "code": 3,
+            "message": "execution reverted",
+            "data": MALFORMED_REVERT_DATA
+        })),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let processor = EthereumTransactionProcessor::new(provider, vec![subscription]);
+    let block: Block = serde_json::from_value(block_json(
+        10,
+        vec![
+            transaction_json(&hash(1), 10, CONTRACT_ADDRESS, "0x"),
+            transaction_json(&hash(2), 10, CONTRACT_ADDRESS, "0x"),
+        ],
+    ))
+    .unwrap();
+
+    let failures = Arc::new(Mutex::new(Vec::new()));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    processor
+        .process_transactions(
+            &block,
+            |event| {
+                let events = events.clone();
+                async move { events.lock().unwrap().push(event) }
+            },
+            |_, _| async {},
+            |failed_transaction| {
+                let failures = failures.clone();
+                async move { failures.lock().unwrap().push(failed_transaction) }
+            },
+        )
+        .await
+        .expect("Processing failed");
+
+    let failures = failures.lock().unwrap();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(
+        failures[0].revert_reason,
+        RevertReason::Unknown(MALFORMED_REVERT_DATA.parse().unwrap())
+    );
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].transaction_hash.to_string(), hash(2));
+    assert_eq!(events[0].parameters, vec![Parameter::Uint(42)]);
+}
+
+#[tokio::test]
+async fn test_function_call_decoding() {
+    let server = MockRpcServer::start(|method, _| match method {
+        "eth_getTransactionReceipt" => Ok(receipt_json(&hash(1), 10, true, vec![])),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let processor = EthereumTransactionProcessor::new(provider, vec![subscription()]);
+    let block: Block = serde_json::from_value(block_json(
+        10,
+        vec![transaction_json(
+            &hash(1),
+            10,
+            CONTRACT_ADDRESS,
+            &create_input(),
+        )],
+    ))
+    .unwrap();
+
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    processor
+        .process_transactions(
+            &block,
+            |_| async {},
+            |function_filter, function_call| {
+                let calls = calls.clone();
+                async move { calls.lock().unwrap().push((function_filter, function_call)) }
+            },
+            |_| async {},
+        )
+        .await
+        .expect("Processing failed");
+
+    let calls = calls.lock().unwrap();
+    assert_eq!(calls.len(), 1);
+    let (function_filter, function_call) = &calls[0];
+    assert_eq!(function_filter.function_name, "createDummyStruct");
+    assert!(function_call.success);
+    assert_eq!(function_call.block_number, 10);
+    assert_eq!(function_call.block_hash.to_string(), hash(10));
+    assert_eq!(
+        function_call.parameters,
+        vec![Parameter::Uint(42), Parameter::Int(-5)]
+    );
+    assert!(server.requests("eth_call").is_empty());
+}
+
+#[tokio::test]
+async fn test_function_call_decode_failure_is_counted() {
+    let server = MockRpcServer::start(|method, _| match method {
+        "eth_getTransactionReceipt" => Ok(receipt_json(&hash(1), 10, true, vec![])),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let processor = EthereumTransactionProcessor::new(provider, vec![subscription()])
+        .with_network("function-decode-test".to_string());
+    // Only the first of the two arguments is present.
+    let truncated = format!("{}{}", create_function().selector, &CREATE_ARGS[..64]);
+    let block: Block = serde_json::from_value(block_json(
+        10,
+        vec![transaction_json(&hash(1), 10, CONTRACT_ADDRESS, &truncated)],
+    ))
+    .unwrap();
+
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    processor
+        .process_transactions(
+            &block,
+            |_| async {},
+            |_, function_call| {
+                let calls = calls.clone();
+                async move { calls.lock().unwrap().push(function_call) }
+            },
+            |_| async {},
+        )
+        .await
+        .expect("Processing failed");
+
+    assert!(calls.lock().unwrap().is_empty());
+    let subscription = Address::from_str(CONTRACT_ADDRESS).unwrap().to_string();
+    assert_eq!(
+        metrics().counter(
+            EVENTS_FAILED,
+            &[
+                ("network", "function-decode-test"),
+                ("subscription", subscription.as_str()),
+                ("event", "createDummyStruct"),
+            ]
+        ),
+        1
+    );
+}
+
+#[tokio::test]
+async fn test_events_are_delivered_in_an_envelope() {
+    let event_filter =
+        EventFilter::new("Stored(uint256)".to_string()).expect("Invalid event signature");
+    let topic = event_filter.hash.to_string();
+    let server = MockRpcServer::start(move |method, _| match method {
+        "eth_getTransactionReceipt" => Ok(receipt_json(
+            &hash(1),
+            10,
+            true,
+            vec![log_json(
+                &hash(1),
+                10,
+                vec![topic.clone()],
+                &format!("0x{:064x}", 42),
+            )],
+        )),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let address = Address::from_str(CONTRACT_ADDRESS).unwrap();
+    let processor = EthereumTransactionProcessor::new(
+        provider,
+        vec![EventSubscription::new(address, vec![event_filter])],
+    )
+    .with_chain_id(31337);
+    let block: Block = serde_json::from_value(block_json(
+        10,
+        vec![transaction_json(&hash(1), 10, CONTRACT_ADDRESS, "0x")],
+    ))
+    .unwrap();
+    let block_hash = block.header.hash;
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    processor
+        .process_transaction_logs(&block, |event| {
+            let events = events.clone();
+            async move { events.lock().unwrap().push(event) }
+        })
+        .await
+        .expect("Processing failed");
+
+    let events = events.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    let event = &events[0];
+    assert_eq!(event.chain_id, 31337);
+    assert_eq!(event.block_number, 10);
+    assert_eq!(event.block_hash, block_hash);
+    assert_eq!(event.block_timestamp, 1_700_000_010);
+    assert_eq!(event.transaction_hash.to_string(), hash(1));
+    assert_eq!(event.transaction_index, Some(0));
+    assert_eq!(event.from, Address::from_str(SENDER_ADDRESS).unwrap());
+    assert_eq!(event.to, address);
+    assert_eq!(event.gas_used, 0x5208);
+    assert_eq!(event.address, address);
+    assert_eq!(event.log_index, Some(0));
+    assert!(!event.removed);
+    assert_eq!(event.event_name, "Stored");
+    assert_eq!(event.signature, "Stored(uint256)");
+    assert_eq!(event.parameters, vec![Parameter::Uint(42)]);
+}
+
+fn stored_subscription() -> (EventFilter, EventSubscription) {
+    let event_filter =
+        EventFilter::new("Stored(uint256)".to_string()).expect("Invalid event signature");
+    let subscription = EventSubscription::new(
+        Address::from_str(CONTRACT_ADDRESS).unwrap(),
+        vec![event_filter.clone()],
+    );
+    (event_filter, subscription)
+}
+
+/// Receipt of the transaction `hash(index)` in `block_number`, emitting
+/// `Stored(index)`.
+fn stored_receipt(event_filter: &EventFilter, block_number: u64, index: u64) -> Value {
+    receipt_json(
+        &hash(index),
+        block_number,
+        true,
+        vec![log_json(
+            &hash(index),
+            block_number,
+            vec![event_filter.hash.to_string()],
+            &format!("0x{:064x}", index),
+        )],
+    )
+}
+
+fn stored_block(number: u64, indexes: impl IntoIterator<Item = u64>) -> Block {
+    serde_json::from_value(block_json(
+        number,
+        indexes
+            .into_iter()
+            .map(|index| transaction_json(&hash(index), number, CONTRACT_ADDRESS, "0x"))
+            .collect(),
+    ))
+    .unwrap()
+}
+
+async fn stored_values(processor: &EthereumTransactionProcessor, block: Block) -> Vec<Parameter> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    processor
+        .process_transaction_logs(&block, |event| {
+            let events = events.clone();
+            async move { events.lock().unwrap().extend(event.parameters) }
+        })
+        .await
+        .expect("Processing failed");
+    Arc::try_unwrap(events).unwrap().into_inner().unwrap()
+}
+
+#[tokio::test]
+async fn test_receipts_are_fetched_per_block_when_supported() {
+    let (event_filter, subscription) = stored_subscription();
+    let server = MockRpcServer::start(move |method, _| match method {
+        "eth_getBlockReceipts" => Ok(json!([3, 9, 2, 1]
+            .into_iter()
+            .map(|index| stored_receipt(&event_filter, 10, index))
+            .collect::<Vec<_>>())),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let processor = EthereumTransactionProcessor::new(provider, vec![subscription]);
+
+    let values = stored_values(&processor, stored_block(10, [1, 2, 3])).await;
+
+    assert_eq!(
+        values,
+        vec![Parameter::Uint(1), Parameter::Uint(2), Parameter::Uint(3)]
+    );
+    assert_eq!(
+        server.requests("eth_getBlockReceipts"),
+        vec![json!([{ "blockHash": hash(10) }])]
+    );
+    assert!(server.requests("eth_getTransactionReceipt").is_empty());
+}
+
+#[tokio::test]
+async fn test_block_receipts_are_retried_on_transient_errors() {
+    let (event_filter, subscription) = stored_subscription();
+    let failed = AtomicBool::new(false);
+    let server = MockRpcServer::start(move |method, _| match method {
+        "eth_getBlockReceipts" if !failed.swap(true, Ordering::SeqCst) => {
+            Err(json!({ "code": -32000, "message": "header not found" }))
+        }
+        "eth_getBlockReceipts" => Ok(json!([stored_receipt(&event_filter, 10, 1)])),
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let processor = EthereumTransactionProcessor::new(provider, vec![subscription])
+        .with_retry_policy(
+            RetryPolicy::default().with_backoff(Duration::from_millis(1), Duration::from_millis(1)),
+        );
+
+    let values = stored_values(&processor, stored_block(10, [1])).await;
+
+    assert_eq!(values, vec![Parameter::Uint(1)]);
+    assert_eq!(server.requests("eth_getBlockReceipts").len(), 2);
+    assert!(server.requests("eth_getTransactionReceipt").is_empty());
+}
+
+#[tokio::test]
+async fn test_receipts_fall_back_to_concurrent_fetches_in_order() {
+    let (event_filter, subscription) = stored_subscription();
+    let server = MockRpcServer::start(move |method, params| match method {
+        "eth_getTransactionReceipt" => {
+            let index = (1..=6)
+                .find(|index| params[0] == json!(hash(*index)))
+                .unwrap();
+            Ok(stored_receipt(&event_filter, 10 + index / 4, index))
+        }
+        _ => Err(json!({ "code": -32601, "message": "method not found" })),
+    })
+    .await;
+    let provider = Arc::new(
+        ProviderBuilder::new()
+            .on_builtin(&server.url)
+            .await
+            .unwrap(),
+    );
+    let processor =
+        EthereumTransactionProcessor::new(provider, vec![subscription]).with_receipt_concurrency(2);
+
+    assert_eq!(
+        stored_values(&processor, stored_block(10, [1, 2, 3])).await,
+        vec![Parameter::Uint(1), Parameter::Uint(2), Parameter::Uint(3)]
+    );
+    assert_eq!(
+        stored_values(&processor, stored_block(11, [4, 5, 6])).await,
+        vec![Parameter::Uint(4), Parameter::Uint(5), Parameter::Uint(6)]
+    );
+    assert_eq!(server.requests("eth_getBlockReceipts").len(), 1);
+    assert_eq!(server.requests("eth_getTransactionReceipt").len(), 6);
+}
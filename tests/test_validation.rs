@@ -0,0 +1,248 @@
+use blockstream::configuration::{load_effective_config, EffectiveConfig};
+use blockstream::validation::validate;
+
+#[test]
+fn test_validate_default_config() {
+    let effective_config = load_effective_config(None, &[]).expect("Loading failed");
+    assert!(validate(&effective_config).is_empty());
+}
+
+#[test]
+fn test_validate_reports_every_issue() {
+    let effective_config =
+        load_effective_config(Some("broken".to_string()), &[]).expect("Loading failed");
+    let issues: Vec<String> = validate(&effective_config)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+
+    assert_eq!(
+        issues,
+        vec![
+            "resources/application-broken.yml:4: networks.local.host: host is not used with the ipc protocol",
+            "resources/application-broken.yml:5: networks.local.port: port is not used with the ipc protocol",
+            "resources/application-broken.yml:6: networks.local.path: path to the IPC socket is required",
+            "resources/application-broken.yml:7: networks.local.confirmations: confirmations must be a non-negative integer, got -1",
+            "resources/application-broken.yml:8: networks.local.receipt_concurrency: receipt_concurrency must be at least 1",
+            "resources/application-broken.yml:9: networks.local.batch: batching is only used with the http and https protocols",
+            "resources/application-broken.yml:12: networks.local.rate_limit.requests_per_second: requests_per_second must be a positive number, got 0",
+            "resources/application-broken.yml:14: networks.local.retry.max_attempts: max_attempts must be at least 1",
+            "resources/application-broken.yml:15: networks.local.retry.on_exhaustion: unsupported on_exhaustion 'retry', expected one of halt, skip, park",
+            "resources/application-broken.yml:16: networks.local.poll_mode: unsupported poll_mode 'block', expected one of filter, head",
+            "resources/application-broken.yml:19: subscriptions.0.network: unknown network 'mainnet', expected one of local",
+            "resources/application-broken.yml:20: subscriptions.0.contract_address: invalid address '0xnotanaddress'",
+            "resources/application-broken.yml:22: subscriptions.0.events.0: unsupported type 'float' in 'DummyStructCreated(uint256,float)'",
+            "resources/application-broken.yml:24: subscriptions.1.events: subscription has no events or functions",
+            "resources/application-broken.yml:28: subscriptions.2.events.1: duplicate subscription to \
+             DummyStructCreated(uint256, uint256, int256, bool, address, string, bytes32) on \
+             0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512, already defined at resources/application-broken.yml:27",
+        ]
+    );
+}
+
+#[test]
+fn test_validate_overrides_and_port_conflicts() {
+    let mut effective_config = EffectiveConfig::default();
+    effective_config
+        .merge_file("resources/application.yml")
+        .expect("Loading failed");
+    effective_config
+        .merge_overrides(&[
+            "networks.local.protocol=udp".to_string(),
+            "networks.local.port=70000".to_string(),
+            "metrics.enabled=true".to_string(),
+            "health.enabled=true".to_string(),
+            "health.port=9090".to_string(),
+        ])
+        .expect("Merging failed");
+
+    let issues: Vec<String> = validate(&effective_config)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        issues,
+        vec![
+            "command line: networks.local.protocol: unsupported protocol 'udp', expected one of http, https, ws, wss, ipc",
+            "command line: networks.local.port: invalid port 70000",
+            "command line: health.port: port 9090 is already used by metrics.port",
+        ]
+    );
+}
+
+#[test]
+fn test_validate_compares_effective_ports_of_enabled_listeners() {
+    let issues = |overrides: &[&str]| -> Vec<String> {
+        let mut effective_config = EffectiveConfig::default();
+        effective_config
+            .merge_overrides(
+                &[
+                    "networks.local.url=http://localhost:8545".to_string(),
+                    "subscriptions=[]".to_string(),
+                ]
+                .into_iter()
+                .chain(overrides.iter().map(ToString::to_string))
+                .collect::<Vec<_>>(),
+            )
+            .expect("Merging failed");
+        validate(&effective_config)
+            .iter()
+            .map(ToString::to_string)
+            .collect()
+    };
+
+    // The health server on its default port clashes with metrics moved onto it.
+    assert_eq!(
+        issues(&[
+            "metrics.enabled=true",
+            "metrics.port=8080",
+            "health.enabled=true"
+        ]),
+        vec!["command line: health.enabled: port 8080 is already used by metrics.port"]
+    );
+    assert_eq!(
+        issues(&[
+            "metrics.enabled=true",
+            "health.enabled=true",
+            "health.port=9090"
+        ]),
+        vec!["command line: health.port: port 9090 is already used by metrics.port (default)"]
+    );
+    // A disabled listener does not bind its port.
+    assert!(issues(&["metrics.port=8080", "health.enabled=true"]).is_empty());
+    assert!(issues(&[
+        "metrics.enabled=true",
+        "health.enabled=false",
+        "health.port=9090"
+    ])
+    .is_empty());
+}
+
+#[test]
+fn test_validate_network_url() {
+    let mut effective_config = EffectiveConfig::default();
+    effective_config
+        .merge_file("resources/application.yml")
+        .expect("Loading failed");
+    effective_config
+        .merge_overrides(&[
+            "networks.local.url=ws://node.internal:8546".to_string(),
+            "networks.local.auth.headers.X-Api-Key=abc".to_string(),
+        ])
+        .expect("Merging failed");
+
+    let issues: Vec<String> = validate(&effective_config)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        issues,
+        vec![
+            "resources/application.yml:3: networks.local.protocol: protocol cannot be combined with url",
+            "resources/application.yml:4: networks.local.host: host cannot be combined with url",
+            "resources/application.yml:5: networks.local.port: port cannot be combined with url",
+            "command line: networks.local.auth.headers: custom headers are not supported with websockets",
+        ]
+    );
+}
+
+#[test]
+fn test_locations_in_flow_style_and_multi_line_values() {
+    let path = std::env::temp_dir().join("blockstream-test-locations.yml");
+    std::fs::write(
+        &path,
+        "\
+defaults: &defaults
+  protocol: ws
+  port: 8545
+networks:
+  local:
+    <<: *defaults
+    host: >
+      a folded value
+      with: a colon
+    path: \"a quoted
+      value\"
+    retry: {max_attempts: 0, on_exhaustion: halt}
+  backup: *defaults
+subscriptions:
+  - contract_address: '0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512'
+    events: [Created(uint256), Updated(uint256)]
+",
+    )
+    .unwrap();
+
+    let mut effective_config = EffectiveConfig::default();
+    effective_config
+        .merge_file(path.to_str().unwrap())
+        .expect("Loading failed");
+    std::fs::remove_file(&path).unwrap();
+
+    let filename = path.to_str().unwrap();
+    let location = |key: &str| effective_config.location(key);
+    for (key, line) in [
+        ("defaults.port", 3),
+        ("networks.local.host", 7),
+        ("networks.local.path", 10),
+        ("networks.local.retry.max_attempts", 12),
+        ("networks.backup.port", 13),
+        ("subscriptions.0.contract_address", 15),
+        ("subscriptions.0.events.1", 16),
+    ] {
+        assert_eq!(
+            location(key),
+            Some(format!("{}:{}", filename, line)),
+            "{}",
+            key
+        );
+    }
+}
+
+#[test]
+fn test_validate_accepts_abi_functions_and_errors() {
+    let path = std::env::temp_dir().join("blockstream-test-abi.yml");
+    std::fs::write(
+        &path,
+        "\
+networks:
+  local:
+    url: http://localhost:8545
+subscriptions:
+  - contract_address: '0xe7f1725E7734CE288F8367e1Bb143E90bb3F0512'
+    functions:
+      - name: transfer
+        type: function
+        inputs:
+          - {name: to, type: address}
+          - {name: amount, type: uint256}
+      - name: broken
+        inputs:
+          - {name: value, type: float}
+    errors:
+      - name: InsufficientBalance
+        type: error
+        inputs:
+          - {name: available, type: uint256}
+          - {name: required, type: uint256}
+",
+    )
+    .unwrap();
+
+    let mut effective_config = EffectiveConfig::default();
+    effective_config
+        .merge_file(path.to_str().unwrap())
+        .expect("Loading failed");
+    std::fs::remove_file(&path).unwrap();
+
+    let issues: Vec<String> = validate(&effective_config)
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    assert_eq!(
+        issues,
+        vec![format!(
+            "{}:12: subscriptions.0.functions.1: invalid ABI entry: Unsupported data type: float",
+            path.display()
+        )]
+    );
+}